@@ -0,0 +1,75 @@
+//! Capture batching: one message per callback against --accumulate-ms.
+//!
+//! Prints allocations per second of audio before timing, since that's what
+//! batching is meant to cut.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The crate is a binary, so pull the module in directly
+#[allow(dead_code, unused_imports)]
+#[path = "../src/mix.rs"]
+mod mix;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// 48kHz stereo i16 in 5ms callbacks
+const CALLBACK_FRAMES: usize = 240;
+const CALLBACKS_PER_SEC: usize = 200;
+
+/// One second of capture; returns the number of messages sent.
+fn capture_second(data: &[i16], accumulate_frames: usize) -> usize {
+    let mut acc = mix::Accumulator::new(accumulate_frames);
+    let mut sent = 0;
+    for _ in 0..CALLBACKS_PER_SEC {
+        if let Some(batch) = acc.push(|out| mix::downmix_into(data, 2, out)) {
+            black_box(batch);
+            sent += 1;
+        }
+    }
+    sent
+}
+
+fn accumulate(c: &mut Criterion) {
+    let data: Vec<i16> = (0..CALLBACK_FRAMES * 2)
+        .map(|i| ((i as f32 * 0.01).sin() * 32767.0) as i16)
+        .collect();
+    let settings = [("per callback", 0), ("25ms", 1200), ("50ms", 2400)];
+
+    for (name, frames) in settings {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let sent = capture_second(&data, frames);
+        let allocs = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("{name}: {sent} messages, {allocs} allocations per second of audio");
+    }
+
+    let mut group = c.benchmark_group("capture batching");
+    for (name, frames) in settings {
+        group.bench_function(name, |b| {
+            b.iter(|| capture_second(black_box(&data), frames))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, accumulate);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Mono capture: the direct-copy fast path against frame averaging.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The crate is a binary, so pull the module in directly
+#[allow(dead_code, unused_imports)]
+#[path = "../src/mix.rs"]
+mod mix;
+
+fn mono_downmix(c: &mut Criterion) {
+    // One 100ms callback at 48kHz
+    let f32_data: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.01).sin()).collect();
+    let i16_data: Vec<i16> = f32_data.iter().map(|&s| (s * 32767.0) as i16).collect();
+
+    let mut group = c.benchmark_group("mono downmix");
+    group.bench_function("f32 fast path", |b| {
+        b.iter(|| mix::downmix(black_box(&f32_data), 1))
+    });
+    group.bench_function("f32 frame averaging", |b| {
+        b.iter(|| mix::mix_frames(black_box(&f32_data), 1).collect::<Vec<_>>())
+    });
+    group.bench_function("i16 fast path", |b| {
+        b.iter(|| mix::downmix(black_box(&i16_data), 1))
+    });
+    group.bench_function("i16 frame averaging", |b| {
+        b.iter(|| mix::mix_frames(black_box(&i16_data), 1).collect::<Vec<_>>())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mono_downmix);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+//! i16 capture into the RMS window: the float path against the Q15 one of
+//! `--features fixed-point`, each with the callback's downmix and gain.
+
+use std::collections::VecDeque;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// The crate is a binary, so pull the modules in directly
+#[allow(dead_code, unused_imports)]
+#[path = "../src/mix.rs"]
+mod mix;
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/fixed.rs"]
+mod fixed;
+
+/// The sliding sum of `dsp::RingBuffer`, which can't be pulled in alone.
+struct FloatRing {
+    buf: VecDeque<f32>,
+    capacity: usize,
+    sum_squares: f64,
+}
+
+impl FloatRing {
+    fn extend(&mut self, samples: &[f32]) {
+        for &s in samples {
+            if self.buf.len() >= self.capacity {
+                if let Some(old) = self.buf.pop_front() {
+                    self.sum_squares -= (old as f64) * (old as f64);
+                }
+            }
+            self.sum_squares += (s as f64) * (s as f64);
+            self.buf.push_back(s);
+        }
+        if self.sum_squares < 0.0 {
+            self.sum_squares = 0.0;
+        }
+    }
+
+    fn rms(&self) -> f32 {
+        (self.sum_squares / self.buf.len().max(1) as f64).sqrt() as f32
+    }
+}
+
+fn i16_to_rms(c: &mut Criterion) {
+    // One 100ms stereo callback at 48kHz, into a 50ms window
+    let data: Vec<i16> = (0..9600)
+        .map(|i| ((i as f32 * 0.01).sin() * 16000.0) as i16)
+        .collect();
+    let window = 2400;
+    let gain = 1.5;
+
+    let mut group = c.benchmark_group("i16 to rms");
+    group.bench_function("float", |b| {
+        let mut ring = FloatRing {
+            buf: VecDeque::with_capacity(window),
+            capacity: window,
+            sum_squares: 0.0,
+        };
+        let mut mono = Vec::with_capacity(data.len() / 2);
+        b.iter(|| {
+            mono.clear();
+            mix::downmix_into(black_box(&data), 2, &mut mono);
+            mix::apply_gain(&mut mono, gain);
+            ring.extend(&mono);
+            ring.rms()
+        })
+    });
+    group.bench_function("fixed-point", |b| {
+        let mut ring = fixed::FixedRing::new(window);
+        let mut mono = Vec::with_capacity(data.len() / 2);
+        let gain = fixed::gain_q16(gain);
+        b.iter(|| {
+            mono.clear();
+            fixed::downmix_i16_into(black_box(&data), 2, &mut mono);
+            fixed::apply_gain_q15(&mut mono, gain);
+            ring.extend_q15(&mono);
+            ring.rms()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, i16_to_rms);
+criterion_main!(benches);
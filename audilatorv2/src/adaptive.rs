@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// Histogram resolution.
+const BIN_DB: f32 = 0.5;
+/// Histogram range; readings outside are clamped into the end bins.
+const FLOOR_DBFS: f32 = -80.0;
+const CEILING_DBFS: f32 = 0.0;
+/// Narrowest quiet-to-loud span, matching calibration's minimum dead zone.
+const MIN_SPAN_DB: f32 = 4.0;
+/// Readings needed before the first thresholds are trusted.
+const MIN_FILL: f32 = 0.25;
+
+/// Settings for thresholds that follow the program's recent dynamics.
+#[derive(Clone, Debug)]
+pub struct AdaptiveConfig {
+    /// Seconds of loudness history in the histogram
+    pub window_sec: f32,
+    /// Seconds between threshold updates
+    pub update_sec: f32,
+    /// Percentile (0-100) of recent loudness taken as the quiet threshold
+    pub quiet_percentile: f32,
+    /// Percentile (0-100) taken as the loud threshold
+    pub loud_percentile: f32,
+}
+
+/// Settings for thresholds set around the rolling median of loudness.
+#[derive(Clone, Debug)]
+pub struct MedianConfig {
+    /// Seconds of loudness history the median is taken over
+    pub window_sec: f32,
+    /// dB over the median before content is ducked
+    pub above_db: f32,
+    /// dB under the median before content is boosted
+    pub below_db: f32,
+}
+
+/// Quiet and loud boundaries in dBFS: below quiet boosts, above loud cuts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Thresholds {
+    pub quiet_dbfs: f32,
+    pub loud_dbfs: f32,
+}
+
+impl Thresholds {
+    /// The target in the middle and the dead zone reaching each threshold.
+    pub fn target_and_zone(&self) -> (f32, f32) {
+        let mid = (self.quiet_dbfs + self.loud_dbfs) / 2.0;
+        (mid, (self.loud_dbfs - self.quiet_dbfs) / 2.0)
+    }
+}
+
+/// Bounded rolling histogram of envelope levels for percentile lookups.
+pub struct LevelHistogram {
+    history: VecDeque<usize>,
+    counts: Vec<u32>,
+    capacity: usize,
+}
+
+impl LevelHistogram {
+    /// Keeps the most recent `capacity` readings.
+    pub fn new(capacity: usize) -> Self {
+        let bins = ((CEILING_DBFS - FLOOR_DBFS) / BIN_DB) as usize + 1;
+        Self {
+            history: VecDeque::new(),
+            counts: vec![0; bins],
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn bin(level_dbfs: f32) -> usize {
+        ((level_dbfs.clamp(FLOOR_DBFS, CEILING_DBFS) - FLOOR_DBFS) / BIN_DB).round() as usize
+    }
+
+    pub fn push(&mut self, level_dbfs: f32) {
+        let bin = Self::bin(level_dbfs);
+        if self.history.len() >= self.capacity {
+            if let Some(old) = self.history.pop_front() {
+                self.counts[old] -= 1;
+            }
+        }
+        self.history.push_back(bin);
+        self.counts[bin] += 1;
+    }
+
+    /// Fraction of the capacity filled so far.
+    pub fn fill(&self) -> f32 {
+        self.history.len() as f32 / self.capacity as f32
+    }
+
+    /// Level at `pct` (0-100) of recent readings, or None if there are none.
+    pub fn percentile(&self, pct: f32) -> Option<f32> {
+        let last = self.history.len().checked_sub(1)?;
+        let rank = ((pct / 100.0).clamp(0.0, 1.0) * last as f32) as u32;
+        let mut seen = 0;
+        for (bin, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen > rank {
+                return Some(FLOOR_DBFS + bin as f32 * BIN_DB);
+            }
+        }
+        Some(CEILING_DBFS)
+    }
+}
+
+/// Rolling histogram of envelope loudness, re-reading its percentiles as
+/// thresholds every `update_sec`.
+pub struct AdaptiveThresholds {
+    config: AdaptiveConfig,
+    levels: LevelHistogram,
+    update_every: usize,
+    since_update: usize,
+}
+
+impl AdaptiveThresholds {
+    pub fn new(config: AdaptiveConfig, update_rate_hz: f32) -> Self {
+        Self {
+            levels: LevelHistogram::new((config.window_sec * update_rate_hz) as usize),
+            update_every: ((config.update_sec * update_rate_hz) as usize).max(1),
+            config,
+            since_update: 0,
+        }
+    }
+
+    /// Record a non-silent envelope reading. Returns new thresholds when an
+    /// update is due and enough history has built up.
+    pub fn update(&mut self, level_dbfs: f32) -> Option<Thresholds> {
+        self.levels.push(level_dbfs);
+
+        self.since_update += 1;
+        if self.since_update < self.update_every || self.levels.fill() < MIN_FILL {
+            return None;
+        }
+        self.since_update = 0;
+
+        let quiet = self.levels.percentile(self.config.quiet_percentile)?;
+        let loud = self.levels.percentile(self.config.loud_percentile)?;
+        let mid = (quiet + loud) / 2.0;
+        let half = ((loud - quiet) / 2.0).max(MIN_SPAN_DB / 2.0);
+        Some(Thresholds {
+            quiet_dbfs: mid - half,
+            loud_dbfs: mid + half,
+        })
+    }
+}
+
+/// Thresholds a fixed distance either side of the median of recent
+/// loudness. Unlike a mean, the median barely moves for a few loud
+/// outliers, so short spikes don't drag the target up after them.
+pub struct MedianThresholds {
+    config: MedianConfig,
+    levels: LevelHistogram,
+}
+
+impl MedianThresholds {
+    pub fn new(config: MedianConfig, update_rate_hz: f32) -> Self {
+        Self {
+            levels: LevelHistogram::new((config.window_sec * update_rate_hz) as usize),
+            config,
+        }
+    }
+
+    /// Record a non-silent envelope reading. Returns thresholds once enough
+    /// history has built up.
+    pub fn update(&mut self, level_dbfs: f32) -> Option<Thresholds> {
+        self.levels.push(level_dbfs);
+        if self.levels.fill() < MIN_FILL {
+            return None;
+        }
+        let median = self.levels.percentile(50.0)?;
+        Some(Thresholds {
+            quiet_dbfs: median - self.config.below_db,
+            loud_dbfs: median + self.config.above_db,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> AdaptiveThresholds {
+        AdaptiveThresholds::new(
+            AdaptiveConfig {
+                window_sec: 10.0,
+                update_sec: 2.0,
+                quiet_percentile: 10.0,
+                loud_percentile: 90.0,
+            },
+            20.0,
+        )
+    }
+
+    /// Levels spread evenly over [low, high], repeating.
+    fn spread(low: f32, high: f32, n: usize) -> impl Iterator<Item = f32> {
+        (0..n).map(move |i| low + (high - low) * (i % 21) as f32 / 20.0)
+    }
+
+    #[test]
+    fn thresholds_track_a_shifting_distribution() {
+        let mut t = tracker();
+        let mut latest = None;
+        for level in spread(-40.0, -20.0, 200) {
+            latest = t.update(level).or(latest);
+        }
+        let first = latest.unwrap();
+        assert!((first.quiet_dbfs + 38.0).abs() <= 1.5, "{first:?}");
+        assert!((first.loud_dbfs + 22.0).abs() <= 1.5, "{first:?}");
+
+        // Program gets louder and more compressed
+        for level in spread(-20.0, -14.0, 240) {
+            latest = t.update(level).or(latest);
+        }
+        let second = latest.unwrap();
+        assert!(second.quiet_dbfs > first.quiet_dbfs + 15.0, "{second:?}");
+        assert!((second.loud_dbfs + 14.5).abs() < 1.0, "{second:?}");
+    }
+
+    #[test]
+    fn updates_only_every_interval() {
+        let mut t = tracker();
+        let updates = (0..200).filter_map(|_| t.update(-30.0)).count();
+        // First once a quarter of the 10s window is full, then every 2s
+        assert_eq!(updates, 4);
+    }
+
+    #[test]
+    fn median_ignores_a_skewed_tail() {
+        let mut t = MedianThresholds::new(
+            MedianConfig {
+                window_sec: 10.0,
+                above_db: 3.0,
+                below_db: 6.0,
+            },
+            20.0,
+        );
+        // Dialogue around -30 with one reading in five a -5 spike: the mean
+        // would sit at -25, the median stays with the dialogue
+        let mut latest = None;
+        for i in 0..400 {
+            let level = if i % 5 == 0 {
+                -5.0
+            } else {
+                -30.0 + (i % 3) as f32
+            };
+            latest = t.update(level).or(latest);
+        }
+        let th = latest.unwrap();
+        assert_eq!(
+            th,
+            Thresholds {
+                quiet_dbfs: -35.0,
+                loud_dbfs: -26.0
+            }
+        );
+        // Asymmetric thresholds shift the target
+        assert_eq!(th.target_and_zone(), (-30.5, 4.5));
+    }
+
+    #[test]
+    fn median_waits_for_some_history() {
+        let config = MedianConfig {
+            window_sec: 10.0,
+            above_db: 3.0,
+            below_db: 3.0,
+        };
+        let mut t = MedianThresholds::new(config, 20.0);
+        assert!((0..49).all(|_| t.update(-30.0).is_none()));
+        assert!(t.update(-30.0).is_some());
+    }
+
+    #[test]
+    fn narrow_programs_keep_a_minimum_span() {
+        let mut t = tracker();
+        let th = (0..200).filter_map(|_| t.update(-30.0)).last().unwrap();
+        assert!((th.loud_dbfs - th.quiet_dbfs - MIN_SPAN_DB).abs() < 1e-4);
+        assert_eq!(th.target_and_zone(), (-30.0, MIN_SPAN_DB / 2.0));
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, FromSample, Sample, SampleFormat, SizedSample, StreamConfig, StreamError};
+
+use crate::mix::{apply_gain, downmix_into, interleaved_into, Accumulator};
+use tokio::sync::mpsc;
+
+pub fn list_devices() -> Result<()> {
+    let host = cpal::default_host();
+    println!("Available input devices:");
+    for device in host.input_devices()? {
+        if let Ok(name) = device.name() {
+            print!("  {name}");
+            if let Ok(cfg) = device.default_input_config() {
+                print!(
+                    " ({}ch, {}Hz, {:?})",
+                    cfg.channels(),
+                    cfg.sample_rate().0,
+                    cfg.sample_format()
+                );
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
+
+pub fn find_device(name_filter: Option<&str>) -> Result<Device> {
+    let host = cpal::default_host();
+    match name_filter {
+        Some(filter) => {
+            let filter_lower = filter.to_lowercase();
+            host.input_devices()?
+                .find(|d| {
+                    d.name()
+                        .map(|n| n.to_lowercase().contains(&filter_lower))
+                        .unwrap_or(false)
+                })
+                .ok_or_else(|| anyhow!("No input device matching '{filter}'"))
+        }
+        None => host
+            .default_input_device()
+            .ok_or_else(|| anyhow!("No default input device")),
+    }
+}
+
+pub fn find_output_device(name_filter: Option<&str>) -> Result<Device> {
+    let host = cpal::default_host();
+    match name_filter {
+        Some(filter) => {
+            let filter_lower = filter.to_lowercase();
+            host.output_devices()?
+                .find(|d| {
+                    d.name()
+                        .map(|n| n.to_lowercase().contains(&filter_lower))
+                        .unwrap_or(false)
+                })
+                .ok_or_else(|| anyhow!("No output device matching '{filter}'"))
+        }
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No default output device")),
+    }
+}
+
+/// What the capture callback hands to the analyzer.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Capture {
+    /// All channels averaged to one
+    Mono,
+    /// Sanitized frames, channels kept interleaved
+    Interleaved,
+}
+
+/// One message from the capture callback.
+pub enum Batch {
+    Float(Vec<f32>),
+    /// Mono Q15 from a 16-bit device, for the integer RMS window (see `fixed`)
+    #[cfg(feature = "fixed-point")]
+    Q15(Vec<i16>),
+}
+
+impl Batch {
+    /// The samples as float, for readers without an integer path.
+    pub fn into_float(self) -> Vec<f32> {
+        match self {
+            Batch::Float(samples) => samples,
+            #[cfg(feature = "fixed-point")]
+            Batch::Q15(samples) => crate::fixed::to_f32(&samples),
+        }
+    }
+}
+
+/// Open the device's input stream. Returns the stream and its channel count.
+/// Callbacks are batched until `accumulate_frames` frames are ready (0 sends
+/// each callback on its own). Samples are sanitized, then scaled and clipped
+/// by the linear `input_gain` (see `preprocess` for why not in the chain).
+/// Stream errors go to `on_error`. With `--features fixed-point`, a 16-bit
+/// device captured mono sends `Batch::Q15`: downmix and gain in integers.
+pub fn build_input_stream(
+    device: &Device,
+    sample_rate: u32,
+    capture: Capture,
+    accumulate_frames: usize,
+    input_gain: f32,
+    tx: mpsc::UnboundedSender<Batch>,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<(cpal::Stream, usize)> {
+    let supported = device.default_input_config()?;
+    let config = StreamConfig {
+        channels: supported.channels(),
+        sample_rate: cpal::SampleRate(sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let channels = config.channels as usize;
+    let accumulate_samples = match capture {
+        Capture::Mono => accumulate_frames,
+        Capture::Interleaved => accumulate_frames * channels,
+    };
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream(
+            device,
+            &config,
+            to_float::<f32>(capture, channels, input_gain),
+            accumulate_samples,
+            Batch::Float,
+            tx,
+            on_error,
+        ),
+        #[cfg(feature = "fixed-point")]
+        SampleFormat::I16 if capture == Capture::Mono => {
+            use crate::fixed::{apply_gain_q15, downmix_i16_into, gain_q16};
+            let gain = gain_q16(input_gain);
+            build_stream(
+                device,
+                &config,
+                move |data: &[i16], out: &mut Vec<i16>| {
+                    let start = out.len();
+                    downmix_i16_into(data, channels, out);
+                    apply_gain_q15(&mut out[start..], gain);
+                },
+                accumulate_samples,
+                Batch::Q15,
+                tx,
+                on_error,
+            )
+        }
+        SampleFormat::I16 => build_stream(
+            device,
+            &config,
+            to_float::<i16>(capture, channels, input_gain),
+            accumulate_samples,
+            Batch::Float,
+            tx,
+            on_error,
+        ),
+        SampleFormat::U16 => build_stream(
+            device,
+            &config,
+            to_float::<u16>(capture, channels, input_gain),
+            accumulate_samples,
+            Batch::Float,
+            tx,
+            on_error,
+        ),
+        fmt => Err(anyhow!("Unsupported sample format: {fmt:?}")),
+    }?;
+    Ok((stream, channels))
+}
+
+/// The error handler for streams nothing else watches.
+pub fn log_stream_error(err: StreamError) {
+    tracing::error!("Audio error: {err}");
+}
+
+/// Converts one callback's samples for the analyzer, appending to the batch.
+type Convert<T> = fn(&[T], usize, &mut Vec<f32>);
+
+/// The float conversion for `capture`, then the input gain.
+fn to_float<T>(
+    capture: Capture,
+    channels: usize,
+    input_gain: f32,
+) -> impl FnMut(&[T], &mut Vec<f32>) + Send + 'static
+where
+    T: Sample + 'static,
+    f32: FromSample<T>,
+{
+    let convert: Convert<T> = match capture {
+        Capture::Mono => downmix_into,
+        Capture::Interleaved => interleaved_into,
+    };
+    move |data, out| {
+        let start = out.len();
+        convert(data, channels, out);
+        apply_gain(&mut out[start..], input_gain);
+    }
+}
+
+/// Batches what `convert` appends per callback, sending each as `batch`.
+fn build_stream<T, S>(
+    device: &Device,
+    config: &StreamConfig,
+    mut convert: impl FnMut(&[T], &mut Vec<S>) + Send + 'static,
+    accumulate_samples: usize,
+    batch: fn(Vec<S>) -> Batch,
+    tx: mpsc::UnboundedSender<Batch>,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + 'static,
+    S: Send + 'static,
+{
+    let mut pending = Accumulator::new(accumulate_samples);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            if let Some(samples) = pending.push(|out| convert(data, out)) {
+                let _ = tx.send(batch(samples));
+            }
+        },
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dsp::tests::test_config;
+    use crate::dsp::Compressor;
+    use crate::mix::downmix;
+    use crate::mix::tests::any_sample;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn fixed_point_downmix_matches_float(
+            data in prop::collection::vec(any::<i16>(), 0..2048),
+            channels in 0usize..9,
+        ) {
+            let mut q15 = Vec::new();
+            crate::fixed::downmix_i16_into(&data, channels, &mut q15);
+            let fixed = crate::fixed::to_f32(&q15);
+            let float = downmix(&data, channels);
+            prop_assert_eq!(fixed.len(), float.len());
+            // Integer averaging rounds: at most one LSB apart
+            prop_assert!(fixed.iter().zip(&float).all(|(a, b)| (a - b).abs() <= 1.0 / 32768.0));
+        }
+    }
+
+    proptest! {
+        // Fewer cases: each one pushes thousands of samples through the pipeline
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn compressor_outputs_stay_in_range(
+            buffers in prop::collection::vec(
+                (prop::collection::vec(any_sample(), 0..512), 0usize..5),
+                1..32,
+            ),
+        ) {
+            let mut config = test_config();
+            config.rms_window_ms = 10.0;
+            let (vol_min, vol_max) = (config.vol_min, config.vol_max);
+            let mut comp = Compressor::new(config, 0.5);
+            for (raw, channels) in buffers {
+                if let Some(r) = comp.process(&downmix(&raw, channels)) {
+                    prop_assert!(r.envelope_dbfs.is_finite());
+                    prop_assert!((-80.0..=0.01).contains(&r.envelope_dbfs));
+                    prop_assert!(r.delta_db.is_finite());
+                    prop_assert!((vol_min..=vol_max).contains(&r.volume));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+
+use crate::dsp::{Compressor, CompressorConfig, ProcessResult};
+use crate::mix::deinterleave;
+use crate::output::SendGate;
+
+/// Volume for one output channel of the controller's device.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ChannelVolume {
+    pub channel: usize,
+    pub volume: f32,
+}
+
+/// Body of `POST /volume/channels`.
+#[derive(Serialize, Debug)]
+pub struct ChannelVolumeRequest {
+    pub channels: Vec<ChannelVolume>,
+}
+
+/// The input channel carrying dialogue (e.g. the centre of 5.1).
+#[derive(Clone, Copy, Debug)]
+pub struct Dialogue {
+    pub channel: usize,
+    /// Its target above the others', so dialogue sits over the effects
+    pub boost_db: f32,
+}
+
+/// An independent compressor for each captured channel, steering the
+/// output channel it maps to (e.g. centre in -> centre out). A dialogue
+/// channel is levelled on speech alone: it only moves while speech is
+/// detected in it, towards a target `boost_db` louder, so quiet dialogue is
+/// brought up and music or effects in that channel leave it be.
+pub struct ChannelCompressors {
+    channels: Vec<ChannelState>,
+    input_channels: usize,
+}
+
+struct ChannelState {
+    output: usize,
+    compressor: Compressor,
+    gate: SendGate,
+    latest: Option<f32>,
+}
+
+impl ChannelCompressors {
+    /// `map[i]` is the output channel driven by input channel `i`.
+    /// Input channels beyond the map are ignored.
+    pub fn new(
+        config: CompressorConfig,
+        map: &[usize],
+        input_channels: usize,
+        initial_volume: f32,
+        steps: Option<u32>,
+        deadband: f32,
+        dialogue: Option<Dialogue>,
+    ) -> Self {
+        let channels = map
+            .iter()
+            .take(input_channels)
+            .enumerate()
+            .map(|(input, &output)| {
+                let mut config = config.clone();
+                if let Some(d) = dialogue.filter(|d| d.channel == input) {
+                    config.target_dbfs += d.boost_db;
+                    config.speech_only = true;
+                    config.hold_during_speech = false;
+                }
+                ChannelState {
+                    output,
+                    compressor: Compressor::new(config, initial_volume),
+                    gate: SendGate::new(steps, deadband),
+                    latest: None,
+                }
+            })
+            .collect();
+        Self {
+            channels,
+            input_channels,
+        }
+    }
+
+    /// Feed interleaved frames. Returns each channel's result for this chunk
+    /// (None where that channel hasn't completed an RMS window yet).
+    pub fn process(&mut self, interleaved: &[f32]) -> Vec<Option<ProcessResult>> {
+        let split = deinterleave(interleaved, self.input_channels);
+        self.channels
+            .iter_mut()
+            .zip(split)
+            .map(|(ch, samples)| {
+                let result = ch.compressor.process(&samples);
+                if let Some(r) = &result {
+                    ch.latest = Some(r.volume);
+                }
+                result
+            })
+            .collect()
+    }
+
+    /// Channels whose volume would change on the device, or None if none would.
+    pub fn pending_request(&self) -> Option<ChannelVolumeRequest> {
+        let channels: Vec<ChannelVolume> = self
+            .channels
+            .iter()
+            .filter_map(|ch| {
+                let volume = ch.gate.pending(ch.latest?)?;
+                Some(ChannelVolume {
+                    channel: ch.output,
+                    volume,
+                })
+            })
+            .collect();
+        (!channels.is_empty()).then_some(ChannelVolumeRequest { channels })
+    }
+
+    /// Record a request the controller accepted.
+    pub fn mark_sent(&mut self, req: &ChannelVolumeRequest) {
+        for sent in &req.channels {
+            if let Some(ch) = self.channels.iter_mut().find(|c| c.output == sent.channel) {
+                ch.gate.mark_sent(sent.volume);
+            }
+        }
+    }
+
+    /// Latest volume per output channel, for display.
+    pub fn volumes(&self) -> impl Iterator<Item = (usize, Option<f32>)> + '_ {
+        self.channels.iter().map(|c| (c.output, c.latest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::tests::test_config;
+    use crate::output::DEFAULT_SEND_DEADBAND;
+    use crate::speech::tests::{music_like, speech_like};
+
+    /// `secs` of stereo frames at constant per-channel amplitudes.
+    fn stereo(left: f32, right: f32, secs: f32) -> Vec<f32> {
+        let frames = (8000.0 * secs) as usize;
+        (0..frames).flat_map(|_| [left, right]).collect()
+    }
+
+    /// Interleave equal-length channels.
+    fn frames(channels: &[Vec<f32>]) -> Vec<f32> {
+        (0..channels[0].len())
+            .flat_map(|i| channels.iter().map(move |ch| ch[i]))
+            .collect()
+    }
+
+    fn run(comps: &mut ChannelCompressors, data: &[f32]) {
+        // 50ms of frames at a time
+        for chunk in data.chunks(400 * comps.input_channels) {
+            comps.process(chunk);
+        }
+    }
+
+    #[test]
+    fn channels_are_levelled_independently() {
+        let mut comps = ChannelCompressors::new(
+            test_config(),
+            &[0, 1],
+            2,
+            0.5,
+            None,
+            DEFAULT_SEND_DEADBAND,
+            None,
+        );
+        // Left loud (-6 dBFS), right quiet dialogue (-45 dBFS); target is -25
+        run(&mut comps, &stereo(0.5, 0.0056, 3.0));
+
+        let vols: Vec<f32> = comps.volumes().map(|(_, v)| v.unwrap()).collect();
+        assert!(vols[0] < 0.5, "loud channel should come down: {}", vols[0]);
+        assert!(vols[1] > 0.5, "quiet channel should come up: {}", vols[1]);
+    }
+
+    #[test]
+    fn payload_uses_channel_map_and_skips_unchanged() {
+        // Capture L/R, drive outputs 2 and 0
+        let mut comps = ChannelCompressors::new(
+            test_config(),
+            &[2, 0],
+            2,
+            0.5,
+            None,
+            DEFAULT_SEND_DEADBAND,
+            None,
+        );
+        run(&mut comps, &stereo(0.5, 0.0056, 3.0));
+
+        let req = comps.pending_request().unwrap();
+        let outputs: Vec<usize> = req.channels.iter().map(|c| c.channel).collect();
+        assert_eq!(outputs, vec![2, 0]);
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["channels"][0]["channel"], 2);
+        assert!(json["channels"][0]["volume"].as_f64().unwrap() < 0.5);
+
+        comps.mark_sent(&req);
+        assert!(comps.pending_request().is_none());
+    }
+
+    #[test]
+    fn extra_input_channels_are_ignored() {
+        let mut comps = ChannelCompressors::new(
+            test_config(),
+            &[0],
+            2,
+            0.5,
+            None,
+            DEFAULT_SEND_DEADBAND,
+            None,
+        );
+        let results = comps.process(&stereo(0.5, 0.5, 0.1));
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn dialogue_channel_moves_only_for_speech() {
+        let centre = |dialogue, content: Vec<f32>| {
+            let mut comps = ChannelCompressors::new(
+                test_config(),
+                &[0, 1, 2],
+                3,
+                0.5,
+                None,
+                DEFAULT_SEND_DEADBAND,
+                dialogue,
+            );
+            // Music left and right, `content` in the centre
+            let music = music_like(4.0, 0.1);
+            run(&mut comps, &frames(&[music.clone(), music, content]));
+            comps.volumes().last().unwrap().1.unwrap()
+        };
+        let dialogue = Some(Dialogue {
+            channel: 2,
+            boost_db: 3.0,
+        });
+
+        // Quiet dialogue in the centre is brought up
+        assert!(centre(dialogue, speech_like(4.0, 0.01)) > 0.5);
+        // Quiet effects there aren't, though a plain channel would be
+        assert_eq!(centre(dialogue, music_like(4.0, 0.005)), 0.5);
+        assert!(centre(None, music_like(4.0, 0.005)) > 0.5);
+    }
+}
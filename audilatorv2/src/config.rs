@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dsp::CompressorConfig;
+use crate::params::{self, ParamConfig};
+use crate::preprocess::ProcessorConfig;
+use crate::regions::{self, Region};
+use crate::scenes::{self, Scene};
+use crate::schedule::RecalibrateConfig;
+use crate::units::Units;
+
+/// Contents of the `--config` JSON file. Everything is optional.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub zones: Vec<ZoneConfig>,
+    /// Loudness regions, lowest first, replacing the dead zone model
+    pub regions: Vec<Region>,
+    /// Kinds of content recognized by their signature, each with a target
+    pub scenes: Vec<Scene>,
+    /// Periodically re-derive target and dead zone from recent content
+    pub recalibrate: Option<RecalibrateConfig>,
+    /// Settings for whichever zone captures from a device, keyed by device
+    /// name (substring match), e.g. a console's HDMI capture vs the cable box
+    pub devices: BTreeMap<String, ControlOverrides>,
+    /// Filters applied, in order, to every capture before analysis
+    pub preprocess: Vec<ProcessorConfig>,
+    /// Controls sent with the volume, each following its own feature
+    pub params: Vec<ParamConfig>,
+}
+
+/// An independently-levelled room: its own mic and the controllers it drives.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneConfig {
+    pub name: String,
+    /// Input device name (substring match); default input if omitted
+    #[serde(default)]
+    pub device: Option<String>,
+    /// Controller addresses as "host" or "host:port"
+    pub endpoints: Vec<String>,
+    /// Settings that differ from the command line for this zone
+    #[serde(default)]
+    pub control: ControlOverrides,
+}
+
+/// Per-zone replacements for command-line control settings. Zones sharing
+/// a device hear the same audio, so two of them can A/B parameter sets live
+/// on two controllers.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlOverrides {
+    /// In --units
+    pub target: Option<f32>,
+    pub dead_zone: Option<f32>,
+    pub hysteresis: Option<f32>,
+    pub attack: Option<f32>,
+    pub release: Option<f32>,
+    pub max_slew: Option<f32>,
+    pub duck_ratio: Option<f32>,
+}
+
+impl ControlOverrides {
+    pub fn apply(&self, config: &mut CompressorConfig, units: Units) {
+        let fields = [
+            (self.dead_zone, &mut config.dead_zone_db),
+            (self.hysteresis, &mut config.hysteresis_db),
+            (self.attack, &mut config.attack_ms),
+            (self.release, &mut config.release_ms),
+            (self.max_slew, &mut config.max_slew_db_per_sec),
+            (self.duck_ratio, &mut config.duck_ratio),
+        ];
+        for (value, field) in fields {
+            if let Some(v) = value {
+                *field = v;
+            }
+        }
+        if let Some(t) = self.target {
+            config.target_dbfs = units.dbfs(t);
+        }
+    }
+}
+
+impl FileConfig {
+    /// The `devices` entry for `device_name`: the longest key it contains,
+    /// so "USB Audio 2" can differ from "USB".
+    pub fn device_profile(&self, device_name: &str) -> Option<(&str, &ControlOverrides)> {
+        self.devices
+            .iter()
+            .filter(|(key, _)| device_name.contains(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(key, control)| (key.as_str(), control))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read config {}", path.display()))?;
+        let config: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, zone) in self.zones.iter().enumerate() {
+            if zone.endpoints.is_empty() {
+                bail!("Zone '{}' has no endpoints", zone.name);
+            }
+            if self.zones[..i].iter().any(|z| z.name == zone.name) {
+                bail!("Duplicate zone '{}'", zone.name);
+            }
+        }
+        regions::validate(&self.regions)?;
+        params::validate(&self.params)?;
+        scenes::validate(&self.scenes)
+    }
+}
+
+/// `http://host:port/volume` for an endpoint, filling in `default_port`.
+/// IPv6 addresses come bracketed, with a port or without.
+pub fn endpoint_url(endpoint: &str, default_port: u16) -> String {
+    let (host, port) = split_host_port(endpoint);
+    let port = port.unwrap_or(default_port);
+    if host.contains(':') {
+        format!("http://[{host}]:{port}/volume")
+    } else {
+        format!("http://{host}:{port}/volume")
+    }
+}
+
+/// `host`, `host:port`, a bare IPv6 address or `[v6]` / `[v6]:port`.
+fn split_host_port(endpoint: &str) -> (&str, Option<u16>) {
+    if let Some((host, rest)) = endpoint
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+    {
+        return (host, rest.strip_prefix(':').and_then(|p| p.parse().ok()));
+    }
+    match endpoint.rsplit_once(':') {
+        // More colons than that is an IPv6 address without a port
+        Some((host, port)) if !host.contains(':') => (host, port.parse().ok()),
+        _ => (endpoint, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::tests::{feed_level, test_config};
+    use crate::dsp::{Analyzer, Compressor};
+    use crate::units::{LoudnessUnit, DEFAULT_FULL_SCALE_SPL};
+
+    /// Level of each 50 ms window: 3s at -20 dBFS, then 3s at -40.
+    fn programme() -> Vec<f32> {
+        [-20.0; 60].into_iter().chain([-40.0; 60]).collect()
+    }
+
+    #[test]
+    fn parses_zones() {
+        let config: FileConfig = serde_json::from_str(
+            r#"{"zones": [
+                {"name": "living", "device": "USB", "endpoints": ["10.0.0.2:9000"]},
+                {"name": "bedroom", "endpoints": ["10.0.0.3"]}
+            ]}"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.zones.len(), 2);
+        assert_eq!(config.zones[0].device.as_deref(), Some("USB"));
+        assert_eq!(config.zones[1].device, None);
+    }
+
+    #[test]
+    fn rejects_bad_zones() {
+        let dup: FileConfig = serde_json::from_str(
+            r#"{"zones": [{"name": "a", "endpoints": ["x"]}, {"name": "a", "endpoints": ["y"]}]}"#,
+        )
+        .unwrap();
+        assert!(dup.validate().is_err());
+
+        let empty: FileConfig =
+            serde_json::from_str(r#"{"zones": [{"name": "a", "endpoints": []}]}"#).unwrap();
+        assert!(empty.validate().is_err());
+
+        assert!(serde_json::from_str::<FileConfig>(r#"{"zone": []}"#).is_err());
+    }
+
+    #[test]
+    fn endpoint_url_fills_default_port() {
+        assert_eq!(
+            endpoint_url("10.0.0.3", 8765),
+            "http://10.0.0.3:8765/volume"
+        );
+        assert_eq!(
+            endpoint_url("10.0.0.3:9000", 8765),
+            "http://10.0.0.3:9000/volume"
+        );
+        assert_eq!(
+            endpoint_url("tv.local:9000", 8765),
+            "http://tv.local:9000/volume"
+        );
+    }
+
+    #[test]
+    fn endpoint_url_brackets_ipv6() {
+        assert_eq!(
+            endpoint_url("fe80::1", 8765),
+            "http://[fe80::1]:8765/volume"
+        );
+        assert_eq!(endpoint_url("[::1]:9000", 8765), "http://[::1]:9000/volume");
+        assert_eq!(endpoint_url("[::1]", 8765), "http://[::1]:8765/volume");
+    }
+
+    #[test]
+    fn device_profile_follows_the_input_device() {
+        let config: FileConfig = serde_json::from_str(
+            r#"{"devices": {
+                "HDMI": {"target": -28},
+                "USB": {"target": -22},
+                "USB Audio 2": {"target": -30, "release": 4000}
+            }}"#,
+        )
+        .unwrap();
+        let target = |name| config.device_profile(name).map(|(key, c)| (key, c.target));
+        assert_eq!(target("HDMI Capture (hw:2,0)"), Some(("HDMI", Some(-28.0))));
+        assert_eq!(target("USB Audio 1"), Some(("USB", Some(-22.0))));
+        // The most specific key wins
+        assert_eq!(
+            target("USB Audio 2 Mic"),
+            Some(("USB Audio 2", Some(-30.0)))
+        );
+        assert_eq!(target("Built-in Microphone"), None);
+    }
+
+    #[test]
+    fn overrides_give_zones_their_own_trajectory() {
+        let config: FileConfig = serde_json::from_str(
+            r#"{"zones": [
+                {"name": "a", "device": "USB", "endpoints": ["10.0.0.2"]},
+                {"name": "b", "device": "USB", "endpoints": ["10.0.0.3"],
+                 "control": {"target": -20, "max_slew": 10}}
+            ]}"#,
+        )
+        .unwrap();
+        let units = Units::new(LoudnessUnit::Dbfs, DEFAULT_FULL_SCALE_SPL);
+
+        // Same audio through both zones' controllers, analyzed once
+        let configs: Vec<CompressorConfig> = config
+            .zones
+            .iter()
+            .map(|zone| {
+                let mut cc = test_config();
+                zone.control.apply(&mut cc, units);
+                cc
+            })
+            .collect();
+        let mut analyzer = Analyzer::new(&configs[0]);
+        let mut comps: Vec<Compressor> = configs
+            .into_iter()
+            .map(|cc| Compressor::new(cc, 0.5))
+            .collect();
+        let mut trajectories = vec![Vec::new(); comps.len()];
+        for dbfs in programme() {
+            let readings = analyzer.push(&[10f32.powf(dbfs / 20.0); 400]);
+            for (comp, volumes) in comps.iter_mut().zip(&mut trajectories) {
+                volumes.extend(comp.apply(&readings).map(|r| r.volume));
+            }
+        }
+
+        // A zone of its own, analyzing for itself
+        let mut comp = Compressor::new(test_config(), 0.5);
+        let baseline: Vec<f32> = programme()
+            .into_iter()
+            .flat_map(|dbfs| feed_level(&mut comp, dbfs, 0.05))
+            .map(|r| r.volume)
+            .collect();
+        assert_eq!(
+            trajectories[0], baseline,
+            "no overrides: command-line settings, as if analyzed alone"
+        );
+        assert_ne!(trajectories[0], trajectories[1]);
+        // -20 dB is on target for "b", too loud for "a"
+        let loud = trajectories[0].len() / 2;
+        assert_eq!(trajectories[0][loud - 1], 0.05);
+        assert!(trajectories[1][..loud].iter().all(|&v| v > 0.5));
+        // Both boost on the quiet part
+        assert!(trajectories.iter().all(|t| t.last() == Some(&0.95)));
+    }
+}
@@ -0,0 +1,165 @@
+//! The Mac's own output volume, set through CoreAudio instead of a controller.
+
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::ptr::null;
+
+use anyhow::{anyhow, Result};
+use coreaudio_sys::{
+    kAudioDevicePropertyScopeOutput, kAudioDevicePropertyVolumeScalar, kAudioHardwareNoError,
+    kAudioHardwarePropertyDefaultOutputDevice, kAudioObjectPropertyElementMaster,
+    kAudioObjectPropertyScopeGlobal, kAudioObjectSystemObject, AudioDeviceID,
+    AudioObjectGetPropertyData, AudioObjectHasProperty, AudioObjectPropertyAddress,
+    AudioObjectSetPropertyData, OSStatus,
+};
+
+use crate::sink::{SinkFuture, VolumeSink};
+
+/// Left and right, for devices without a main volume control.
+const STEREO_ELEMENTS: [u32; 2] = [1, 2];
+
+/// The default output device's volume scalar. 0.0-1.0 maps straight onto it.
+pub struct CoreAudioSink {
+    device: AudioDeviceID,
+    /// Elements carrying a volume control: the main one, or each channel
+    elements: Vec<u32>,
+    name: String,
+}
+
+impl CoreAudioSink {
+    /// The output device selected in System Settings right now.
+    pub fn default_output() -> Result<Self> {
+        let address = AudioObjectPropertyAddress {
+            mSelector: kAudioHardwarePropertyDefaultOutputDevice,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let mut device: AudioDeviceID = 0;
+        let mut size = size_of::<AudioDeviceID>() as u32;
+        check(
+            unsafe {
+                AudioObjectGetPropertyData(
+                    kAudioObjectSystemObject,
+                    &address,
+                    0,
+                    null(),
+                    &mut size,
+                    &mut device as *mut _ as *mut c_void,
+                )
+            },
+            "reading the default output device",
+        )?;
+
+        let elements: Vec<u32> = if has_volume(device, kAudioObjectPropertyElementMaster) {
+            vec![kAudioObjectPropertyElementMaster]
+        } else {
+            STEREO_ELEMENTS
+                .into_iter()
+                .filter(|&e| has_volume(device, e))
+                .collect()
+        };
+        if elements.is_empty() {
+            return Err(anyhow!("Default output device has no volume control"));
+        }
+        Ok(Self {
+            device,
+            elements,
+            name: format!("coreaudio:{device}"),
+        })
+    }
+
+    /// Current volume, averaged over channels when there's no main control.
+    pub fn volume(&self) -> Result<f32> {
+        let mut total = 0.0;
+        for &element in &self.elements {
+            let mut scalar: f32 = 0.0;
+            let mut size = size_of::<f32>() as u32;
+            check(
+                unsafe {
+                    AudioObjectGetPropertyData(
+                        self.device,
+                        &volume_address(element),
+                        0,
+                        null(),
+                        &mut size,
+                        &mut scalar as *mut _ as *mut c_void,
+                    )
+                },
+                "reading the output volume",
+            )?;
+            total += scalar;
+        }
+        Ok(total / self.elements.len() as f32)
+    }
+
+    fn set(&self, volume: f32) -> Result<()> {
+        let scalar = volume.clamp(0.0, 1.0);
+        for &element in &self.elements {
+            check(
+                unsafe {
+                    AudioObjectSetPropertyData(
+                        self.device,
+                        &volume_address(element),
+                        0,
+                        null(),
+                        size_of::<f32>() as u32,
+                        &scalar as *const _ as *const c_void,
+                    )
+                },
+                "setting the output volume",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl VolumeSink for CoreAudioSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+        // A local property write; no need to leave the task
+        let result = self.set(volume);
+        Box::pin(async move { result })
+    }
+}
+
+fn volume_address(element: u32) -> AudioObjectPropertyAddress {
+    AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyVolumeScalar,
+        mScope: kAudioDevicePropertyScopeOutput,
+        mElement: element,
+    }
+}
+
+fn has_volume(device: AudioDeviceID, element: u32) -> bool {
+    unsafe { AudioObjectHasProperty(device, &volume_address(element)) != 0 }
+}
+
+fn check(status: OSStatus, what: &str) -> Result<()> {
+    if status == kAudioHardwareNoError as i32 {
+        Ok(())
+    } else {
+        Err(anyhow!("CoreAudio error {status} {what}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_round_trips() {
+        // CI Macs may have no output device at all
+        let Ok(sink) = CoreAudioSink::default_output() else {
+            return;
+        };
+        let original = sink.volume().unwrap();
+        sink.set(0.3).unwrap();
+        let read = sink.volume().unwrap();
+        sink.set(original).unwrap();
+        // Devices quantize their volume steps
+        assert!((read - 0.3).abs() < 0.05, "{read}");
+    }
+}
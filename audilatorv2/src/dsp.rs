@@ -1,6 +1,25 @@
 use std::collections::VecDeque;
+use std::ops::Range;
 use std::time::Instant;
 
+use serde::Serialize;
+
+use crate::adaptive::{
+    AdaptiveConfig, AdaptiveThresholds, LevelHistogram, MedianConfig, MedianThresholds, Thresholds,
+};
+use crate::learn::{ContentTracker, ContentType, LearnedTargets};
+use crate::loudness::{ControlTimescale, Loudness, LoudnessMeter};
+use crate::regions::{Region, RegionMap};
+use crate::scenes::{BassMeter, Scene, SceneClassifier};
+use crate::speech::SpeechDetector;
+use crate::target::{FixedTarget, TargetProvider};
+
+/// The compressor's RMS window: integer sums with `--features fixed-point`.
+#[cfg(feature = "fixed-point")]
+type WindowRing = crate::fixed::FixedRing;
+#[cfg(not(feature = "fixed-point"))]
+type WindowRing = RingBuffer;
+
 /// Fixed-size ring buffer for RMS computation. O(1) insert.
 pub struct RingBuffer {
     buf: VecDeque<f32>,
@@ -77,6 +96,72 @@ impl EnvelopeFollower {
         }
         self.value
     }
+
+    /// Jump straight to `level_dbfs`, discarding attack/release history.
+    pub fn reset(&mut self, level_dbfs: f32) {
+        self.value = level_dbfs;
+    }
+}
+
+/// Exponential moving average for values that are only shown, never acted on.
+pub struct DisplaySmoother {
+    coeff: f32,
+    value: Option<f32>,
+}
+
+impl DisplaySmoother {
+    /// A `time_constant_sec` of 0 passes values straight through.
+    pub fn new(time_constant_sec: f32, update_rate_hz: f32) -> Self {
+        let coeff = if time_constant_sec > 0.0 {
+            (-1.0 / (time_constant_sec * update_rate_hz)).exp()
+        } else {
+            0.0
+        };
+        Self { coeff, value: None }
+    }
+
+    pub fn update(&mut self, value: f32) -> f32 {
+        let smoothed = match self.value {
+            Some(prev) => self.coeff * prev + (1.0 - self.coeff) * value,
+            None => value,
+        };
+        self.value = Some(smoothed);
+        smoothed
+    }
+}
+
+/// Target and dead zone suggested by loudness percentiles: level on the
+/// median, leaving a sixth of the 10th-90th spread (at least 2 dB) either side.
+pub fn suggested_settings(p10: f32, p50: f32, p90: f32) -> (f32, f32) {
+    (p50, ((p90 - p10) / 6.0).max(2.0))
+}
+
+/// Share of the recalibration window that must be heard before recalibrating.
+const RECALIBRATE_MIN_FILL: f32 = 0.25;
+
+/// Dead zone/hysteresis multiplier while content is volatile.
+const VOLATILE_ZONE_SCALE: f32 = 2.0;
+/// Slew multiplier while content is volatile.
+const VOLATILE_SLEW_SCALE: f32 = 0.5;
+
+/// Correction speed that grows with the size of the loudness step being
+/// corrected: `min_db_per_sec` for a step just past the dead zone, rising
+/// linearly to `max_db_per_sec` for steps of `full_at_db` or more. Replaces
+/// the fixed slew limit, so a channel change is caught quickly while small
+/// drifts are eased in.
+#[derive(Clone, Copy, Debug)]
+pub struct RampConfig {
+    pub min_db_per_sec: f32,
+    pub max_db_per_sec: f32,
+    pub full_at_db: f32,
+}
+
+impl RampConfig {
+    fn rate_db_per_sec(&self, step_db: f32, dead_zone_db: f32) -> f32 {
+        let span = (self.full_at_db - dead_zone_db).max(f32::EPSILON);
+        let share = ((step_db - dead_zone_db) / span).clamp(0.0, 1.0);
+        self.min_db_per_sec + (self.max_db_per_sec - self.min_db_per_sec) * share
+    }
 }
 
 /// Gain computer with dead zone + hysteresis to prevent oscillation.
@@ -85,7 +170,17 @@ struct GainComputer {
     dead_zone: f32,
     hysteresis: f32,
     max_slew_per_update: f32,
+    /// dB cut per dB of overshoot above the loud threshold
+    duck_ratio: f32,
+    max_duck_per_update: f32,
+    ramp: Option<RampConfig>,
+    update_rate_hz: f32,
+    /// Largest error seen in the current correction
+    step_db: f32,
+    /// The boost limit of the last update: ramp and volatility applied
+    max_boost: f32,
     is_adjusting: bool,
+    volatile: bool,
 }
 
 impl GainComputer {
@@ -101,34 +196,200 @@ impl GainComputer {
             dead_zone,
             hysteresis,
             max_slew_per_update: max_slew_db_per_sec / update_rate_hz,
+            duck_ratio: 1.0,
+            max_duck_per_update: max_slew_db_per_sec / update_rate_hz,
+            ramp: None,
+            update_rate_hz,
+            step_db: 0.0,
+            max_boost: max_slew_db_per_sec / update_rate_hz,
             is_adjusting: false,
+            volatile: false,
+        }
+    }
+
+    /// Scale both slew limits with the step being corrected.
+    fn with_ramp(mut self, ramp: Option<RampConfig>) -> Self {
+        self.ramp = ramp;
+        self
+    }
+
+    /// Cut loud content by `ratio` dB per dB over the loud threshold, at most
+    /// `max_db_per_sec` (0 keeps the slew limit).
+    fn with_ducking(mut self, ratio: f32, max_db_per_sec: f32, update_rate_hz: f32) -> Self {
+        self.duck_ratio = ratio;
+        if max_db_per_sec > 0.0 {
+            self.max_duck_per_update = max_db_per_sec / update_rate_hz;
+        }
+        self
+    }
+
+    fn set_target(&mut self, target_dbfs: f32) {
+        self.target = target_dbfs;
+    }
+
+    fn set_dead_zone(&mut self, dead_zone_db: f32) {
+        self.dead_zone = dead_zone_db;
+    }
+
+    /// Envelope levels beyond which it boosts (quiet) or cuts (loud).
+    fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            quiet_dbfs: self.target - self.dead_zone,
+            loud_dbfs: self.target + self.dead_zone,
         }
     }
 
+    /// Forget the hysteresis state, as if starting inside the dead zone.
+    fn reset(&mut self) {
+        self.is_adjusting = false;
+    }
+
+    /// Widen the zones and slow down while content is swinging quiet/loud.
+    fn set_volatile(&mut self, volatile: bool) {
+        self.volatile = volatile;
+    }
+
     fn compute(&mut self, envelope_dbfs: f32) -> f32 {
+        let (zone_scale, slew_scale) = if self.volatile {
+            (VOLATILE_ZONE_SCALE, VOLATILE_SLEW_SCALE)
+        } else {
+            (1.0, 1.0)
+        };
+        let dead_zone = self.dead_zone * zone_scale;
+        let hysteresis = self.hysteresis * zone_scale;
+
         let error = self.target - envelope_dbfs;
         let abs_error = error.abs();
 
         // Hysteresis state machine
         if self.is_adjusting {
-            if abs_error < self.hysteresis {
+            if abs_error < hysteresis {
                 self.is_adjusting = false;
                 return 0.0;
             }
-        } else if abs_error > self.dead_zone {
+        } else if abs_error > dead_zone {
             self.is_adjusting = true;
+            self.step_db = 0.0;
         } else {
             return 0.0;
         }
+        // The envelope reveals a step gradually; the ramp follows its size
+        self.step_db = self.step_db.max(abs_error);
+        let (max_slew, max_duck) = match self.ramp {
+            Some(ramp) => {
+                let rate = ramp.rate_db_per_sec(self.step_db, dead_zone) / self.update_rate_hz;
+                (rate, rate)
+            }
+            None => (self.max_slew_per_update, self.max_duck_per_update),
+        };
+        let max_slew = max_slew * slew_scale;
+        self.max_boost = max_slew;
 
         // Correct only beyond dead zone boundary
-        let correction = if error > 0.0 {
-            error - self.dead_zone
+        if error > 0.0 {
+            (error - dead_zone).clamp(-max_slew, max_slew)
         } else {
-            error + self.dead_zone
-        };
+            // Proportional duck: slightly loud dips a little, an explosion a lot
+            let overshoot = -error - dead_zone;
+            let max_duck = max_duck * slew_scale;
+            (-overshoot * self.duck_ratio).clamp(-max_duck, max_slew)
+        }
+    }
+}
+
+/// Seconds a drop into quiet content must last to count as a transition,
+/// so pauses between words don't qualify.
+const TRANSITION_HOLD_SEC: f32 = 0.25;
 
-        correction.clamp(-self.max_slew_per_update, self.max_slew_per_update)
+/// Spots content flipping to the far side of the dead zone from the
+/// direction we've been correcting in (boosting, then loud; cutting, then quiet).
+struct TransitionDetector {
+    hold_updates: usize,
+    quiet_updates: usize,
+}
+
+impl TransitionDetector {
+    fn new(update_rate_hz: f32) -> Self {
+        Self {
+            hold_updates: ((TRANSITION_HOLD_SEC * update_rate_hz) as usize).max(1),
+            quiet_updates: 0,
+        }
+    }
+
+    /// `direction` is the sign of the last correction (0 if none yet).
+    fn update(&mut self, level_dbfs: f32, target: f32, dead_zone: f32, direction: f32) -> bool {
+        if direction < 0.0 && level_dbfs < target - dead_zone {
+            self.quiet_updates += 1;
+            if self.quiet_updates >= self.hold_updates {
+                self.quiet_updates = 0;
+                return true;
+            }
+            return false;
+        }
+        self.quiet_updates = 0;
+        // A jump into loud content counts at once, like the envelope's attack
+        direction > 0.0 && level_dbfs > target + dead_zone
+    }
+}
+
+/// Rolling standard deviation of the envelope over a fixed window.
+/// Flags content that alternates between quiet and loud too fast to chase.
+pub struct VarianceTracker {
+    levels: VecDeque<f32>,
+    capacity: usize,
+    sum: f64,
+    sum_squares: f64,
+    threshold_db: f32,
+}
+
+impl VarianceTracker {
+    pub fn new(window_sec: f32, threshold_db: f32, update_rate_hz: f32) -> Self {
+        let capacity = (window_sec * update_rate_hz) as usize;
+        Self {
+            levels: VecDeque::with_capacity(capacity),
+            capacity,
+            sum: 0.0,
+            sum_squares: 0.0,
+            threshold_db,
+        }
+    }
+
+    /// Push a level. Returns true once the window is full and volatile.
+    fn update(&mut self, level_dbfs: f32) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        self.push(level_dbfs);
+        if self.levels.len() < self.capacity {
+            return false;
+        }
+        self.std_dev() > self.threshold_db
+    }
+
+    /// Add a level to the window without judging it.
+    pub fn push(&mut self, level_dbfs: f32) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.levels.len() >= self.capacity {
+            if let Some(old) = self.levels.pop_front() {
+                self.sum -= old as f64;
+                self.sum_squares -= (old as f64) * (old as f64);
+            }
+        }
+        self.sum += level_dbfs as f64;
+        self.sum_squares += (level_dbfs as f64) * (level_dbfs as f64);
+        self.levels.push_back(level_dbfs);
+    }
+
+    /// Over what the window holds so far (0 when empty).
+    pub fn std_dev(&self) -> f32 {
+        if self.levels.is_empty() {
+            return 0.0;
+        }
+        let n = self.levels.len() as f64;
+        let mean = self.sum / n;
+        (self.sum_squares / n - mean * mean).max(0.0).sqrt() as f32
     }
 }
 
@@ -159,6 +420,66 @@ impl SilenceDetector {
     }
 }
 
+/// Holds boosts across brief drops below the silence threshold, like the
+/// gap before an ad break, and for a while after sound returns. The envelope
+/// sags during the gap, so boosting then would only be slammed back down by
+/// whatever loud content follows.
+struct GapHold {
+    threshold: f32,
+    hold_updates: usize,
+    remaining: usize,
+}
+
+impl GapHold {
+    fn new(threshold_dbfs: f32, hold_ms: f32, update_rate_hz: f32) -> Self {
+        Self {
+            threshold: threshold_dbfs,
+            hold_updates: (hold_ms / 1000.0 * update_rate_hz).round() as usize,
+            remaining: 0,
+        }
+    }
+
+    /// Feed the unsmoothed level; true while boosts are held.
+    fn update(&mut self, dbfs: f32) -> bool {
+        if dbfs < self.threshold {
+            self.remaining = self.hold_updates;
+            return true;
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+/// Equal-loudness excess at 100 Hz (ISO 226:2003): (phon, dB SPL above the
+/// 1 kHz reference needed to sound as loud). Bass fades faster than the
+/// midrange as playback gets quieter.
+const BASS_CONTOUR: [(f32, f32); 4] = [(20.0, 31.4), (40.0, 22.9), (60.0, 14.3), (80.0, 5.9)];
+/// Playback level taken for full volume, in phon.
+const FULL_VOLUME_PHON: f32 = 80.0;
+/// dB of extra perceived bass loss that doubles a boost at strength 1.
+const COMPENSATION_SCALE_DB: f32 = 20.0;
+
+/// How much to scale a boost at `volume` (0.0-1.0) so quiet listening isn't
+/// left thinner than the RMS says: 1.0 at full volume, rising as the volume
+/// falls and the bass contour diverges from its full-volume shape.
+fn low_volume_boost(volume: f32, strength: f32) -> f32 {
+    let phon = (FULL_VOLUME_PHON + 20.0 * volume.max(1e-6).log10())
+        .clamp(BASS_CONTOUR[0].0, FULL_VOLUME_PHON);
+    let excess = BASS_CONTOUR
+        .windows(2)
+        .find(|w| phon <= w[1].0)
+        .map(|w| {
+            let ((p0, e0), (p1, e1)) = (w[0], w[1]);
+            e0 + (e1 - e0) * (phon - p0) / (p1 - p0)
+        })
+        .unwrap_or(BASS_CONTOUR[3].1);
+    let loss = excess - BASS_CONTOUR[3].1;
+    1.0 + strength * loss / COMPENSATION_SCALE_DB
+}
+
 /// Tracks volume scalar and applies dB-domain changes.
 struct VolumeState {
     scalar: f32,
@@ -175,6 +496,17 @@ impl VolumeState {
         }
     }
 
+    /// The bound `delta_db` pushes against, if the volume is already there.
+    fn saturation(&self, delta_db: f32) -> Option<Saturation> {
+        if delta_db > 0.0 && self.scalar >= self.max {
+            Some(Saturation::Max)
+        } else if delta_db < 0.0 && self.scalar <= self.min {
+            Some(Saturation::Min)
+        } else {
+            None
+        }
+    }
+
     fn apply_db_change(&mut self, delta_db: f32) -> f32 {
         if delta_db.abs() < 0.001 {
             return self.scalar;
@@ -186,6 +518,7 @@ impl VolumeState {
 }
 
 /// Configuration for the compressor.
+#[derive(Clone)]
 pub struct CompressorConfig {
     pub target_dbfs: f32,
     pub dead_zone_db: f32,
@@ -193,94 +526,572 @@ pub struct CompressorConfig {
     pub attack_ms: f32,
     pub release_ms: f32,
     pub max_slew_db_per_sec: f32,
+    /// dB cut per dB the envelope is over the loud threshold
+    pub duck_ratio: f32,
+    /// Cap on how fast loud content is cut (0 = max_slew_db_per_sec)
+    pub duck_max_db_per_sec: f32,
+    /// Slew limits that scale with the step size instead (None = fixed)
+    pub ramp: Option<RampConfig>,
     pub silence_threshold_dbfs: f32,
     pub silence_hold_sec: f32,
+    /// Seconds of envelope history for volatility detection (0 disables)
+    pub variance_window_sec: f32,
+    /// Envelope std-dev in dB above which content counts as volatile
+    pub variance_threshold_db: f32,
+    /// Drop envelope and hysteresis state when content flips quiet/loud
+    pub reset_on_transition: bool,
+    /// Hold boosts for this long after a brief silence gap (0 disables)
+    pub gap_hold_ms: f32,
+    /// Hold the volume while speech is detected
+    pub hold_during_speech: bool,
+    /// The opposite: correct only while speech is detected (a dialogue
+    /// channel, levelled on the dialogue alone)
+    pub speech_only: bool,
+    /// Offset the target per content type (None = no content tracking)
+    pub learned: Option<LearnedTargets>,
+    /// Strength of the extra boost at low volumes (0 disables, 1 nominal)
+    pub low_volume_compensation: f32,
+    /// Loudness regions replacing the dead zone model (empty = off)
+    pub regions: Vec<Region>,
+    /// Content scenes with their own targets (empty = off)
+    pub scenes: Vec<Scene>,
+    /// Which measurement feeds the envelope
+    pub control_timescale: ControlTimescale,
+    /// Take target and dead zone from a rolling loudness histogram
+    pub adaptive: Option<AdaptiveConfig>,
+    /// Take them from deviations around the rolling median instead
+    pub median: Option<MedianConfig>,
+    /// Seconds of loudness kept for `Compressor::recalibrate` (0 disables)
+    pub recalibration_window_sec: f32,
     pub rms_window_ms: f32,
     pub sample_rate: u32,
     pub vol_min: f32,
     pub vol_max: f32,
 }
 
+/// Settings before and after a `Compressor::recalibrate`.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Recalibration {
+    pub old_target_dbfs: f32,
+    pub old_dead_zone_db: f32,
+    pub target_dbfs: f32,
+    pub dead_zone_db: f32,
+}
+
+/// A volume bound the controller is pinned against: the content still wants
+/// more (or less) than vol_max (or vol_min) allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Saturation {
+    Min,
+    Max,
+}
+
 /// Result of processing an audio chunk.
 pub struct ProcessResult {
     pub envelope_dbfs: f32,
+    pub loudness: Loudness,
+    pub target_dbfs: f32,
+    pub thresholds: Thresholds,
     pub delta_db: f32,
     pub volume: f32,
     pub silent: bool,
+    pub volatile: bool,
+    /// Pinned at a bound, until the content stops pushing into it
+    pub saturated: Option<Saturation>,
+    /// Index of the scene playing (see `Compressor::scene_name`)
+    pub scene: Option<usize>,
+}
+
+/// One window's measurements, before any zone's control settings apply.
+#[derive(Clone, Copy, Debug)]
+pub struct Reading {
+    rms: f32,
+    loudness: Loudness,
+    speaking: bool,
+    /// For scenes
+    bass_db: Option<f32>,
+}
+
+/// Windows a capture and measures each window: RMS, loudness, speech and
+/// the scene features. Zones on one capture can share an analyzer, since
+/// their overrides (target, dead zone, attack, release...) only change
+/// what they do with a reading.
+pub struct Analyzer {
+    ring: WindowRing,
+    loudness: LoudnessMeter,
+    speech: Option<SpeechDetector>,
+    bass: Option<BassMeter>,
+    window_samples: usize,
+    samples_since_rms: usize,
+}
+
+impl Analyzer {
+    pub fn new(config: &CompressorConfig) -> Self {
+        let window_samples = (config.sample_rate as f32 * config.rms_window_ms / 1000.0) as usize;
+        let update_rate = 1000.0 / config.rms_window_ms;
+        Self {
+            ring: WindowRing::new(window_samples),
+            loudness: LoudnessMeter::new(config.sample_rate, update_rate),
+            speech: (config.hold_during_speech || config.speech_only || config.learned.is_some())
+                .then(|| SpeechDetector::new(config.sample_rate, update_rate)),
+            bass: (!config.scenes.is_empty()).then(|| BassMeter::new(config.sample_rate)),
+            window_samples,
+            samples_since_rms: 0,
+        }
+    }
+
+    /// Feed audio samples. Returns a reading for each window completed.
+    ///
+    /// Buffers are split at window boundaries, so the readings don't depend
+    /// on how the samples were batched.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Reading> {
+        self.windows(samples, |ring, range| ring.extend(&samples[range]))
+    }
+
+    /// `push` for a Q15 batch: the RMS window sums `q15` itself, and
+    /// `samples`, its float copy, goes to the filters.
+    #[cfg(feature = "fixed-point")]
+    pub fn push_q15(&mut self, q15: &[i16], samples: &[f32]) -> Vec<Reading> {
+        self.windows(samples, |ring, range| ring.extend_q15(&q15[range]))
+    }
+
+    /// Split `samples` at window boundaries, filling the RMS window's part
+    /// with `fill`.
+    fn windows(
+        &mut self,
+        samples: &[f32],
+        mut fill: impl FnMut(&mut WindowRing, Range<usize>),
+    ) -> Vec<Reading> {
+        let mut readings = Vec::new();
+        let mut start = 0;
+        while start < samples.len() {
+            let room = self
+                .window_samples
+                .saturating_sub(self.samples_since_rms)
+                .max(1);
+            let end = (start + room).min(samples.len());
+            let head = &samples[start..end];
+            fill(&mut self.ring, start..end);
+            start = end;
+
+            self.loudness.push(head);
+            if let Some(bass) = &mut self.bass {
+                bass.push(head);
+            }
+            if let Some(speech) = &mut self.speech {
+                speech.push(head);
+            }
+            self.samples_since_rms += head.len();
+
+            if self.samples_since_rms >= self.window_samples && self.ring.is_full() {
+                self.samples_since_rms = 0;
+                readings.push(Reading {
+                    rms: self.ring.rms(),
+                    loudness: self.loudness.update(),
+                    speaking: self.speech.as_mut().is_some_and(SpeechDetector::update),
+                    bass_db: self.bass.as_mut().map(BassMeter::take),
+                });
+            }
+        }
+        readings
+    }
+
+    /// Nothing is read until a fresh window has filled.
+    fn restart_window(&mut self) {
+        self.ring = WindowRing::new(self.window_samples);
+        self.samples_since_rms = 0;
+    }
 }
 
-/// Full compressor pipeline: RingBuffer -> dBFS -> Envelope -> Gain -> Volume.
+/// Full compressor pipeline: Analyzer -> dBFS -> Envelope -> Gain -> Volume.
 pub struct Compressor {
-    ring: RingBuffer,
+    analyzer: Analyzer,
+    timescale: ControlTimescale,
     envelope: EnvelopeFollower,
+    target: Box<dyn TargetProvider>,
     gain: GainComputer,
     silence: SilenceDetector,
+    variance: VarianceTracker,
+    transition: Option<TransitionDetector>,
+    gap_hold: Option<GapHold>,
+    hold_during_speech: bool,
+    speech_only: bool,
+    content: Option<ContentTracker>,
+    content_type: Option<ContentType>,
+    learned: LearnedTargets,
+    low_volume_compensation: f32,
+    regions: Option<RegionMap>,
+    scenes: Option<SceneClassifier>,
+    scene: Option<usize>,
+    adaptive: Option<AdaptiveThresholds>,
+    median: Option<MedianThresholds>,
+    adaptive_thresholds: Option<Thresholds>,
+    /// Open-loop levels: what the content would measure at the starting
+    /// volume, so recalibration doesn't just confirm the leveler's own work
+    recalibration: Option<LevelHistogram>,
+    reference_volume: f32,
+    /// Recalibrated target relative to the target provider's
+    calibration_offset: f32,
+    last_direction: f32,
     volume: VolumeState,
-    window_samples: usize,
-    samples_since_rms: usize,
+    /// Envelope starts from the next window (see `warm_up`)
+    warming: bool,
+    /// Volume stands still while sends are held (see `hold`)
+    held: bool,
+    saturated: Option<Saturation>,
 }
 
 impl Compressor {
     pub fn new(config: CompressorConfig, initial_volume: f32) -> Self {
-        let window_samples = (config.sample_rate as f32 * config.rms_window_ms / 1000.0) as usize;
+        let target = Box::new(FixedTarget(config.target_dbfs));
+        Self::with_target(config, initial_volume, target)
+    }
+
+    /// Like `new`, but the loudness target comes from `target` on every update.
+    pub fn with_target(
+        config: CompressorConfig,
+        initial_volume: f32,
+        target: Box<dyn TargetProvider>,
+    ) -> Self {
         let update_rate = 1000.0 / config.rms_window_ms;
 
         Self {
-            ring: RingBuffer::new(window_samples),
+            analyzer: Analyzer::new(&config),
+            timescale: config.control_timescale,
             envelope: EnvelopeFollower::new(config.attack_ms, config.release_ms, update_rate),
+            target,
             gain: GainComputer::new(
                 config.target_dbfs,
                 config.dead_zone_db,
                 config.hysteresis_db,
                 config.max_slew_db_per_sec,
                 update_rate,
-            ),
+            )
+            .with_ducking(config.duck_ratio, config.duck_max_db_per_sec, update_rate)
+            .with_ramp(config.ramp),
             silence: SilenceDetector::new(config.silence_threshold_dbfs, config.silence_hold_sec),
+            variance: VarianceTracker::new(
+                config.variance_window_sec,
+                config.variance_threshold_db,
+                update_rate,
+            ),
+            transition: config
+                .reset_on_transition
+                .then(|| TransitionDetector::new(update_rate)),
+            gap_hold: (config.gap_hold_ms > 0.0).then(|| {
+                GapHold::new(
+                    config.silence_threshold_dbfs,
+                    config.gap_hold_ms,
+                    update_rate,
+                )
+            }),
+            hold_during_speech: config.hold_during_speech,
+            speech_only: config.speech_only,
+            content: config
+                .learned
+                .is_some()
+                .then(|| ContentTracker::new(update_rate)),
+            content_type: None,
+            learned: config.learned.unwrap_or_default(),
+            low_volume_compensation: config.low_volume_compensation,
+            regions: (!config.regions.is_empty())
+                .then(|| RegionMap::new(config.regions, update_rate)),
+            scenes: (!config.scenes.is_empty())
+                .then(|| SceneClassifier::new(config.scenes, update_rate)),
+            scene: None,
+            adaptive: config
+                .adaptive
+                .map(|a| AdaptiveThresholds::new(a, update_rate)),
+            median: config.median.map(|m| MedianThresholds::new(m, update_rate)),
+            adaptive_thresholds: None,
+            recalibration: (config.recalibration_window_sec > 0.0).then(|| {
+                LevelHistogram::new((config.recalibration_window_sec * update_rate) as usize)
+            }),
+            reference_volume: initial_volume.clamp(config.vol_min, config.vol_max),
+            calibration_offset: 0.0,
+            last_direction: 0.0,
             volume: VolumeState::new(initial_volume, config.vol_min, config.vol_max),
-            window_samples,
-            samples_since_rms: 0,
+            warming: false,
+            held: false,
+            saturated: None,
         }
     }
 
+    /// Name of a scene from `ProcessResult::scene`.
+    pub fn scene_name(&self, index: usize) -> &str {
+        self.scenes
+            .as_ref()
+            .map_or("", |scenes| &scenes.scene(index).name)
+    }
+
+    /// What's playing, when learned targets are on.
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.content_type
+    }
+
+    /// Replace the per-content target offsets, e.g. after a nudge.
+    pub fn set_learned(&mut self, learned: LearnedTargets) {
+        self.learned = learned;
+    }
+
+    /// Start over after standby. Hours of silence in the window and envelope
+    /// say nothing about the audio that's back, so nothing is analyzed until
+    /// a full window of it has arrived, and the envelope starts from there.
+    /// A shared analyzer kept reading for the other zones: the envelope
+    /// starts from its next reading.
+    pub fn warm_up(&mut self) {
+        self.analyzer.restart_window();
+        self.warming = true;
+    }
+
+    /// Stop moving the volume while nothing can be sent, so it doesn't run
+    /// off from what the sink has. Analysis carries on.
+    pub fn hold(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    pub fn held(&self) -> bool {
+        self.held
+    }
+
+    /// Continue from `volume`, e.g. the value last sent while held.
+    pub fn resync(&mut self, volume: f32) {
+        self.volume.scalar = volume.clamp(self.volume.min, self.volume.max);
+    }
+
+    /// Re-derive target and dead zone from recent non-silent loudness at
+    /// the starting volume, as `--calibrate` would suggest. The target
+    /// stays relative to the target provider's, so a schedule keeps moving
+    /// it. None until enough has been heard.
+    pub fn recalibrate(&mut self) -> Option<Recalibration> {
+        let levels = self.recalibration.as_ref()?;
+        if levels.fill() < RECALIBRATE_MIN_FILL {
+            return None;
+        }
+        let (target, dead_zone) = suggested_settings(
+            levels.percentile(10.0)?,
+            levels.percentile(50.0)?,
+            levels.percentile(90.0)?,
+        );
+        let old = Recalibration {
+            old_target_dbfs: self.gain.target,
+            old_dead_zone_db: self.gain.dead_zone,
+            target_dbfs: target,
+            dead_zone_db: dead_zone,
+        };
+        self.calibration_offset = target - self.target.current_target();
+        self.gain.set_target(target);
+        self.gain.set_dead_zone(dead_zone);
+        Some(old)
+    }
+
     /// Feed audio samples. Returns a result when a full RMS window has been analyzed.
+    ///
+    /// Buffers are split at window boundaries, so a buffer spanning several
+    /// windows analyzes each of them and returns the last result. The outcome
+    /// doesn't depend on how the samples were batched.
     pub fn process(&mut self, samples: &[f32]) -> Option<ProcessResult> {
-        self.ring.extend(samples);
-        self.samples_since_rms += samples.len();
+        let readings = self.analyzer.push(samples);
+        self.apply(&readings)
+    }
 
-        if self.samples_since_rms < self.window_samples || !self.ring.is_full() {
-            return None;
+    /// Like `process`, from readings of an analyzer shared with other zones
+    /// (this compressor's own goes unused). Returns the last result.
+    pub fn apply(&mut self, readings: &[Reading]) -> Option<ProcessResult> {
+        readings.iter().map(|r| self.analyze(r)).last()
+    }
+
+    /// One control update over a window's reading.
+    fn analyze(&mut self, reading: &Reading) -> ProcessResult {
+        let Reading {
+            rms,
+            loudness,
+            speaking,
+            bass_db,
+        } = *reading;
+        let dbfs = match self.timescale {
+            ControlTimescale::Window => rms_to_dbfs(rms),
+            ControlTimescale::Momentary => loudness.momentary,
+            ControlTimescale::ShortTerm => loudness.short_term,
+            // Short-term stands in until enough has been heard to integrate
+            ControlTimescale::Integrated => loudness.integrated.unwrap_or(loudness.short_term),
+        };
+        // Silence is neither speech nor music: what's playing is whatever
+        // played last
+        let silent_window = dbfs < self.silence.threshold;
+        if let Some(content) = self.content.as_mut().filter(|_| !silent_window) {
+            self.content_type = Some(content.update(speaking));
         }
+        let learned = self.content_type.map_or(0.0, |c| self.learned.offset(c));
+        let target = match self.adaptive_thresholds {
+            Some(t) => t.target_and_zone().0,
+            None => self.target.current_target() + self.calibration_offset,
+        } + learned;
+        self.gain.set_target(target);
 
-        self.samples_since_rms = 0;
+        if let Some(transition) = &mut self.transition {
+            if transition.update(
+                dbfs,
+                self.gain.target,
+                self.gain.dead_zone,
+                self.last_direction,
+            ) {
+                // Momentum from the previous condition would only overshoot
+                self.envelope.reset(dbfs);
+                self.gain.reset();
+                self.last_direction = 0.0;
+            }
+        }
 
-        let rms = self.ring.rms();
-        let dbfs = rms_to_dbfs(rms);
+        if std::mem::take(&mut self.warming) {
+            self.envelope.reset(dbfs);
+            self.gain.reset();
+            self.last_direction = 0.0;
+        }
         let env = self.envelope.update(dbfs);
-
+        if let (Some(scenes), Some(bass_db)) = (&mut self.scenes, bass_db) {
+            // A scene's target wins over every other source
+            self.scene = scenes.update(env, bass_db);
+            if let Some(i) = self.scene {
+                self.gain.set_target(scenes.scene(i).target);
+            }
+        }
+        let volatile = self.variance.update(env);
+        let gap_held = self.gap_hold.as_mut().is_some_and(|g| g.update(dbfs));
         if self.silence.is_silent(env) {
-            return Some(ProcessResult {
+            return ProcessResult {
                 envelope_dbfs: env,
+                loudness,
+                target_dbfs: self.gain.target,
+                thresholds: self.gain.thresholds(),
                 delta_db: 0.0,
                 volume: self.volume.scalar,
                 silent: true,
-            });
+                volatile,
+                // Frozen along with the volume
+                saturated: self.saturated,
+                scene: self.scene,
+            };
         }
 
-        let delta = self.gain.compute(env);
+        if let Some(levels) = &mut self.recalibration {
+            let gain_db = 20.0 * (self.volume.scalar / self.reference_volume).log10();
+            levels.push(env - gain_db);
+        }
+        let followed = match (&mut self.adaptive, &mut self.median) {
+            (Some(a), _) => a.update(env),
+            (None, Some(m)) => m.update(env),
+            (None, None) => None,
+        };
+        if let Some(t) = followed {
+            let (target, dead_zone) = t.target_and_zone();
+            self.gain.set_target(target);
+            self.gain.set_dead_zone(dead_zone);
+            self.adaptive_thresholds = Some(t);
+        }
+
+        self.gain.set_volatile(volatile);
+        let (mut delta, max_boost) = match &self.regions {
+            Some(regions) => (regions.step(env, self.volume.scalar), regions.max_step(env)),
+            None => (self.gain.compute(env), self.gain.max_boost),
+        };
+        if delta > 0.0 && self.low_volume_compensation > 0.0 {
+            // Faster, but no faster than this update's own limit
+            let scale = low_volume_boost(self.volume.scalar, self.low_volume_compensation);
+            delta = (delta * scale).min(max_boost);
+        }
+        if gap_held {
+            // Cuts still go through: loud content may be what ends the gap
+            delta = delta.min(0.0);
+        }
+        if speaking && self.hold_during_speech || !speaking && self.speech_only {
+            delta = 0.0;
+        }
+        if self.held {
+            // Saturation stays frozen along with the volume
+            return ProcessResult {
+                envelope_dbfs: env,
+                loudness,
+                target_dbfs: self.gain.target,
+                thresholds: self.gain.thresholds(),
+                delta_db: 0.0,
+                volume: self.volume.scalar,
+                silent: false,
+                volatile,
+                saturated: self.saturated,
+                scene: self.scene,
+            };
+        }
         let vol = self.volume.apply_db_change(delta);
+        self.saturated = self.volume.saturation(delta);
+        if delta != 0.0 {
+            self.last_direction = delta.signum();
+        }
 
-        Some(ProcessResult {
+        ProcessResult {
             envelope_dbfs: env,
+            loudness,
+            target_dbfs: self.gain.target,
+            thresholds: self.gain.thresholds(),
             delta_db: delta,
             volume: vol,
             silent: false,
-        })
+            volatile,
+            saturated: self.saturated,
+            scene: self.scene,
+        }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::target::tests::PushedTarget;
+
+    pub(crate) fn test_config() -> CompressorConfig {
+        CompressorConfig {
+            target_dbfs: -25.0,
+            dead_zone_db: 4.0,
+            hysteresis_db: 2.0,
+            attack_ms: 100.0,
+            release_ms: 2000.0,
+            max_slew_db_per_sec: 30.0,
+            duck_ratio: 1.0,
+            duck_max_db_per_sec: 0.0,
+            ramp: None,
+            silence_threshold_dbfs: -60.0,
+            silence_hold_sec: 5.0,
+            variance_window_sec: 0.0,
+            variance_threshold_db: 6.0,
+            reset_on_transition: false,
+            gap_hold_ms: 0.0,
+            hold_during_speech: false,
+            speech_only: false,
+            learned: None,
+            low_volume_compensation: 0.0,
+            regions: Vec::new(),
+            scenes: Vec::new(),
+            control_timescale: ControlTimescale::Window,
+            adaptive: None,
+            median: None,
+            recalibration_window_sec: 0.0,
+            rms_window_ms: 50.0,
+            sample_rate: 8000,
+            vol_min: 0.05,
+            vol_max: 0.95,
+        }
+    }
+
+    /// Feed `secs` of constant-level signal in one-window chunks.
+    pub(crate) fn feed_level(comp: &mut Compressor, dbfs: f32, secs: f32) -> Vec<ProcessResult> {
+        let chunk = comp.analyzer.window_samples;
+        let amplitude = 10.0_f32.powf(dbfs / 20.0);
+        let updates = (secs * 1000.0 / 50.0) as usize;
+        (0..updates)
+            .filter_map(|_| comp.process(&vec![amplitude; chunk]))
+            .collect()
+    }
 
     #[test]
     fn ring_buffer_rms_of_silence() {
@@ -296,6 +1107,41 @@ mod tests {
         assert!((ring.rms() - 0.5).abs() < 0.001);
     }
 
+    #[test]
+    fn fixed_point_rms_matches_float() {
+        use crate::fixed::{apply_gain_q15, downmix_i16_into, gain_q16, FixedRing};
+        use crate::mix::{apply_gain, downmix};
+
+        // A stereo i16 capture both ways: cpal's float conversion into
+        // `RingBuffer`, and the Q15 path into `FixedRing`
+        let mut rng = fastrand::Rng::with_seed(5);
+        for dbfs in [-6.0, -20.0, -40.0, -60.0] {
+            let amplitude = 10.0_f32.powf(dbfs / 20.0) * 32767.0;
+            let sine = (0..9600).map(|n| amplitude * ((n / 2) as f32 * 0.05).sin() * 1.414);
+            let noise = (0..9600).map(|_| amplitude * (rng.f32() * 2.0 - 1.0) * 1.732);
+            for data in [
+                sine.map(|s| s as i16).collect::<Vec<_>>(),
+                noise.map(|s| s as i16).collect(),
+            ] {
+                for gain in [1.0, 1.5] {
+                    let mut float = RingBuffer::new(2400);
+                    let mut mono = downmix(&data, 2);
+                    apply_gain(&mut mono, gain);
+                    float.extend(&mono);
+
+                    let mut fixed = FixedRing::new(2400);
+                    let mut q15 = Vec::new();
+                    downmix_i16_into(&data, 2, &mut q15);
+                    apply_gain_q15(&mut q15, gain_q16(gain));
+                    fixed.extend_q15(&q15);
+
+                    let error = rms_to_dbfs(fixed.rms()) - rms_to_dbfs(float.rms());
+                    assert!(error.abs() < 0.05, "{dbfs} dBFS x{gain}: off by {error} dB");
+                }
+            }
+        }
+    }
+
     #[test]
     fn ring_buffer_overwrites_old_samples() {
         let mut ring = RingBuffer::new(4);
@@ -344,6 +1190,67 @@ mod tests {
         assert!(env.value > -15.0);
     }
 
+    /// Updates a closed loop takes to correct a loudness step of `step_db`,
+    /// with the volume change heard immediately.
+    fn correction_updates(gc: &mut GainComputer, step_db: f32) -> usize {
+        let mut gain_db = 0.0;
+        let mut updates = 0;
+        loop {
+            let delta = gc.compute(-25.0 + step_db + gain_db);
+            if delta == 0.0 {
+                return updates;
+            }
+            gain_db += delta;
+            updates += 1;
+        }
+    }
+
+    #[test]
+    fn ramp_speeds_up_with_the_step() {
+        let ramp = RampConfig {
+            min_db_per_sec: 3.0,
+            max_db_per_sec: 30.0,
+            full_at_db: 20.0,
+        };
+        let ramped = || GainComputer::new(-25.0, 4.0, 2.0, 3.0, 20.0).with_ramp(Some(ramp));
+        let fixed = || GainComputer::new(-25.0, 4.0, 2.0, 3.0, 20.0);
+
+        // At the fixed rate, four times the correction takes ten times as long
+        let (small, large) = (
+            correction_updates(&mut fixed(), 6.0),
+            correction_updates(&mut fixed(), 24.0),
+        );
+        assert!(large >= small * 9, "{small} vs {large}");
+
+        // Ramped, the big step goes faster, so it takes barely twice as long
+        let (small, large) = (
+            correction_updates(&mut ramped(), 6.0),
+            correction_updates(&mut ramped(), 24.0),
+        );
+        assert!(large <= small * 2, "{small} vs {large}");
+        // Boosts ramp the same way
+        assert_eq!(correction_updates(&mut ramped(), -24.0), large);
+    }
+
+    #[test]
+    fn ramp_rate_is_continuous_and_bounded() {
+        let ramp = RampConfig {
+            min_db_per_sec: 3.0,
+            max_db_per_sec: 30.0,
+            full_at_db: 20.0,
+        };
+        assert_eq!(ramp.rate_db_per_sec(4.0, 4.0), 3.0);
+        assert_eq!(ramp.rate_db_per_sec(12.0, 4.0), 16.5);
+        assert_eq!(ramp.rate_db_per_sec(20.0, 4.0), 30.0);
+        assert_eq!(ramp.rate_db_per_sec(40.0, 4.0), 30.0);
+        let rates: Vec<f32> = (0..40)
+            .map(|s| ramp.rate_db_per_sec(s as f32, 4.0))
+            .collect();
+        assert!(rates
+            .windows(2)
+            .all(|w| w[1] >= w[0] && w[1] - w[0] <= 30.0 / 16.0));
+    }
+
     #[test]
     fn gain_computer_dead_zone() {
         let mut gc = GainComputer::new(-25.0, 4.0, 2.0, 30.0, 20.0);
@@ -356,6 +1263,24 @@ mod tests {
         assert!(delta > 0.0); // should increase volume
     }
 
+    #[test]
+    fn duck_depth_scales_with_overshoot() {
+        // 20 updates/s: 3 dB per update slew, ducks capped at 2 dB per update
+        let duck = |envelope: f32| {
+            let mut gc =
+                GainComputer::new(-25.0, 4.0, 2.0, 60.0, 20.0).with_ducking(0.5, 40.0, 20.0);
+            gc.compute(envelope)
+        };
+        // Loud threshold is -21: 1 dB over, 2 dB over, then well over
+        assert!((duck(-20.0) + 0.5).abs() < 1e-5);
+        assert!((duck(-19.0) + 1.0).abs() < 1e-5);
+        assert!(duck(-19.0) < duck(-20.0));
+        assert_eq!(duck(-5.0), -2.0);
+        // Boosts are untouched
+        let mut gc = GainComputer::new(-25.0, 4.0, 2.0, 60.0, 20.0).with_ducking(0.5, 40.0, 20.0);
+        assert_eq!(gc.compute(-40.0), 3.0);
+    }
+
     #[test]
     fn volume_state_applies_db_change() {
         let mut vs = VolumeState::new(0.5, 0.05, 0.95);
@@ -370,4 +1295,391 @@ mod tests {
         vs.apply_db_change(-40.0);
         assert!(vs.scalar >= 0.05);
     }
+
+    #[test]
+    fn variance_tracker_flags_alternating_levels() {
+        let mut vt = VarianceTracker::new(1.0, 3.0, 20.0);
+        let steady = (0..40).map(|_| vt.update(-25.0)).last().unwrap();
+        assert!(!steady);
+        let swinging = (0..40)
+            .map(|i| vt.update(if i % 2 == 0 { -40.0 } else { -10.0 }))
+            .last()
+            .unwrap();
+        assert!(swinging);
+    }
+
+    #[test]
+    fn volatile_content_reduces_adjustments() {
+        let total_adjustment_db = |variance_window_sec: f32| {
+            let mut config = test_config();
+            config.variance_window_sec = variance_window_sec;
+            let mut comp = Compressor::new(config, 0.5);
+            let mut total_db = 0.0;
+            for i in 0..20 {
+                let level = if i % 2 == 0 { -45.0 } else { -8.0 };
+                total_db += feed_level(&mut comp, level, 2.0)
+                    .iter()
+                    .map(|r| r.delta_db.abs())
+                    .sum::<f32>();
+            }
+            total_db
+        };
+
+        let normal = total_adjustment_db(0.0);
+        let adaptive = total_adjustment_db(10.0);
+        assert!(
+            adaptive < normal * 0.75,
+            "adaptive {adaptive} vs normal {normal}"
+        );
+    }
+
+    #[test]
+    fn transition_reset_stops_boosting_on_loud_onset() {
+        let boosts_after_onset = |reset_on_transition: bool| {
+            let mut config = test_config();
+            config.reset_on_transition = reset_on_transition;
+            let mut comp = Compressor::new(config, 0.3);
+            let quiet = feed_level(&mut comp, -45.0, 3.0);
+            assert!(quiet.iter().any(|r| r.delta_db > 0.0));
+            feed_level(&mut comp, -10.0, 1.0)
+                .iter()
+                .filter(|r| r.delta_db > 0.0)
+                .count()
+        };
+
+        assert!(boosts_after_onset(false) > 0);
+        assert_eq!(boosts_after_onset(true), 0);
+    }
+
+    #[test]
+    fn transition_reset_stops_cutting_once_quiet_persists() {
+        let cuts_after_drop = |reset_on_transition: bool| {
+            let mut config = test_config();
+            config.reset_on_transition = reset_on_transition;
+            let mut comp = Compressor::new(config, 0.8);
+            feed_level(&mut comp, -8.0, 3.0);
+            feed_level(&mut comp, -40.0, 2.0)
+                .iter()
+                .filter(|r| r.delta_db < 0.0)
+                .count()
+        };
+
+        assert!(cuts_after_drop(true) < cuts_after_drop(false));
+    }
+
+    #[test]
+    fn regions_replace_dead_zone_response() {
+        let mut config = test_config();
+        config.regions = crate::regions::tests::five_regions();
+        let mut comp = Compressor::new(config, 0.5);
+        feed_level(&mut comp, -25.0, 2.0);
+
+        // Inside "normal": hold
+        let normal = feed_level(&mut comp, -25.0, 1.0);
+        assert!(normal.iter().all(|r| r.delta_db == 0.0));
+
+        // "loud" ducks gently, "very loud" ducks hard
+        let loud = feed_level(&mut comp, -14.0, 3.0);
+        let very_loud = feed_level(&mut comp, -3.0, 1.0);
+        let last = |rs: &[ProcessResult]| rs.last().unwrap().delta_db;
+        assert!((last(&loud) + 8.0 / 20.0).abs() < 1e-4);
+        assert!(last(&very_loud) < last(&loud));
+    }
+
+    #[test]
+    fn adaptive_thresholds_replace_fixed_target() {
+        let mut config = test_config();
+        config.adaptive = Some(AdaptiveConfig {
+            window_sec: 4.0,
+            update_sec: 1.0,
+            quiet_percentile: 10.0,
+            loud_percentile: 90.0,
+        });
+        let mut comp = Compressor::new(config, 0.5);
+        // Settle the envelope, then a steady -40 program: the target follows it
+        feed_level(&mut comp, -40.0, 2.0);
+        let results = feed_level(&mut comp, -40.0, 4.0);
+        let last = results.last().unwrap();
+        assert!(
+            (last.target_dbfs + 40.0).abs() < 1.0,
+            "{}",
+            last.target_dbfs
+        );
+        assert!(last.thresholds.quiet_dbfs < -40.0 && last.thresholds.loud_dbfs > -40.0);
+        // Inside its own thresholds, so it holds
+        assert_eq!(last.delta_db, 0.0);
+    }
+
+    #[test]
+    fn speech_holds_the_volume_and_music_does_not() {
+        use crate::speech::tests::{music_like, speech_like};
+
+        // Near the target long enough to be recognized, then much quieter
+        let volumes = |signal: fn(f32, f32) -> Vec<f32>| {
+            let mut config = test_config();
+            config.hold_during_speech = true;
+            let mut comp = Compressor::new(config, 0.5);
+            let mut samples = signal(2.0, 0.08);
+            samples.extend(signal(3.0, 0.01));
+            samples
+                .chunks(comp.analyzer.window_samples)
+                .filter_map(|chunk| comp.process(chunk))
+                .map(|r| r.volume)
+                .skip(40)
+                .collect::<Vec<_>>()
+        };
+        let speech = volumes(speech_like);
+        assert!(speech.iter().all(|&v| v == speech[0]), "{speech:?}");
+        assert!(speech[0] < 0.9);
+        let music = volumes(music_like);
+        assert!(music.last().unwrap() > &(music[0] + 0.1), "{music:?}");
+    }
+
+    #[test]
+    fn learned_offsets_follow_the_content() {
+        use crate::speech::tests::{music_like, speech_like};
+
+        let mut learned = LearnedTargets::default();
+        learned.nudge(ContentType::Speech, 5.0);
+        learned.nudge(ContentType::Music, -3.0);
+        let last_target = |comp: &mut Compressor, samples: Vec<f32>| {
+            samples
+                .chunks(comp.analyzer.window_samples)
+                .filter_map(|chunk| comp.process(chunk))
+                .last()
+                .unwrap()
+                .target_dbfs
+        };
+        let mut config = test_config();
+        config.learned = Some(learned.clone());
+        let mut comp = Compressor::new(config, 0.5);
+        assert_eq!(last_target(&mut comp, speech_like(12.0, 0.08)), -20.0);
+        assert_eq!(comp.content_type(), Some(ContentType::Speech));
+        assert_eq!(last_target(&mut comp, music_like(12.0, 0.08)), -28.0);
+
+        // A nudge elsewhere takes effect on the next update
+        learned.nudge(ContentType::Music, 1.0);
+        comp.set_learned(learned);
+        assert_eq!(last_target(&mut comp, music_like(1.0, 0.08)), -27.0);
+
+        // A long pause after speech doesn't turn it into music
+        assert_eq!(last_target(&mut comp, speech_like(12.0, 0.08)), -20.0);
+        last_target(&mut comp, vec![0.0; 8000 * 60]);
+        assert_eq!(comp.content_type(), Some(ContentType::Speech));
+    }
+
+    #[test]
+    fn median_thresholds_ride_out_spikes() {
+        let mut config = test_config();
+        config.median = Some(MedianConfig {
+            window_sec: 20.0,
+            above_db: 4.0,
+            below_db: 4.0,
+        });
+        let mut comp = Compressor::new(config, 0.5);
+        feed_level(&mut comp, -40.0, 12.0);
+        // A second of something loud is an outlier, not the new normal
+        feed_level(&mut comp, -10.0, 1.0);
+        let results = feed_level(&mut comp, -40.0, 8.0);
+        let last = results.last().unwrap();
+        assert!(
+            (last.target_dbfs + 40.0).abs() < 1.0,
+            "{}",
+            last.target_dbfs
+        );
+        assert_eq!(last.delta_db, 0.0);
+    }
+
+    #[test]
+    fn saturates_at_the_bound_and_recovers() {
+        let mut comp = Compressor::new(test_config(), 0.5);
+        // Far too quiet: boosts until vol_max, then keeps wanting more
+        let quiet = feed_level(&mut comp, -50.0, 5.0);
+        let first = quiet.iter().position(|r| r.saturated.is_some()).unwrap();
+        assert_eq!(quiet[first].volume, 0.95);
+        assert!(quiet[first..]
+            .iter()
+            .all(|r| r.saturated == Some(Saturation::Max) && r.volume == 0.95));
+
+        // Content on target no longer pushes
+        let on_target = feed_level(&mut comp, -25.0, 3.0);
+        assert_eq!(on_target.last().unwrap().saturated, None);
+    }
+
+    #[test]
+    fn scene_target_takes_over() {
+        let mut config = test_config();
+        config.scenes =
+            serde_json::from_str(r#"[{"name": "steady", "dynamics": [0, 3], "target": -35}]"#)
+                .unwrap();
+        let mut comp = Compressor::new(config, 0.5);
+        let results = feed_level(&mut comp, -25.0, 5.0);
+        assert_eq!(results[0].scene, None);
+        assert_eq!(results[0].target_dbfs, -25.0);
+        let last = results.last().unwrap();
+        assert_eq!(last.scene, Some(0));
+        assert_eq!(last.target_dbfs, -35.0);
+        assert_eq!(comp.scene_name(0), "steady");
+    }
+
+    #[test]
+    fn warm_up_starts_from_fresh_audio() {
+        let mut comp = Compressor::new(test_config(), 0.5);
+        feed_level(&mut comp, -80.0, 10.0);
+
+        comp.warm_up();
+        // Half a window of audio: still warming
+        let half = vec![0.1; comp.analyzer.window_samples / 2];
+        assert!(comp.process(&half).is_none());
+        let first = comp.process(&half).unwrap();
+        // At the audio's level, not released up from silence
+        assert!((first.envelope_dbfs + 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn recalibrate_follows_recent_content() {
+        let mut config = test_config();
+        config.recalibration_window_sec = 10.0;
+        let provider = PushedTarget::new(-25.0);
+        let mut comp = Compressor::with_target(config, 0.5, Box::new(provider.clone()));
+        assert!(comp.recalibrate().is_none(), "nothing heard yet");
+
+        // Content at -35 dBFS at the starting volume, heard through
+        // whatever volume the leveler has set
+        let mut volume: f32 = 0.5;
+        let mut play = |comp: &mut Compressor, secs: f32| {
+            let mut last = None;
+            for _ in 0..(secs * 20.0) as usize {
+                let dbfs = -35.0 + 20.0 * (volume / 0.5).log10();
+                let r = comp.process(&[10.0_f32.powf(dbfs / 20.0); 400]).unwrap();
+                volume = r.volume;
+                last = Some(r);
+            }
+            last.unwrap()
+        };
+        let heard = play(&mut comp, 10.0);
+        // Turned up: the mic hears the leveler's work, not the content
+        assert!(heard.envelope_dbfs > -31.0, "{}", heard.envelope_dbfs);
+        let r = comp.recalibrate().unwrap();
+        assert_eq!((r.old_target_dbfs, r.old_dead_zone_db), (-25.0, 4.0));
+        assert!((r.target_dbfs + 35.0).abs() < 1.0, "{}", r.target_dbfs);
+        assert_eq!(r.dead_zone_db, 2.0);
+
+        // New target sticks for later updates, as an offset on the provider's
+        assert_eq!(play(&mut comp, 1.0).target_dbfs, r.target_dbfs);
+        provider.set(-20.0);
+        assert_eq!(play(&mut comp, 0.1).target_dbfs, r.target_dbfs + 5.0);
+    }
+
+    #[test]
+    fn batching_does_not_change_analysis() {
+        // Alternating quiet and loud half-seconds, 4s at 8kHz
+        let signal: Vec<f32> = (0..32_000)
+            .map(|i| {
+                let level = if (i / 4000) % 2 == 0 { 0.02 } else { 0.4 };
+                level * (i as f32 * 0.05).sin()
+            })
+            .collect();
+
+        let run = |chunk: usize| {
+            let mut comp = Compressor::new(test_config(), 0.5);
+            let mut last = None;
+            for c in signal.chunks(chunk) {
+                last = comp.process(c).or(last);
+            }
+            last.unwrap()
+        };
+        let small = run(64);
+        for chunk in [400, 1000, 1600, 32_000] {
+            let batched = run(chunk);
+            assert_eq!(
+                batched.envelope_dbfs.to_bits(),
+                small.envelope_dbfs.to_bits()
+            );
+            assert_eq!(batched.volume.to_bits(), small.volume.to_bits());
+            assert_eq!(
+                batched.loudness.momentary.to_bits(),
+                small.loudness.momentary.to_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn gap_hold_prevents_boost_before_loud_content() {
+        let run = |gap_hold_ms: f32| {
+            let mut config = test_config();
+            config.gap_hold_ms = gap_hold_ms;
+            let mut comp = Compressor::new(config, 0.5);
+            feed_level(&mut comp, -25.0, 3.0);
+            let mut after = feed_level(&mut comp, -80.0, 0.4);
+            after.extend(feed_level(&mut comp, -10.0, 0.5));
+            after
+        };
+
+        let boosted = run(0.0).iter().any(|r| r.delta_db > 0.0);
+        assert!(boosted, "the gap alone should look like quiet content");
+
+        let held = run(1000.0);
+        assert!(held.iter().all(|r| r.delta_db <= 0.0));
+        // The loud content after the gap is still cut
+        assert!(held.iter().any(|r| r.delta_db < 0.0));
+    }
+
+    #[test]
+    fn low_volume_boost_follows_the_contour() {
+        assert_eq!(low_volume_boost(1.0, 1.0), 1.0);
+        assert_eq!(low_volume_boost(0.1, 0.0), 1.0);
+        // 0.1 is 60 phon: 8.4 dB more bass loss than at full volume
+        assert!((low_volume_boost(0.1, 1.0) - (1.0 + 8.4 / 20.0)).abs() < 1e-4);
+        // Bottoms out at the quietest contour
+        assert_eq!(low_volume_boost(0.0001, 1.0), low_volume_boost(0.001, 1.0));
+        assert!(low_volume_boost(0.1, 2.0) > low_volume_boost(0.1, 1.0));
+    }
+
+    #[test]
+    fn compensation_boosts_more_at_low_volume() {
+        let first_boost = |initial: f32, strength: f32| {
+            let mut config = test_config();
+            // Envelope jumps straight to the level: no start-up boost
+            config.attack_ms = 1.0;
+            config.low_volume_compensation = strength;
+            let mut comp = Compressor::new(config, initial);
+            feed_level(&mut comp, -25.0, 1.0);
+            feed_level(&mut comp, -31.0, 5.0)
+                .into_iter()
+                .map(|r| r.delta_db)
+                .find(|&d| d > 0.0)
+                .unwrap()
+        };
+
+        assert_eq!(first_boost(0.8, 0.0), first_boost(0.1, 0.0));
+        let (loud, mid, quiet) = (
+            first_boost(0.8, 1.0),
+            first_boost(0.3, 1.0),
+            first_boost(0.1, 1.0),
+        );
+        assert!(loud < mid && mid < quiet, "{loud} {mid} {quiet}");
+    }
+
+    #[test]
+    fn compensation_keeps_to_the_ramp() {
+        let mut config = test_config();
+        config.attack_ms = 1.0;
+        config.low_volume_compensation = 2.0;
+        // Well under --max-slew: 0.3 dB an update at 20 Hz
+        config.ramp = Some(RampConfig {
+            min_db_per_sec: 6.0,
+            max_db_per_sec: 6.0,
+            full_at_db: 20.0,
+        });
+        let mut comp = Compressor::new(config, 0.1);
+        feed_level(&mut comp, -25.0, 1.0);
+        let boosts: Vec<f32> = feed_level(&mut comp, -40.0, 2.0)
+            .into_iter()
+            .map(|r| r.delta_db)
+            .filter(|&d| d > 0.0)
+            .collect();
+        assert!(!boosts.is_empty());
+        assert!(boosts.iter().all(|&d| d <= 0.3 + 1e-4), "{boosts:?}");
+    }
 }
@@ -0,0 +1,72 @@
+use tokio::sync::broadcast;
+
+use crate::loudness::Loudness;
+
+/// Something the main loop did or saw, for anyone who wants to watch.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// One analysis update from the compressor
+    Reading {
+        envelope_dbfs: f32,
+        /// Envelope for readouts, after --display-smoothing
+        display_dbfs: f32,
+        target_dbfs: f32,
+        delta_db: f32,
+        volume: f32,
+        silent: bool,
+        /// Momentary, short-term and integrated LUFS
+        loudness: Loudness,
+        /// `envelope_dbfs` on the 0-100 score scale
+        score: u8,
+    },
+    /// The controller accepted a volume
+    Sent { volume: f32 },
+    /// A send failed (HTTP status or transport error)
+    SendFailed { error: String },
+    /// No send for --settle-time
+    Settled,
+}
+
+/// Fan-out of events to any number of subscribers. Publishing never blocks;
+/// slow subscribers miss old events rather than holding up the audio path.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // No subscribers is fine
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribers_see_published_events() {
+        let bus = EventBus::new(8);
+        let mut rx = bus.subscribe();
+        bus.publish(Event::Sent { volume: 0.4 });
+        assert!(matches!(rx.try_recv(), Ok(Event::Sent { volume }) if volume == 0.4));
+    }
+
+    #[test]
+    fn publish_without_subscribers_is_ok() {
+        let bus = EventBus::new(8);
+        bus.publish(Event::SendFailed {
+            error: "timeout".into(),
+        });
+    }
+}
@@ -0,0 +1,141 @@
+//! Volumes for shell scripts, written to a named pipe as they're set.
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use crate::sink::{SinkFuture, VolumeSink};
+
+/// Writes "<zone> <volume>" lines to a FIFO, e.g. for `while read zone vol`.
+/// Never blocks: with no reader attached, or a reader that isn't keeping up,
+/// lines are dropped rather than held.
+pub struct FifoSink {
+    path: PathBuf,
+    zone: String,
+    name: String,
+    /// Open while a reader is attached
+    pipe: Mutex<Option<File>>,
+}
+
+impl FifoSink {
+    /// Creates the FIFO if nothing exists at `path`.
+    pub fn new(path: &Path, zone: &str) -> Result<Self> {
+        create(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            zone: zone.to_string(),
+            name: format!("fifo:{}", path.display()),
+            pipe: Mutex::new(None),
+        })
+    }
+
+    fn write(&self, volume: f32) {
+        let mut pipe = self.pipe.lock().unwrap();
+        if pipe.is_none() {
+            // Fails with ENXIO until a reader opens the other end
+            *pipe = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+                .ok();
+        }
+        let Some(file) = pipe.as_mut() else {
+            return;
+        };
+        let line = format!("{} {volume:.4}\n", self.zone);
+        match file.write_all(line.as_bytes()) {
+            Ok(()) => {}
+            // Reader is behind; it'll get the next one
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            // Reader went away (EPIPE): reopen for the next one
+            Err(_) => *pipe = None,
+        }
+    }
+}
+
+impl VolumeSink for FifoSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+        self.write(volume);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn create(path: &Path) -> Result<()> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => Ok(()),
+        Ok(_) => Err(anyhow!("{} exists and is not a FIFO", path.display())),
+        Err(_) => {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                return Err(anyhow!(
+                    "Cannot create FIFO {}: {}",
+                    path.display(),
+                    std::io::Error::last_os_error()
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn open_reader(path: &Path) -> File {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    fn read_all(reader: &mut File) -> String {
+        let mut buf = [0u8; 256];
+        let n = reader.read(&mut buf).unwrap_or(0);
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    }
+
+    #[tokio::test]
+    async fn readers_get_lines_and_can_come_and_go() {
+        let path = std::env::temp_dir().join(format!("audilator-fifo-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = FifoSink::new(&path, "living").unwrap();
+        assert!(std::fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        // Nobody listening: dropped, not blocked
+        sink.set_volume(0.3).await.unwrap();
+
+        let mut reader = open_reader(&path);
+        sink.set_volume(0.45).await.unwrap();
+        assert_eq!(read_all(&mut reader), "living 0.4500\n");
+
+        // Reader leaves, then a new one arrives
+        drop(reader);
+        sink.set_volume(0.5).await.unwrap();
+        let mut reader = open_reader(&path);
+        sink.set_volume(0.55).await.unwrap();
+        assert_eq!(read_all(&mut reader), "living 0.5500\n");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_regular_file() {
+        let path = std::env::temp_dir().join(format!("audilator-notfifo-{}", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        assert!(FifoSink::new(&path, "living").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Integer capture and RMS for CPUs without a fast FPU (`--features
+//! fixed-point`). A 16-bit device captured mono stays Q15 (full scale =
+//! 32768) from the callback to the RMS window: the downmix, `--input-gain`
+//! and the window's sum of squares are integer. Floats come in once per
+//! window to read the RMS, and where the float filters after the window
+//! (loudness weighting, speech, the preprocess chain) need the samples.
+
+use std::collections::VecDeque;
+
+const FULL_SCALE: f32 = 32768.0;
+/// `--input-gain` as Q16: 65536 is unity
+const GAIN_ONE: i64 = 1 << 16;
+
+/// `dsp::RingBuffer` with an exact integer sum of squares. A full-scale
+/// square is under 2^31, so u64 holds windows of billions of samples and
+/// nothing drifts.
+pub struct FixedRing {
+    buf: VecDeque<i16>,
+    capacity: usize,
+    sum_squares: u64,
+}
+
+impl FixedRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            sum_squares: 0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.buf.len() >= self.capacity
+    }
+
+    /// Float sources on a fixed-point build: quantized on the way in.
+    pub fn extend(&mut self, samples: &[f32]) {
+        for &s in samples {
+            // Saturating cast: out of range clips, NaN is silence
+            self.push((s * FULL_SCALE).round() as i16);
+        }
+    }
+
+    pub fn extend_q15(&mut self, samples: &[i16]) {
+        for &q in samples {
+            self.push(q);
+        }
+    }
+
+    fn push(&mut self, q: i16) {
+        if self.buf.len() >= self.capacity {
+            if let Some(old) = self.buf.pop_front() {
+                self.sum_squares -= square(old);
+            }
+        }
+        self.sum_squares += square(q);
+        self.buf.push_back(q);
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.buf.is_empty() {
+            return 0.0;
+        }
+        ((self.sum_squares as f64 / self.buf.len() as f64).sqrt() as f32) / FULL_SCALE
+    }
+}
+
+fn square(q: i16) -> u64 {
+    let q = q as i64;
+    (q * q) as u64
+}
+
+/// `mix::downmix_into` in integers: frames are averaged as i32, and there
+/// is nothing to sanitize. Averages round half to even: truncating, or
+/// rounding a stereo pair's half-LSB ties one way, biases quiet levels.
+pub fn downmix_i16_into(data: &[i16], channels: usize, out: &mut Vec<i16>) {
+    match channels.max(1) {
+        1 => out.extend_from_slice(data),
+        // The common layout, without the division: floor, plus the tie's
+        // odd-to-even step
+        2 => out.extend(data.chunks_exact(2).map(|frame| {
+            let sum = frame[0] as i32 + frame[1] as i32;
+            let q = sum >> 1;
+            (q + (sum & q & 1)) as i16
+        })),
+        channels => {
+            let n = channels as i32;
+            out.extend(data.chunks_exact(channels).map(|frame| {
+                let sum: i32 = frame.iter().map(|&s| s as i32).sum();
+                let (q, r) = (sum.div_euclid(n), sum.rem_euclid(n));
+                let up = 2 * r > n || (2 * r == n && q % 2 != 0);
+                (q + up as i32) as i16
+            }))
+        }
+    }
+}
+
+/// A linear gain as Q16, converted once when the stream opens.
+pub fn gain_q16(gain: f32) -> i64 {
+    (gain as f64 * GAIN_ONE as f64).round() as i64
+}
+
+/// `mix::apply_gain` on Q15 samples, clipping at full scale.
+pub fn apply_gain_q15(samples: &mut [i16], gain_q16: i64) {
+    if gain_q16 == GAIN_ONE {
+        return;
+    }
+    for s in samples {
+        *s = ((*s as i64 * gain_q16) >> 16).clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+    }
+}
+
+/// The float copy for whatever reads a Q15 batch after the RMS window.
+pub fn to_f32(samples: &[i16]) -> Vec<f32> {
+    samples.iter().map(|&q| q as f32 / FULL_SCALE).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rms_of_dc_and_silence() {
+        let mut ring = FixedRing::new(100);
+        assert_eq!(ring.rms(), 0.0);
+        ring.extend_q15(&[16384; 100]);
+        assert!(ring.is_full());
+        assert!((ring.rms() - 0.5).abs() < 1e-4);
+        ring.extend_q15(&[0; 100]);
+        assert_eq!(ring.rms(), 0.0);
+    }
+
+    #[test]
+    fn out_of_range_float_samples_clip() {
+        let mut ring = FixedRing::new(3);
+        ring.extend(&[2.0, -2.0, f32::NAN]);
+        let expected = (2.0f32 / 3.0).sqrt();
+        assert!((ring.rms() - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn integer_downmix_averages_frames() {
+        let mut out = Vec::new();
+        downmix_i16_into(
+            &[16384, 0, -16384, -16384, 3, 0, -3, 0, 5, 0, 7],
+            2,
+            &mut out,
+        );
+        assert_eq!(out, vec![8192, -16384, 2, -2, 2]);
+        downmix_i16_into(&[5, -5], 0, &mut out);
+        assert_eq!(out, vec![8192, -16384, 2, -2, 2, 5, -5]);
+        out.clear();
+        downmix_i16_into(&[3, 0, 0, -3, 0, 0, 4, 0, 0, 7], 3, &mut out);
+        assert_eq!(out, vec![1, -1, 1]);
+    }
+
+    #[test]
+    fn integer_gain_scales_and_clips() {
+        let mut samples = [1000, -3000, 20000];
+        apply_gain_q15(&mut samples, gain_q16(2.0));
+        assert_eq!(samples, [2000, -6000, i16::MAX]);
+        apply_gain_q15(&mut samples, gain_q16(0.5));
+        assert_eq!(samples, [1000, -3000, 16383]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Input gain staging: is the mic level in a range the leveler works well in?
+//!
+//! Three numbers decide it. Headroom is how far peaks sit below full scale;
+//! the noise floor is the quiet between sounds; the typical level is the
+//! median of windows clearly above that floor. `--input-gain` should put the
+//! typical level near `--target` without eating the headroom or lifting the
+//! floor over `--silence-threshold`.
+
+use crate::dsp::{rms_to_dbfs, RingBuffer};
+
+/// Peaks this close to full scale are clipping.
+const CLIP_DBFS: f32 = -0.5;
+/// Headroom to keep above the loudest peak.
+const MIN_HEADROOM_DB: f32 = 6.0;
+/// How far below --silence-threshold the floor should stay.
+const FLOOR_MARGIN_DB: f32 = 6.0;
+/// Windows this far over the floor count as signal.
+const SIGNAL_OVER_FLOOR_DB: f32 = 10.0;
+/// Closer to target than this needs no change.
+const TOLERANCE_DB: f32 = 3.0;
+
+/// What was measured, after the current --input-gain.
+#[derive(Debug, Clone, Copy)]
+pub struct Levels {
+    pub peak_dbfs: f32,
+    pub floor_dbfs: f32,
+    /// None if nothing rose clearly above the floor
+    pub typical_dbfs: Option<f32>,
+}
+
+impl Levels {
+    pub fn headroom_db(&self) -> f32 {
+        -self.peak_dbfs
+    }
+}
+
+/// Collects peak and window RMS levels from captured audio.
+pub struct GainMeter {
+    ring: RingBuffer,
+    window_samples: usize,
+    samples_since_rms: usize,
+    peak: f32,
+    windows: Vec<f32>,
+}
+
+impl GainMeter {
+    pub fn new(window_samples: usize) -> Self {
+        Self {
+            ring: RingBuffer::new(window_samples),
+            window_samples,
+            samples_since_rms: 0,
+            peak: 0.0,
+            windows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        self.peak = samples.iter().fold(self.peak, |p, s| p.max(s.abs()));
+        self.ring.extend(samples);
+        self.samples_since_rms += samples.len();
+        if self.samples_since_rms >= self.window_samples && self.ring.is_full() {
+            self.samples_since_rms = 0;
+            self.windows.push(rms_to_dbfs(self.ring.rms()));
+        }
+    }
+
+    /// None until a full window has been heard.
+    pub fn levels(&self) -> Option<Levels> {
+        if self.windows.is_empty() {
+            return None;
+        }
+        let mut sorted = self.windows.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let floor_dbfs = sorted[sorted.len() / 10];
+        let signal: Vec<f32> = sorted
+            .into_iter()
+            .filter(|&l| l >= floor_dbfs + SIGNAL_OVER_FLOOR_DB)
+            .collect();
+        Some(Levels {
+            peak_dbfs: rms_to_dbfs(self.peak),
+            floor_dbfs,
+            typical_dbfs: signal.get(signal.len() / 2).copied(),
+        })
+    }
+}
+
+/// What to do about --input-gain.
+#[derive(Debug, PartialEq)]
+pub enum Advice {
+    /// Nothing but the floor was heard
+    NoSignal,
+    /// Peaks clip before --input-gain applies; only the mic or OS input
+    /// level can fix that
+    Clipping,
+    Keep,
+    Change {
+        input_gain_db: f32,
+        /// Set by headroom or the floor rather than the target
+        limited: Option<Limit>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Limit {
+    /// Peaks would come within MIN_HEADROOM_DB of full scale
+    Headroom,
+    /// The room's quiet would reach --silence-threshold
+    NoiseFloor,
+}
+
+/// Advice for `levels` measured at `input_gain_db`.
+pub fn advise(
+    levels: &Levels,
+    input_gain_db: f32,
+    target_dbfs: f32,
+    silence_threshold_dbfs: f32,
+) -> Advice {
+    // With a boost applied the clipping may be ours; without, it's upstream
+    if input_gain_db <= 0.0 && levels.peak_dbfs - input_gain_db >= CLIP_DBFS {
+        return Advice::Clipping;
+    }
+    let Some(typical) = levels.typical_dbfs else {
+        return Advice::NoSignal;
+    };
+
+    let wanted = target_dbfs - typical;
+    let headroom_cap = -MIN_HEADROOM_DB - levels.peak_dbfs;
+    let floor_cap = silence_threshold_dbfs - FLOOR_MARGIN_DB - levels.floor_dbfs;
+    let (change, limited) = if wanted <= headroom_cap.min(floor_cap) {
+        if wanted.abs() < TOLERANCE_DB {
+            return Advice::Keep;
+        }
+        (wanted, None)
+    } else if headroom_cap <= floor_cap {
+        (headroom_cap, Some(Limit::Headroom))
+    } else {
+        (floor_cap, Some(Limit::NoiseFloor))
+    };
+
+    // Half-dB steps read better than false precision
+    let suggested = ((input_gain_db + change) * 2.0).round() / 2.0;
+    if suggested == input_gain_db {
+        return Advice::Keep;
+    }
+    Advice::Change {
+        input_gain_db: suggested,
+        limited,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(peak: f32, floor: f32, typical: f32) -> Levels {
+        Levels {
+            peak_dbfs: peak,
+            floor_dbfs: floor,
+            typical_dbfs: Some(typical),
+        }
+    }
+
+    #[test]
+    fn quiet_input_is_raised_to_target() {
+        let advice = advise(&levels(-25.0, -85.0, -40.0), 0.0, -25.0, -60.0);
+        assert_eq!(
+            advice,
+            Advice::Change {
+                input_gain_db: 15.0,
+                limited: None
+            }
+        );
+    }
+
+    #[test]
+    fn hot_input_is_lowered() {
+        let advice = advise(&levels(-3.0, -70.0, -15.0), 6.0, -25.0, -60.0);
+        assert_eq!(
+            advice,
+            Advice::Change {
+                input_gain_db: -4.0,
+                limited: None
+            }
+        );
+    }
+
+    #[test]
+    fn close_enough_is_kept() {
+        assert_eq!(
+            advise(&levels(-12.0, -70.0, -26.5), 0.0, -25.0, -60.0),
+            Advice::Keep
+        );
+    }
+
+    #[test]
+    fn raising_stops_short_of_the_headroom() {
+        // Wants +15, but peaks at -15 leave only +9
+        let advice = advise(&levels(-15.0, -80.0, -40.0), 0.0, -25.0, -60.0);
+        assert_eq!(
+            advice,
+            Advice::Change {
+                input_gain_db: 9.0,
+                limited: Some(Limit::Headroom)
+            }
+        );
+    }
+
+    #[test]
+    fn raising_stops_short_of_the_silence_threshold() {
+        // Wants +15, but a -70 floor may only come up to -66
+        let advice = advise(&levels(-30.0, -70.0, -40.0), 0.0, -25.0, -60.0);
+        assert_eq!(
+            advice,
+            Advice::Change {
+                input_gain_db: 4.0,
+                limited: Some(Limit::NoiseFloor)
+            }
+        );
+    }
+
+    #[test]
+    fn hardware_clipping_needs_the_mic_turned_down() {
+        assert_eq!(
+            advise(&levels(-0.1, -60.0, -12.0), 0.0, -25.0, -60.0),
+            Advice::Clipping
+        );
+        // Clipping from our own boost is fixed by less of it
+        assert!(matches!(
+            advise(&levels(-0.1, -60.0, -12.0), 10.0, -25.0, -60.0),
+            Advice::Change { input_gain_db, .. } if input_gain_db < 10.0
+        ));
+    }
+
+    #[test]
+    fn floor_only_is_no_signal() {
+        let flat = Levels {
+            peak_dbfs: -68.0,
+            floor_dbfs: -70.0,
+            typical_dbfs: None,
+        };
+        assert_eq!(advise(&flat, 0.0, -25.0, -60.0), Advice::NoSignal);
+    }
+
+    #[test]
+    fn meter_separates_floor_and_signal() {
+        let mut meter = GainMeter::new(400);
+        // Half quiet room (about -70 dBFS), half content (about -20 dBFS)
+        for i in 0..100 {
+            let level = if i % 2 == 0 { 0.0003 } else { 0.1 };
+            meter.push(&[level; 400]);
+        }
+        let l = meter.levels().unwrap();
+        assert!((l.peak_dbfs + 20.0).abs() < 0.1);
+        assert!((l.floor_dbfs + 70.5).abs() < 0.5);
+        assert!((l.typical_dbfs.unwrap() + 20.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::register;
+use crate::transition::Transition;
+
+/// Proof of life POSTed to `--heartbeat-url`, independent of volume changes.
+#[derive(Serialize)]
+pub struct Beat {
+    pub hostname: String,
+    pub uptime_sec: u64,
+}
+
+impl Beat {
+    pub fn new(started: Instant, now: Instant) -> Self {
+        Self {
+            hostname: register::hostname(),
+            uptime_sec: now.duration_since(started).as_secs(),
+        }
+    }
+}
+
+/// When the next heartbeat is due. The first fires straight away; missed
+/// beats (a stalled loop) are skipped rather than sent in a burst.
+pub struct HeartbeatTimer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl HeartbeatTimer {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            next: now,
+        }
+    }
+
+    pub fn due(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next += self.interval;
+        if self.next <= now {
+            self.next = now + self.interval;
+        }
+        true
+    }
+}
+
+/// What a failed heartbeat does, beyond the warning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum FailurePolicy {
+    /// Nothing more: heartbeats are informational
+    Warn,
+    /// Mark every zone unhealthy until its next successful send
+    Unhealthy,
+    /// Also hold all sends until a heartbeat gets through again
+    Hold,
+}
+
+/// Whether heartbeats are getting through, under a `FailurePolicy`.
+pub struct BeatHealth {
+    policy: FailurePolicy,
+    failing: bool,
+}
+
+impl BeatHealth {
+    pub fn new(policy: FailurePolicy) -> Self {
+        Self {
+            policy,
+            failing: false,
+        }
+    }
+
+    /// Count a heartbeat's outcome. Returns the change in `holds`, if any.
+    pub fn record(&mut self, ok: bool) -> Option<Transition> {
+        let was = self.holds();
+        self.failing = !ok;
+        match (was, self.holds()) {
+            (false, true) => Some(Transition::Engaged),
+            (true, false) => Some(Transition::Released),
+            _ => None,
+        }
+    }
+
+    /// Zones count as unhealthy after a failure.
+    pub fn marks_unhealthy(&self) -> bool {
+        self.policy != FailurePolicy::Warn
+    }
+
+    /// Sends are held until heartbeats recover.
+    pub fn holds(&self) -> bool {
+        self.failing && self.policy == FailurePolicy::Hold
+    }
+}
+
+pub async fn send(client: &reqwest::Client, url: &str, beat: &Beat) -> Result<()> {
+    let resp = client.post(url).json(beat).send().await?;
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", resp.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::MockServer;
+
+    #[test]
+    fn fires_at_the_interval() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(5);
+        let mut timer = HeartbeatTimer::new(interval, start);
+
+        let fired: Vec<u64> = (0..=20)
+            .map(|s| start + Duration::from_secs(s))
+            .filter(|&now| timer.due(now))
+            .map(|now| now.duration_since(start).as_secs())
+            .collect();
+        assert_eq!(fired, vec![0, 5, 10, 15, 20]);
+    }
+
+    #[test]
+    fn stall_does_not_burst() {
+        let start = Instant::now();
+        let mut timer = HeartbeatTimer::new(Duration::from_secs(5), start);
+        assert!(timer.due(start));
+        // Loop blocked for 30s: one beat, then back on a 5s cadence
+        assert!(timer.due(start + Duration::from_secs(30)));
+        assert!(!timer.due(start + Duration::from_secs(31)));
+        assert!(timer.due(start + Duration::from_secs(35)));
+    }
+
+    #[test]
+    fn hold_policy_holds_until_a_beat_gets_through() {
+        let mut health = BeatHealth::new(FailurePolicy::Hold);
+        assert_eq!(health.record(true), None);
+        assert_eq!(health.record(false), Some(Transition::Engaged));
+        assert_eq!(health.record(false), None);
+        assert!(health.holds() && health.marks_unhealthy());
+        assert_eq!(health.record(true), Some(Transition::Released));
+
+        let mut health = BeatHealth::new(FailurePolicy::Warn);
+        assert_eq!(health.record(false), None);
+        assert!(!health.holds() && !health.marks_unhealthy());
+    }
+
+    #[tokio::test]
+    async fn posts_the_beat() {
+        let server = MockServer::start(200).await;
+        let client = reqwest::Client::new();
+        let start = Instant::now();
+        let beat = Beat::new(start, start + Duration::from_secs(90));
+        send(&client, &server.url("/heartbeat"), &beat)
+            .await
+            .unwrap();
+
+        let req = &server.requests()[0];
+        assert_eq!(
+            (req.method.as_str(), req.path.as_str()),
+            ("POST", "/heartbeat")
+        );
+        let body: serde_json::Value = serde_json::from_str(&req.body).unwrap();
+        assert_eq!(body["uptime_sec"], 90);
+    }
+
+    #[tokio::test]
+    async fn rejection_is_a_failure() {
+        let server = MockServer::start(503).await;
+        let beat = Beat::new(Instant::now(), Instant::now());
+        let err = send(&reqwest::Client::new(), &server.url("/"), &beat).await;
+        assert!(err.unwrap_err().to_string().contains("503"));
+    }
+}
@@ -0,0 +1,77 @@
+//! Rebuilding captures after their device goes away. Unplugging a USB mic,
+//! or a hub re-enumerating, fires a burst of stream errors over a second or
+//! so; rebuilding on each would reopen a device that's still coming and
+//! going. Events are coalesced per capture until none has arrived for the
+//! settle time, then acted on once.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SETTLE_MS: u64 = 1000;
+
+pub struct Debouncer {
+    settle: Duration,
+    /// Capture index -> its latest event
+    pending: BTreeMap<usize, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            settle,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Something happened to capture `key`; its wait starts over.
+    pub fn event(&mut self, key: usize, now: Instant) {
+        self.pending.insert(key, now);
+    }
+
+    /// Captures whose events have settled, each once.
+    pub fn due(&mut self, now: Instant) -> Vec<usize> {
+        let due: Vec<usize> = self
+            .pending
+            .iter()
+            .filter(|(_, &last)| now.duration_since(last) >= self.settle)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in &due {
+            self.pending.remove(key);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_burst_of_events_is_one_rebuild() {
+        let mut d = Debouncer::new(Duration::from_millis(500));
+        let t0 = Instant::now();
+        let mut rebuilds = Vec::new();
+        // Ten errors 100ms apart, checked as often as the main loop does
+        for tick in 0..30 {
+            let now = t0 + Duration::from_millis(tick * 100);
+            if tick < 10 {
+                d.event(0, now);
+            }
+            rebuilds.extend(d.due(now).into_iter().map(|k| (k, tick)));
+        }
+        // 500ms after the last event, and never again
+        assert_eq!(rebuilds, vec![(0, 14)]);
+    }
+
+    #[test]
+    fn captures_settle_independently() {
+        let mut d = Debouncer::new(Duration::from_millis(500));
+        let t0 = Instant::now();
+        d.event(0, t0);
+        d.event(1, t0 + Duration::from_millis(300));
+        assert_eq!(d.due(t0 + Duration::from_millis(500)), vec![0]);
+        assert!(d.due(t0 + Duration::from_millis(700)).is_empty());
+        assert_eq!(d.due(t0 + Duration::from_millis(800)), vec![1]);
+    }
+}
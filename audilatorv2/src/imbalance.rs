@@ -0,0 +1,125 @@
+//! Stereo balance diagnostics. One channel much louder than the other for
+//! seconds on end is usually a wiring or mixing problem (a mic on one side,
+//! a dead input), not programme content, and worth a warning.
+
+use crate::dsp::rms_to_dbfs;
+use crate::transition::Transition;
+
+/// Seconds per measurement: long enough that panned effects don't count.
+pub const WINDOW_SEC: f32 = 5.0;
+
+/// Left minus right, in dB, measured over whole windows.
+pub struct ImbalanceMeter {
+    channels: usize,
+    warn_db: f32,
+    quiet_dbfs: f32,
+    window_frames: usize,
+    sums: [f64; 2],
+    frames: usize,
+    imbalance_db: f32,
+    warned: bool,
+}
+
+impl ImbalanceMeter {
+    /// Looks at the first two of `channels` interleaved channels. Windows
+    /// where both are under `quiet_dbfs` are skipped: noise floors differ.
+    pub fn new(channels: usize, warn_db: f32, quiet_dbfs: f32, window_frames: usize) -> Self {
+        Self {
+            channels: channels.max(2),
+            warn_db,
+            quiet_dbfs,
+            window_frames: window_frames.max(1),
+            sums: [0.0; 2],
+            frames: 0,
+            imbalance_db: 0.0,
+            warned: false,
+        }
+    }
+
+    /// Feed interleaved frames. Returns `Engaged` when the imbalance reaches
+    /// the warning level and `Released` when it drops back under.
+    pub fn push(&mut self, interleaved: &[f32]) -> Option<Transition> {
+        let mut change = None;
+        for frame in interleaved.chunks_exact(self.channels) {
+            self.sums[0] += (frame[0] as f64).powi(2);
+            self.sums[1] += (frame[1] as f64).powi(2);
+            self.frames += 1;
+            if self.frames >= self.window_frames {
+                change = self.update().or(change);
+            }
+        }
+        change
+    }
+
+    fn update(&mut self) -> Option<Transition> {
+        let [left, right] = self
+            .sums
+            .map(|sum| rms_to_dbfs((sum / self.frames as f64).sqrt() as f32));
+        self.sums = [0.0; 2];
+        self.frames = 0;
+        if left.max(right) < self.quiet_dbfs {
+            return None;
+        }
+        self.imbalance_db = left - right;
+        let imbalanced = self.imbalance_db.abs() >= self.warn_db;
+        if imbalanced == self.warned {
+            return None;
+        }
+        self.warned = imbalanced;
+        Some(if imbalanced {
+            Transition::Engaged
+        } else {
+            Transition::Released
+        })
+    }
+
+    /// Left minus right over the last window that wasn't quiet.
+    pub fn imbalance_db(&self) -> f32 {
+        self.imbalance_db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved stereo at constant levels.
+    fn stereo(left: f32, right: f32, frames: usize) -> Vec<f32> {
+        (0..frames).flat_map(|_| [left, right]).collect()
+    }
+
+    fn meter() -> ImbalanceMeter {
+        ImbalanceMeter::new(2, 10.0, -60.0, 1000)
+    }
+
+    #[test]
+    fn one_sided_stereo_warns() {
+        let mut m = meter();
+        // Left at -20 dBFS, right at -40
+        assert_eq!(m.push(&stereo(0.1, 0.01, 1000)), Some(Transition::Engaged));
+        assert!((m.imbalance_db() - 20.0).abs() < 0.01);
+        // Still one-sided: no repeat
+        assert_eq!(m.push(&stereo(0.1, 0.01, 1000)), None);
+        // Balanced again
+        assert_eq!(m.push(&stereo(0.1, 0.08, 1000)), Some(Transition::Released));
+        assert!((m.imbalance_db() - 1.94).abs() < 0.01);
+    }
+
+    #[test]
+    fn balanced_and_quiet_stereo_do_not() {
+        let mut m = meter();
+        assert_eq!(m.push(&stereo(0.1, 0.05, 3000)), None);
+        // Left louder by 6 dB is under the warning level
+        assert!((m.imbalance_db() - 6.02).abs() < 0.01);
+        // Noise floors a few dB apart
+        assert_eq!(m.push(&stereo(0.0001, 0.00001, 3000)), None);
+    }
+
+    #[test]
+    fn only_front_pair_of_wider_layouts_counts() {
+        let mut m = ImbalanceMeter::new(4, 10.0, -60.0, 1000);
+        let frames: Vec<f32> = (0..1000).flat_map(|_| [0.01, 0.1, 0.5, 0.0]).collect();
+        assert_eq!(m.push(&frames), Some(Transition::Engaged));
+        assert!(m.imbalance_db() < -19.0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Noticing when leveling isn't doing anything. Audio that never once drops
+//! under the quiet threshold or climbs over the loud one, for many minutes,
+//! usually means the dead zone is too wide for the content or the wrong
+//! input is being listened to, not that the programme is perfectly even.
+
+use std::time::{Duration, Instant};
+
+use crate::adaptive::Thresholds;
+use crate::transition::Transition;
+
+/// Gaps between updates longer than this (standby, a stalled capture) don't
+/// count as audio heard.
+const MAX_GAP: Duration = Duration::from_secs(1);
+
+pub struct InactivityMonitor {
+    window: Duration,
+    /// Non-silent audio heard in the current window
+    active: Duration,
+    last: Option<Instant>,
+    quiet_triggers: u32,
+    loud_triggers: u32,
+    warned: bool,
+}
+
+impl InactivityMonitor {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            active: Duration::ZERO,
+            last: None,
+            quiet_triggers: 0,
+            loud_triggers: 0,
+            warned: false,
+        }
+    }
+
+    /// Once per update. `Engaged` when a window of audio has gone by with
+    /// neither threshold crossed, `Released` at the next crossing after that.
+    pub fn observe(
+        &mut self,
+        envelope_dbfs: f32,
+        thresholds: Thresholds,
+        silent: bool,
+        now: Instant,
+    ) -> Option<Transition> {
+        let last = self.last.replace(now);
+        if silent {
+            return None;
+        }
+        if let Some(gap) = last
+            .map(|l| now.duration_since(l))
+            .filter(|&g| g <= MAX_GAP)
+        {
+            self.active += gap;
+        }
+        if envelope_dbfs < thresholds.quiet_dbfs {
+            self.quiet_triggers += 1;
+        } else if envelope_dbfs > thresholds.loud_dbfs {
+            self.loud_triggers += 1;
+        }
+
+        if self.warned && self.quiet_triggers + self.loud_triggers > 0 {
+            self.start_window();
+            self.warned = false;
+            return Some(Transition::Released);
+        }
+        if self.active < self.window {
+            return None;
+        }
+        let inactive = self.quiet_triggers == 0 && self.loud_triggers == 0;
+        self.start_window();
+        (inactive && !std::mem::replace(&mut self.warned, true)).then_some(Transition::Engaged)
+    }
+
+    fn start_window(&mut self) {
+        self.active = Duration::ZERO;
+        self.quiet_triggers = 0;
+        self.loud_triggers = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        quiet_dbfs: -29.0,
+        loud_dbfs: -21.0,
+    };
+
+    /// Updates every 50ms for `secs` at `level`, from `start`. The changes.
+    fn run(
+        monitor: &mut InactivityMonitor,
+        start: Instant,
+        secs: u64,
+        level: impl Fn(u64) -> f32,
+    ) -> Vec<(u64, Transition)> {
+        (0..secs * 20)
+            .filter_map(|n| {
+                let now = start + Duration::from_millis(n * 50);
+                monitor
+                    .observe(level(n), THRESHOLDS, false, now)
+                    .map(|c| (n, c))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn always_normal_audio_warns_once() {
+        let mut m = InactivityMonitor::new(Duration::from_secs(60));
+        let changes = run(&mut m, Instant::now(), 180, |_| -25.0);
+        // After the first minute, and not again for the rest
+        assert_eq!(changes, vec![(1200, Transition::Engaged)]);
+    }
+
+    #[test]
+    fn crossings_keep_it_quiet_and_end_a_warning() {
+        let mut m = InactivityMonitor::new(Duration::from_secs(60));
+        // Loud once every 30s
+        let level = |n: u64| if n.is_multiple_of(600) { -15.0 } else { -25.0 };
+        let t0 = Instant::now();
+        assert!(run(&mut m, t0, 180, level).is_empty());
+
+        let start = t0 + Duration::from_secs(180);
+        assert_eq!(run(&mut m, start, 61, |_| -25.0).len(), 1);
+        let after = start + Duration::from_secs(61);
+        assert_eq!(
+            m.observe(-35.0, THRESHOLDS, false, after),
+            Some(Transition::Released)
+        );
+    }
+
+    #[test]
+    fn silence_does_not_count() {
+        let mut m = InactivityMonitor::new(Duration::from_secs(60));
+        let start = Instant::now();
+        for n in 0..2400 {
+            let now = start + Duration::from_millis(n * 50);
+            assert_eq!(m.observe(-70.0, THRESHOLDS, true, now), None);
+        }
+    }
+}
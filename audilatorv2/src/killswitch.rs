@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::transition::Transition;
+
+/// How often to look for the file.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Holds all adjustments while a file exists, so scripts can pause leveling
+/// with `touch` and resume with `rm`.
+pub struct KillSwitch {
+    path: PathBuf,
+    last_poll: Option<Instant>,
+    engaged: bool,
+}
+
+impl KillSwitch {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            last_poll: None,
+            engaged: false,
+        }
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Re-check the file if a poll is due. Returns the change, if any.
+    pub fn poll(&mut self, now: Instant) -> Option<Transition> {
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < POLL_INTERVAL)
+        {
+            return None;
+        }
+        self.last_poll = Some(now);
+
+        let exists = self.path.exists();
+        if exists == self.engaged {
+            return None;
+        }
+        self.engaged = exists;
+        Some(if exists {
+            Transition::Engaged
+        } else {
+            Transition::Released
+        })
+    }
+
+    /// Wait for the file to appear or go. Checks on its own schedule, so a
+    /// stalled capture doesn't delay a hold. Cancel-safe: a change is
+    /// recorded and returned in the same poll.
+    pub async fn changed(&mut self) -> Transition {
+        loop {
+            if let Some(change) = self.poll(Instant::now()) {
+                return change;
+            }
+            if let Some(last) = self.last_poll {
+                tokio::time::sleep_until((last + POLL_INTERVAL).into()).await;
+            }
+        }
+    }
+
+    pub fn engaged(&self) -> bool {
+        self.engaged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("audilator-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn file_is_polled_at_low_frequency() {
+        let path = temp_path("killswitch-poll");
+        let _ = std::fs::remove_file(&path);
+        let mut ks = KillSwitch::new(path.clone());
+        let start = Instant::now();
+        ks.poll(start);
+
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(ks.poll(start + Duration::from_millis(10)), None);
+        assert_eq!(ks.poll(start + POLL_INTERVAL), Some(Transition::Engaged));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn changes_are_seen_without_being_polled() {
+        let path = temp_path("killswitch-changed");
+        let _ = std::fs::remove_file(&path);
+        let mut ks = KillSwitch::new(path.clone());
+        ks.poll(Instant::now());
+
+        std::fs::write(&path, b"").unwrap();
+        let change = tokio::time::timeout(POLL_INTERVAL * 3, ks.changed()).await;
+        assert_eq!(change.ok(), Some(Transition::Engaged));
+        assert!(ks.engaged());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Targets that follow the listener. A nudge (`POST /nudge` on the status
+//! server) while speech or music plays moves that kind of content's target,
+//! and the offsets are saved, so each session starts where the corrections
+//! left off instead of from `--target` again.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Seconds of speech detection a content type is judged over: long enough
+/// that pauses in dialogue and a line over a score don't flip it.
+const CONTENT_WINDOW_SEC: f32 = 10.0;
+const SPEECH_SHARE: f32 = 0.7;
+const MUSIC_SHARE: f32 = 0.2;
+/// No amount of nudging moves a target further than this from `--target`.
+const MAX_OFFSET_DB: f32 = 12.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    Speech,
+    Music,
+    /// Dialogue over a score, or something in between
+    Mixed,
+}
+
+impl ContentType {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Speech => "speech",
+            Self::Music => "music",
+            Self::Mixed => "mixed",
+        }
+    }
+}
+
+/// Speech or not, per update, into a content type.
+pub struct ContentTracker {
+    history: VecDeque<bool>,
+    capacity: usize,
+}
+
+impl ContentTracker {
+    pub fn new(update_rate_hz: f32) -> Self {
+        let capacity = ((CONTENT_WINDOW_SEC * update_rate_hz) as usize).max(1);
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn update(&mut self, speaking: bool) -> ContentType {
+        if self.history.len() >= self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(speaking);
+        let share = self.history.iter().filter(|&&s| s).count() as f32 / self.history.len() as f32;
+        if share >= SPEECH_SHARE {
+            ContentType::Speech
+        } else if share <= MUSIC_SHARE {
+            ContentType::Music
+        } else {
+            ContentType::Mixed
+        }
+    }
+}
+
+/// dB each content type's target sits above (or below) `--target`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LearnedTargets(BTreeMap<ContentType, f32>);
+
+impl LearnedTargets {
+    /// Empty when the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("reading learned targets {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    pub fn offset(&self, content: ContentType) -> f32 {
+        self.0.get(&content).copied().unwrap_or(0.0)
+    }
+
+    /// The listener wanted `db` more while `content` played. Returns the new
+    /// offset.
+    pub fn nudge(&mut self, content: ContentType, db: f32) -> f32 {
+        let offset = self.0.entry(content).or_insert(0.0);
+        *offset = (*offset + db).clamp(-MAX_OFFSET_DB, MAX_OFFSET_DB);
+        *offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_follows_the_share_of_speech() {
+        let mut tracker = ContentTracker::new(10.0);
+        let run = |tracker: &mut ContentTracker, pattern: &[bool]| {
+            let mut content = None;
+            for _ in 0..100 / pattern.len() {
+                for &s in pattern {
+                    content = Some(tracker.update(s));
+                }
+            }
+            content.unwrap()
+        };
+        assert_eq!(run(&mut tracker, &[true]), ContentType::Speech);
+        assert_eq!(run(&mut tracker, &[false]), ContentType::Music);
+        assert_eq!(run(&mut tracker, &[true, false]), ContentType::Mixed);
+        // A few lines over a score are still music
+        assert_eq!(
+            run(
+                &mut tracker,
+                &[true, false, false, false, false, false, false]
+            ),
+            ContentType::Music
+        );
+    }
+
+    #[test]
+    fn learned_targets_shift_toward_corrections() {
+        // The listener wants dialogue 5 dB up and music left alone, and nudges
+        // by up to 2 dB whenever it's off by more than 1
+        let mut learned = LearnedTargets::default();
+        for _session in 0..5 {
+            let off = 5.0 - learned.offset(ContentType::Speech);
+            if off.abs() > 1.0 {
+                learned.nudge(ContentType::Speech, off.clamp(-2.0, 2.0));
+            }
+        }
+        assert!((learned.offset(ContentType::Speech) - 5.0).abs() <= 1.0);
+        assert_eq!(learned.offset(ContentType::Music), 0.0);
+
+        // Nudges that undo each other cancel out, and extremes are capped
+        learned.nudge(ContentType::Music, 3.0);
+        learned.nudge(ContentType::Music, -3.0);
+        assert_eq!(learned.offset(ContentType::Music), 0.0);
+        assert_eq!(learned.nudge(ContentType::Mixed, -30.0), -MAX_OFFSET_DB);
+    }
+
+    #[test]
+    fn learned_targets_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("audilator-learn-{}", std::process::id()));
+        assert_eq!(
+            LearnedTargets::load(&path).unwrap(),
+            LearnedTargets::default()
+        );
+        let mut learned = LearnedTargets::default();
+        learned.nudge(ContentType::Speech, 2.5);
+        learned.save(&path).unwrap();
+        let read = std::fs::read_to_string(&path).unwrap();
+        assert!(read.contains(r#""speech": 2.5"#), "{read}");
+        assert_eq!(LearnedTargets::load(&path).unwrap(), learned);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! Where log messages go (`--log-target`). On a terminal they're short,
+//! coloured, and clear the live meter line first; in a file they carry full
+//! timestamps and rotate by size; for journald they're plain, with the
+//! syslog priority prefix it reads as the level (it adds its own timestamps).
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogTarget {
+    Stdout,
+    Stderr,
+    /// --log-file, rotated at --log-max-mb
+    File,
+    /// stderr without timestamps or colour, levels as syslog priorities
+    Journald,
+}
+
+impl LogTarget {
+    /// Whether the live meter line belongs next to the log: only on a
+    /// terminal stream, never in a file or the journal.
+    pub fn live(self) -> bool {
+        matches!(self, Self::Stdout | Self::Stderr)
+    }
+}
+
+pub struct Rotation {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    /// Rotated files kept as path.1 (newest) to path.N
+    pub keep: usize,
+}
+
+/// Install the global subscriber. `rotation` is required for `File`.
+pub fn init(target: LogTarget, rotation: Option<Rotation>) -> Result<()> {
+    tracing::subscriber::set_global_default(build(target, rotation)?).context("setting up logging")
+}
+
+/// The subscriber `init` installs, writing where `target` says.
+fn build(
+    target: LogTarget,
+    rotation: Option<Rotation>,
+) -> Result<Box<dyn Subscriber + Send + Sync>> {
+    let writer = match target {
+        LogTarget::Stdout => {
+            let tty = io::stdout().is_terminal();
+            BoxMakeWriter::new(move || ClearLine::new(io::stdout(), tty))
+        }
+        LogTarget::Stderr => {
+            let tty = io::stderr().is_terminal();
+            BoxMakeWriter::new(move || ClearLine::new(io::stderr(), tty))
+        }
+        LogTarget::File => {
+            let rotation = rotation.context("--log-target file needs --log-file")?;
+            BoxMakeWriter::new(Mutex::new(RotatingFile::open(rotation)?))
+        }
+        LogTarget::Journald => BoxMakeWriter::new(io::stderr),
+    };
+    let ansi = match target {
+        LogTarget::Stdout => io::stdout().is_terminal(),
+        LogTarget::Stderr => io::stderr().is_terminal(),
+        _ => false,
+    };
+    Ok(subscriber(target, writer, ansi))
+}
+
+fn subscriber(
+    target: LogTarget,
+    writer: BoxMakeWriter,
+    ansi: bool,
+) -> Box<dyn Subscriber + Send + Sync> {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_target(false)
+        .with_writer(writer);
+    match target {
+        LogTarget::Stdout | LogTarget::Stderr => {
+            Box::new(builder.without_time().with_ansi(ansi).finish())
+        }
+        LogTarget::File => Box::new(builder.with_ansi(false).finish()),
+        LogTarget::Journald => Box::new(builder.event_format(JournaldFormat).finish()),
+    }
+}
+
+/// `<priority>message`, one line per event.
+struct JournaldFormat;
+
+impl<S, N> FormatEvent<S, N> for JournaldFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let priority = match *event.metadata().level() {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            _ => 7,
+        };
+        write!(writer, "<{priority}>")?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// On a terminal, returns to the start of the line and clears it before
+/// the event, so it replaces the meter instead of running into it.
+struct ClearLine<W> {
+    inner: W,
+    pending: bool,
+}
+
+impl<W: Write> ClearLine<W> {
+    fn new(inner: W, tty: bool) -> Self {
+        Self {
+            inner,
+            pending: tty,
+        }
+    }
+}
+
+impl<W: Write> Write for ClearLine<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if std::mem::take(&mut self.pending) {
+            self.inner.write_all(b"\r\x1b[K")?;
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Appends to `path` until the next line would take it past `max_bytes`,
+/// then shifts path -> path.1 -> path.2 ..., dropping the oldest.
+struct RotatingFile {
+    rotation: Rotation,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(rotation: Rotation) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&rotation.path)
+            .with_context(|| format!("opening {}", rotation.path.display()))?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            rotation,
+            file,
+            written,
+        })
+    }
+
+    fn numbered(&self, n: usize) -> PathBuf {
+        let mut name = OsString::from(&self.rotation.path);
+        name.push(format!(".{n}"));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let keep = self.rotation.keep;
+        if keep > 0 {
+            for n in (1..keep).rev() {
+                let from = self.numbered(n);
+                if from.exists() {
+                    fs::rename(from, self.numbered(n + 1))?;
+                }
+            }
+            fs::rename(&self.rotation.path, self.numbered(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.rotation.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.rotation.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A warning through `target`'s formatting.
+    fn format(target: LogTarget, ansi: bool) -> String {
+        let buffer = Buffer::default();
+        let writer = {
+            let buffer = buffer.clone();
+            BoxMakeWriter::new(move || buffer.clone())
+        };
+        tracing::subscriber::with_default(subscriber(target, writer, ansi), || {
+            tracing::warn!("living: saturated at --vol-max 1.00");
+            tracing::debug!("not shown");
+        });
+        let out = buffer.0.lock().unwrap().clone();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn each_target_formats_for_its_reader() {
+        assert_eq!(
+            format(LogTarget::Journald, false),
+            "<4>living: saturated at --vol-max 1.00\n"
+        );
+        assert_eq!(
+            format(LogTarget::Stderr, false),
+            " WARN living: saturated at --vol-max 1.00\n"
+        );
+        assert!(format(LogTarget::Stderr, true).contains("\x1b["));
+
+        // Timestamped, e.g. 2026-01-01T12:00:00.000000Z
+        let file = format(LogTarget::File, false);
+        assert!(
+            file.ends_with(" WARN living: saturated at --vol-max 1.00\n"),
+            "{file}"
+        );
+        assert!(file.as_bytes()[4] == b'-' && file.contains('T'), "{file}");
+    }
+
+    #[test]
+    fn file_target_rotates_and_logs_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("audilator-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audilator.log");
+        let rotation = |path: &PathBuf| Rotation {
+            path: path.clone(),
+            max_bytes: 100,
+            keep: 2,
+        };
+
+        let mut file = RotatingFile::open(rotation(&path)).unwrap();
+        for n in 0..10 {
+            // One write per line, as the subscriber does
+            let line = format!("line {n} {}\n", "x".repeat(30));
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        let read = |p: PathBuf| fs::read_to_string(p).unwrap();
+        assert!(read(path.clone()).contains("line 9"));
+        // Two lines fit under 100 bytes
+        assert!(read(file.numbered(1)).starts_with("line 6"));
+        assert!(read(file.numbered(2)).starts_with("line 4"));
+        assert!(!file.numbered(3).exists());
+
+        let logger = build(LogTarget::File, Some(rotation(&path))).unwrap();
+        tracing::subscriber::with_default(logger, || tracing::info!("started"));
+        assert!(read(path.clone()).contains("INFO started"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
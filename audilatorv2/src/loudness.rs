@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// EBU R128 momentary window.
+const MOMENTARY_SEC: f32 = 0.4;
+/// EBU R128 short-term window.
+const SHORT_TERM_SEC: f32 = 3.0;
+/// Blocks below this never count towards integrated loudness.
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+/// Blocks this far below the ungated mean are dropped from integrated loudness.
+const RELATIVE_GATE_LU: f32 = -10.0;
+/// Integrated-loudness histogram resolution and ceiling.
+const BIN_LU: f32 = 0.1;
+const CEILING_LUFS: f32 = 10.0;
+/// Floor for reported values, matching `rms_to_dbfs`.
+const FLOOR_LUFS: f32 = -80.0;
+
+/// Which loudness measurement the compressor levels on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ControlTimescale {
+    /// Unweighted RMS over --window (the original behaviour)
+    Window,
+    /// K-weighted, 400ms (fast ducking)
+    Momentary,
+    /// K-weighted, 3s (general tracking)
+    ShortTerm,
+    /// K-weighted and gated over the whole session (long-term budget)
+    Integrated,
+}
+
+/// The three R128 loudness timescales, in LUFS.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Loudness {
+    pub momentary: f32,
+    pub short_term: f32,
+    /// None until a block has passed the absolute gate
+    pub integrated: Option<f32>,
+}
+
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// Coefficients normalized so a0 = 1.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        // Transposed direct form II
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting (head shelf + RLB high-pass) for any sample rate.
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, highpass }
+    }
+
+    fn process(&mut self, x: f32) -> f64 {
+        self.highpass.process(self.shelf.process(x as f64))
+    }
+}
+
+fn lufs(mean_square: f64) -> f32 {
+    if mean_square <= 0.0 {
+        return FLOOR_LUFS;
+    }
+    ((-0.691 + 10.0 * mean_square.log10()) as f32).max(FLOOR_LUFS)
+}
+
+/// Momentary, short-term and integrated loudness of a mono stream, updated
+/// once per analysis window.
+pub struct LoudnessMeter {
+    filter: KWeighting,
+    window_energy: f64,
+    window_samples: usize,
+    /// Mean square of each recent update window, newest last
+    windows: VecDeque<f64>,
+    momentary_len: usize,
+    short_term_len: usize,
+    /// (energy sum, block count) per BIN_LU of momentary loudness above the
+    /// absolute gate; bounded memory however long the session runs
+    histogram: Vec<(f64, u64)>,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, update_rate_hz: f32) -> Self {
+        let windows_for = |secs: f32| ((secs * update_rate_hz).round() as usize).max(1);
+        let bins = ((CEILING_LUFS - ABSOLUTE_GATE_LUFS) / BIN_LU) as usize + 1;
+        Self {
+            filter: KWeighting::new(sample_rate),
+            window_energy: 0.0,
+            window_samples: 0,
+            windows: VecDeque::new(),
+            momentary_len: windows_for(MOMENTARY_SEC),
+            short_term_len: windows_for(SHORT_TERM_SEC),
+            histogram: vec![(0.0, 0); bins],
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            let y = self.filter.process(s);
+            self.window_energy += y * y;
+        }
+        self.window_samples += samples.len();
+    }
+
+    /// Close the current analysis window and report all three timescales.
+    pub fn update(&mut self) -> Loudness {
+        let mean_square = self.window_energy / self.window_samples.max(1) as f64;
+        self.window_energy = 0.0;
+        self.window_samples = 0;
+        if self.windows.len() >= self.short_term_len {
+            self.windows.pop_front();
+        }
+        self.windows.push_back(mean_square);
+
+        let recent = |n: usize| {
+            let n = n.min(self.windows.len());
+            self.windows.iter().rev().take(n).sum::<f64>() / n as f64
+        };
+        let momentary_energy = recent(self.momentary_len);
+        let momentary = lufs(momentary_energy);
+        let short_term = lufs(recent(self.short_term_len));
+
+        if self.windows.len() >= self.momentary_len && momentary >= ABSOLUTE_GATE_LUFS {
+            let bin = ((momentary.min(CEILING_LUFS) - ABSOLUTE_GATE_LUFS) / BIN_LU) as usize;
+            let slot = &mut self.histogram[bin];
+            slot.0 += momentary_energy;
+            slot.1 += 1;
+        }
+
+        Loudness {
+            momentary,
+            short_term,
+            integrated: self.integrated(),
+        }
+    }
+
+    fn integrated(&self) -> Option<f32> {
+        let mean = |bins: &[(f64, u64)]| {
+            let (energy, count) = bins
+                .iter()
+                .fold((0.0, 0), |(e, c), &(be, bc)| (e + be, c + bc));
+            (count > 0).then(|| energy / count as f64)
+        };
+        let ungated = lufs(mean(&self.histogram)?);
+        let gate = ungated + RELATIVE_GATE_LU;
+        let first =
+            (((gate - ABSOLUTE_GATE_LUFS) / BIN_LU).max(0.0) as usize).min(self.histogram.len());
+        mean(&self.histogram[first..]).map(lufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Feed `secs` of a 997 Hz sine at `amplitude` in 50ms windows.
+    fn feed_sine(meter: &mut LoudnessMeter, amplitude: f32, secs: f32) -> Loudness {
+        let window = (RATE / 20) as usize;
+        let mut last = None;
+        let mut n = 0usize;
+        for _ in 0..(secs * 20.0) as usize {
+            let chunk: Vec<f32> = (0..window)
+                .map(|_| {
+                    n += 1;
+                    amplitude * (2.0 * std::f32::consts::PI * 997.0 * n as f32 / RATE as f32).sin()
+                })
+                .collect();
+            meter.push(&chunk);
+            last = Some(meter.update());
+        }
+        last.unwrap()
+    }
+
+    #[test]
+    fn full_scale_sine_reads_minus_three_lufs() {
+        // BS.1770: a 0 dBFS 997 Hz sine in one channel is -3.01 LUFS
+        let mut meter = LoudnessMeter::new(RATE, 20.0);
+        let l = feed_sine(&mut meter, 1.0, 4.0);
+        assert!((l.momentary + 3.01).abs() < 0.1, "{}", l.momentary);
+        assert!((l.short_term + 3.01).abs() < 0.1, "{}", l.short_term);
+        assert!((l.integrated.unwrap() + 3.01).abs() < 0.1);
+    }
+
+    #[test]
+    fn momentary_reacts_before_short_term() {
+        let mut meter = LoudnessMeter::new(RATE, 20.0);
+        feed_sine(&mut meter, 0.01, 4.0);
+        let l = feed_sine(&mut meter, 1.0, 0.5);
+        assert!((l.momentary + 3.01).abs() < 0.5, "{}", l.momentary);
+        assert!(l.short_term < l.momentary - 5.0);
+    }
+
+    #[test]
+    fn integrated_gates_out_silence_and_quiet_passages() {
+        let mut meter = LoudnessMeter::new(RATE, 20.0);
+        assert_eq!(meter.update().integrated, None);
+
+        feed_sine(&mut meter, 0.1, 5.0); // -23 LUFS
+        feed_sine(&mut meter, 0.0, 5.0); // below the absolute gate
+        let l = feed_sine(&mut meter, 0.001, 5.0); // -63 LUFS, below the relative gate
+        let integrated = l.integrated.unwrap();
+        assert!((integrated + 23.0).abs() < 0.3, "{integrated}");
+        assert!(l.short_term < -60.0);
+    }
+}
@@ -1,15 +1,102 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, SampleFormat, StreamConfig};
-use serde::{Deserialize, Serialize};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use serde::Deserialize;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
+mod adaptive;
+mod audio;
+mod channels;
+mod config;
+#[cfg(target_os = "macos")]
+mod coreaudio;
 mod dsp;
-use dsp::{Compressor, CompressorConfig};
+mod events;
+#[cfg(unix)]
+mod fifo;
+#[cfg(any(feature = "fixed-point", test))]
+mod fixed;
+mod gainstage;
+mod heartbeat;
+mod hotplug;
+mod imbalance;
+mod inactivity;
+mod killswitch;
+mod learn;
+mod logging;
+mod loudness;
+mod midi;
+mod mix;
+mod monitor;
+mod netinput;
+mod notch;
+mod output;
+mod params;
+mod pause;
+mod preprocess;
+mod reference;
+mod regions;
+mod register;
+mod scenes;
+mod schedule;
+mod selftest;
+mod sender;
+#[cfg(unix)]
+mod shm;
+mod sink;
+mod speech;
+mod standby;
+mod status;
+mod target;
+#[cfg(test)]
+mod testutil;
+mod transition;
+mod tui;
+mod units;
+mod ws;
+mod zone;
+use adaptive::{AdaptiveConfig, MedianConfig};
+use audio::{
+    build_input_stream, find_device, find_output_device, list_devices, log_stream_error, Batch,
+    Capture,
+};
+use channels::{ChannelCompressors, Dialogue};
+use config::{FileConfig, ZoneConfig};
+use dsp::{
+    Analyzer, Compressor, CompressorConfig, DisplaySmoother, ProcessResult, RampConfig, Reading,
+};
+use events::{Event, EventBus};
+use gainstage::{Advice, GainMeter, Limit};
+use heartbeat::{Beat, BeatHealth, FailurePolicy, HeartbeatTimer};
+use hotplug::Debouncer;
+use inactivity::InactivityMonitor;
+use killswitch::KillSwitch;
+use learn::{ContentType, LearnedTargets};
+use logging::LogTarget;
+use loudness::ControlTimescale;
+use notch::NotchDetector;
+use output::{Cooldown, CooldownOn, SendBudget, SendGate, SettleTracker, DEFAULT_SEND_DEADBAND};
+use params::ParamSet;
+use pause::ProcessPause;
+use preprocess::SampleProcessor;
+use register::Registration;
+use schedule::RecalibrationSchedule;
+use selftest::{SelfTest, Verdict};
+use sender::{SendOutcome, Sender};
+use sink::{HttpSink, Output, ReadbackCombine, VolumeSink};
+use standby::Standby;
+use status::SharedStatus;
+use std::collections::BTreeSet;
+use transition::Transition;
+use units::{LoudnessUnit, ScoreScale, Units, DEFAULT_FULL_SCALE_SPL};
+use zone::Zone;
+
+/// --target when none is given.
+const DEFAULT_TARGET_DBFS: f32 = -25.0;
 
 #[derive(Parser, Debug)]
 #[command(name = "audilator", about = "TV volume auto-leveler for Raspberry Pi")]
@@ -22,9 +109,23 @@ struct Args {
     #[arg(short, long, default_value_t = 8765)]
     port: u16,
 
-    /// Target loudness in dBFS (calibrate first)
-    #[arg(long, default_value_t = -25.0)]
-    target: f32,
+    /// Target loudness in --units (default: -25 dBFS; calibrate first)
+    #[arg(long, allow_negative_numbers = true)]
+    target: Option<f32>,
+
+    /// Units for --target, region bounds and displayed levels. Phons and
+    /// sones are approximations (see --full-scale-spl)
+    #[arg(long, value_enum, default_value_t = LoudnessUnit::Dbfs)]
+    units: LoudnessUnit,
+
+    /// dB SPL a full-scale signal reaches at the mic, for phons and sones
+    #[arg(long, default_value_t = DEFAULT_FULL_SCALE_SPL)]
+    full_scale_spl: f32,
+
+    /// WAV whose integrated loudness becomes the target (overrides --target).
+    /// Best recorded through the same mic the listener uses.
+    #[arg(long)]
+    reference_wav: Option<std::path::PathBuf>,
 
     /// Dead zone in dB (no adjustment within +/- this of target)
     #[arg(long, default_value_t = 4.0)]
@@ -46,6 +147,32 @@ struct Args {
     #[arg(long, default_value_t = 30.0)]
     max_slew: f32,
 
+    /// dB of cut per dB the level is over the loud threshold
+    #[arg(long, default_value_t = 1.0, value_parser = non_negative_arg)]
+    duck_ratio: f32,
+
+    /// Cap on how fast loud content is cut in dB/sec (0 = --max-slew)
+    #[arg(long, default_value_t = 0.0)]
+    duck_max: f32,
+
+    /// Scale correction speed with the loudness step instead of a fixed
+    /// --max-slew: dB/sec for the largest steps (unset = off)
+    #[arg(long, value_parser = positive_arg)]
+    ramp_max: Option<f32>,
+
+    /// Correction speed in dB/sec for a step just past the dead zone
+    #[arg(long, default_value_t = 3.0, requires = "ramp_max", value_parser = positive_arg)]
+    ramp_min: f32,
+
+    /// Step in dB from which corrections run at --ramp-max
+    #[arg(
+        long,
+        default_value_t = 20.0,
+        requires = "ramp_max",
+        value_parser = non_negative_arg
+    )]
+    ramp_full: f32,
+
     /// Silence threshold in dBFS
     #[arg(long, default_value_t = -60.0)]
     silence_threshold: f32,
@@ -54,10 +181,95 @@ struct Args {
     #[arg(long, default_value_t = 5.0)]
     silence_hold: f32,
 
+    /// Minutes of silence before a zone stands by: no analysis or sends
+    /// until audio returns (0 = off)
+    #[arg(long, default_value_t = 0.0, conflicts_with = "per_channel")]
+    standby_after: f32,
+
+    /// Warn when N minutes of audio pass without ever crossing the quiet or
+    /// loud threshold: leveling is doing nothing
+    #[arg(long, value_parser = positive_arg, conflicts_with = "per_channel")]
+    inactivity_warn: Option<f32>,
+
+    /// Seconds of loudness history for volatility detection (0 = off).
+    /// Volatile content gets a wider dead zone and slower slew.
+    #[arg(long, default_value_t = 0.0)]
+    variance_window: f32,
+
+    /// Loudness std-dev in dB above which content counts as volatile
+    #[arg(long, default_value_t = 6.0)]
+    variance_threshold: f32,
+
+    /// Drop envelope/hysteresis momentum when content flips between
+    /// quiet and loud, so the new condition doesn't overshoot
+    #[arg(long)]
+    reset_on_transition: bool,
+
+    /// Boost more at low volumes, where bass and treble fade faster than
+    /// the RMS shows (ISO 226 approximation; 0 = off, 1 = nominal)
+    #[arg(long, default_value_t = 0.0)]
+    low_volume_compensation: f32,
+
+    /// After a brief drop below --silence-threshold (e.g. before an ad
+    /// break), hold boosts for this many ms once sound returns (0 = off)
+    #[arg(long, default_value_t = 0.0)]
+    gap_hold_ms: f32,
+
+    /// Leave the volume alone while speech is detected, levelling only
+    /// music and effects
+    #[arg(long)]
+    hold_during_speech: bool,
+
+    /// Loudness measurement the compressor levels on. The K-weighted
+    /// timescales are in LUFS, so recalibrate --target when switching.
+    #[arg(long, value_enum, default_value_t = ControlTimescale::Window)]
+    control_timescale: ControlTimescale,
+
+    /// Seconds of loudness history for adaptive thresholds (0 = off).
+    /// Target and dead zone then follow percentiles of recent loudness.
+    #[arg(long, default_value_t = 0.0)]
+    adaptive_window: f32,
+
+    /// Seconds between adaptive threshold updates
+    #[arg(long, default_value_t = 5.0)]
+    adaptive_update: f32,
+
+    /// Percentile of recent loudness used as the quiet threshold
+    #[arg(long, default_value_t = 10.0)]
+    adaptive_quiet_pct: f32,
+
+    /// Percentile of recent loudness used as the loud threshold
+    #[arg(long, default_value_t = 90.0)]
+    adaptive_loud_pct: f32,
+
+    /// Seconds of loudness history for median-relative control (0 = off).
+    /// Content further than the thresholds from the rolling median is
+    /// ducked or boosted.
+    #[arg(long, default_value_t = 0.0, conflicts_with = "adaptive_window")]
+    median_window: f32,
+
+    /// dB over the rolling median before content is ducked
+    #[arg(long, default_value_t = 3.0)]
+    median_above: f32,
+
+    /// dB under the rolling median before content is boosted
+    #[arg(long, default_value_t = 3.0)]
+    median_below: f32,
+
+    /// Warn when one stereo channel is this many dB louder than the other
+    /// for several seconds (usually a wiring or mixing problem)
+    #[arg(long, conflicts_with = "per_channel")]
+    imbalance_warn_db: Option<f32>,
+
     /// RMS measurement window in ms
     #[arg(long, default_value_t = 50.0)]
     window: f32,
 
+    /// Batch capture callbacks into messages of at least this many ms
+    /// (0 = one message per callback). Keep it under --window.
+    #[arg(long, default_value_t = 0.0)]
+    accumulate_ms: f32,
+
     /// Minimum volume (0.0-1.0)
     #[arg(long, default_value_t = 0.05)]
     vol_min: f32,
@@ -70,11 +282,248 @@ struct Args {
     #[arg(long, default_value_t = 0.5)]
     min_interval: f32,
 
+    /// Throttle sends when the controller is slow: space them a few
+    /// round-trips apart, up to this many seconds (0 = off)
+    #[arg(long, default_value_t = 0.0, value_parser = non_negative_arg)]
+    max_interval: f32,
+
+    /// Lengthen the cooldown while adjustments are frequent, up to this
+    /// many seconds, easing back to --min-interval when calm (0 = off)
+    #[arg(long, default_value_t = 0.0, value_parser = non_negative_arg)]
+    adaptive_cooldown_max: f32,
+
+    /// How fast the adaptive cooldown grows: each send in the last minute
+    /// beyond the first 4 multiplies it by 1 + N
+    #[arg(long, default_value_t = 0.5)]
+    adaptive_cooldown_rate: f32,
+
+    /// Collapse volume decisions made within this many ms into one send
+    /// of the last (0 = send each decision as soon as the sender is free)
+    #[arg(long, default_value_t = 0, conflicts_with = "per_channel")]
+    coalesce_ms: u64,
+
+    /// Seconds without a send before the volume counts as settled
+    #[arg(
+        long,
+        default_value_t = 10.0,
+        conflicts_with = "per_channel",
+        value_parser = non_negative_arg
+    )]
+    settle_time: f32,
+
+    /// Which sends start the --min-interval cooldown
+    #[arg(long, value_enum, default_value_t = CooldownOn::Success)]
+    cooldown_on: CooldownOn,
+
+    /// Spread retries after a failed send: wait between (1 - N) and 1
+    /// times --min-interval (0 = off, 1 = full jitter)
+    #[arg(long, default_value_t = 0.0)]
+    retry_jitter: f32,
+
+    /// How a zone with several endpoints combines their current volumes
+    /// into its starting volume ("first" reads the first that answers)
+    #[arg(long, value_enum, default_value_t = ReadbackCombine::First, conflicts_with = "per_channel")]
+    readback_combine: ReadbackCombine,
+
+    /// Stop sending (holding the volume) after this many sends in a session
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "per_channel")]
+    max_sends_per_session: Option<u64>,
+
+    /// Start the --max-sends-per-session budget over every N hours
+    #[arg(long, requires = "max_sends_per_session", value_parser = positive_arg)]
+    send_budget_reset_hours: Option<f32>,
+
+    /// Quantize sent volume to N discrete steps (for devices with fixed levels)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    volume_steps: Option<u32>,
+
+    /// Smallest volume change worth sending (ignored with --volume-steps).
+    /// A value identical to the last one sent is never resent.
+    #[arg(long, default_value_t = DEFAULT_SEND_DEADBAND)]
+    send_deadband: f32,
+
+    /// Where volumes go: the controllers over HTTP, this Mac's default
+    /// output device through CoreAudio, or a MIDI control change
+    #[arg(long, value_enum, default_value_t = Output::Http, conflicts_with = "per_channel")]
+    output: Output,
+
+    /// MIDI output for --output midi (substring match; default: the first)
+    #[arg(long, conflicts_with = "per_channel")]
+    midi_port: Option<String>,
+
+    /// MIDI channel for --output midi, 1-16
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=16))]
+    midi_channel: u8,
+
+    /// Controller number the volume is sent as, 0-127 (default: 7, channel volume)
+    #[arg(long, default_value_t = midi::DEFAULT_CC, value_parser = clap::value_parser!(u8).range(0..=127))]
+    midi_cc: u8,
+
+    /// Also write "<zone> <volume>" lines to this named pipe as volumes
+    /// are set (created if missing; never blocks without a reader)
+    #[cfg(unix)]
+    #[arg(long, conflicts_with = "per_channel")]
+    fifo: Option<std::path::PathBuf>,
+
+    /// Publish each capture's rms, peak and dBFS to a shared-memory ring
+    /// with this name, for local low-latency consumers (layout in shm.rs)
+    #[cfg(unix)]
+    #[arg(long, value_name = "NAME", conflicts_with = "per_channel")]
+    shm: Option<String>,
+
+    /// Level each input channel separately and set per-channel volumes
+    /// on the controller instead of the master volume
+    #[arg(long)]
+    per_channel: bool,
+
+    /// Output channel driven by each input channel, e.g. "0,1,2"
+    /// (default: input N drives output N)
+    #[arg(long, value_delimiter = ',')]
+    channel_map: Vec<usize>,
+
+    /// Input channel carrying dialogue (e.g. 2, the centre of 5.1): it is
+    /// levelled only while speech is detected in it
+    #[arg(long, requires = "per_channel")]
+    dialogue_channel: Option<usize>,
+
+    /// dB above --target for the dialogue channel, lifting speech over
+    /// the other channels
+    #[arg(
+        long,
+        default_value_t = 3.0,
+        requires = "dialogue_channel",
+        value_parser = non_negative_arg
+    )]
+    dialogue_boost: f32,
+
+    /// URL to announce this listener to at startup (POST) and shutdown (DELETE)
+    #[arg(long, conflicts_with = "per_channel")]
+    register_url: Option<String>,
+
+    /// Time constant in seconds for smoothing the displayed level (0 = off).
+    /// Affects only the status line and dashboard, never control.
+    #[arg(long, default_value_t = 0.0, conflicts_with = "per_channel")]
+    display_smoothing: f32,
+
+    /// Level in --units that scores 0 on the 0-100 loudness score
+    /// (default: --silence-threshold)
+    #[arg(long, allow_negative_numbers = true, conflicts_with = "per_channel")]
+    score_floor: Option<f32>,
+
+    /// Level in --units that scores 100 (default: 0 dBFS)
+    #[arg(long, allow_negative_numbers = true, conflicts_with = "per_channel")]
+    score_ceiling: Option<f32>,
+
+    /// Show a live dashboard instead of the status line (q to quit)
+    #[arg(long, conflicts_with = "per_channel")]
+    tui: bool,
+
+    /// Where log messages go: the terminal streams, a rotated --log-file,
+    /// or journald's stderr (plain, with syslog priorities)
+    #[arg(long, value_enum, default_value_t = LogTarget::Stderr)]
+    log_target: LogTarget,
+
+    /// Log file for --log-target file
+    #[arg(long, required_if_eq("log_target", "file"))]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Rotate the log file past this many MB
+    #[arg(long, default_value_t = 10)]
+    log_max_mb: u64,
+
+    /// Rotated log files to keep (audilator.log.1 is the newest)
+    #[arg(long, default_value_t = 5)]
+    log_keep: usize,
+
+    /// JSON config file (zones: named groups of a mic and the endpoints it drives)
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// IANA timezone for the config file's recalibration schedule, e.g.
+    /// "Europe/Berlin" (default: the system's local time)
+    #[arg(long, conflicts_with = "per_channel")]
+    timezone: Option<chrono_tz::Tz>,
+
+    /// Serve GET /status (per-zone state as JSON) on this port
+    #[arg(long, conflicts_with = "per_channel")]
+    status_port: Option<u16>,
+
+    /// Learn a target per content type (speech, music, mixed) from
+    /// POST /nudge?db=N on the status server, kept in this JSON file
+    #[arg(long, requires = "status_port")]
+    learn_file: Option<std::path::PathBuf>,
+
+    /// POST a heartbeat here every --heartbeat-interval, so the server can
+    /// tell the listener is alive between volume changes
+    #[arg(long, conflicts_with = "per_channel")]
+    heartbeat_url: Option<String>,
+
+    /// Seconds between heartbeats
+    #[arg(long, default_value_t = 30.0, requires = "heartbeat_url", value_parser = positive_arg)]
+    heartbeat_interval: f32,
+
+    /// What a failed heartbeat does beyond a warning
+    #[arg(long, value_enum, default_value_t = FailurePolicy::Unhealthy, conflicts_with = "per_channel")]
+    heartbeat_failure: FailurePolicy,
+
+    /// Warn if the first seconds of capture stay below this dBFS (muted
+    /// mic, gain too low, or the wrong input)
+    #[arg(long, allow_negative_numbers = true, conflicts_with = "per_channel")]
+    selftest_min_level: Option<f32>,
+
+    /// Hold all adjustments while this file exists
+    #[arg(long, conflicts_with = "per_channel")]
+    killswitch_file: Option<std::path::PathBuf>,
+
+    /// Volume to send when the kill switch engages (default: leave as is)
+    #[arg(long, requires = "killswitch_file", value_parser = volume_arg)]
+    killswitch_volume: Option<f32>,
+
+    /// Center in Hz of a narrow band to watch, e.g. an alarm tone. Energy
+    /// there sets --notch-volume until the band goes quiet.
+    #[arg(long, conflicts_with = "per_channel")]
+    notch_center: Option<f32>,
+
+    /// Width in Hz of the --notch-center band
+    #[arg(long, default_value_t = 100.0, requires = "notch_center")]
+    notch_bandwidth: f32,
+
+    /// Band level in dBFS that counts as the tone being present
+    #[arg(long, default_value_t = -40.0, allow_negative_numbers = true, conflicts_with = "per_channel")]
+    notch_threshold: f32,
+
+    /// Volume to send while the tone is present
+    #[arg(long, default_value_t = 0.1, value_parser = volume_arg, conflicts_with = "per_channel")]
+    notch_volume: f32,
+
+    /// Hold all adjustments while a process with this name is running
+    /// (e.g. a game or music player that manages its own volume)
+    #[arg(long, value_name = "NAME", conflicts_with = "per_channel")]
+    pause_while_process: Option<String>,
+
+    /// Play what the analyzer hears on this output device (substring match;
+    /// "default" for the default output). Monitors the first zone.
+    #[arg(long, conflicts_with = "per_channel")]
+    monitor_output: Option<String>,
+
     /// Audio input device name (substring match)
     #[arg(long)]
     device: Option<String>,
 
-    /// List available audio devices and exit
+    /// Analyze a network stream instead of a device: an HTTP URL serving
+    /// WAV or audio/L16 PCM, reopened whenever it drops
+    #[arg(
+        long,
+        conflicts_with_all = ["device", "per_channel", "imbalance_warn_db", "calibrate", "gain_report"]
+    )]
+    input_url: Option<String>,
+
+    /// After a capture's stream errors (device unplugged, USB re-enumerating),
+    /// wait until errors stop for N ms, then reopen the device once
+    #[arg(long, default_value_t = hotplug::DEFAULT_SETTLE_MS, conflicts_with = "per_channel")]
+    hotplug_debounce: u64,
+
+    /// List available audio devices and MIDI outputs and exit
     #[arg(long)]
     list_devices: bool,
 
@@ -82,97 +531,50 @@ struct Args {
     #[arg(long, default_value_t = 0.0)]
     calibrate: f32,
 
+    /// Measure headroom, noise floor and typical level for N seconds and
+    /// advise on --input-gain
+    #[arg(long, default_value_t = 0.0)]
+    gain_report: f32,
+
+    /// dB of gain applied to captured audio before analysis
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    input_gain: f32,
+
     /// Audio sample rate
     #[arg(long, default_value_t = 48000)]
     sample_rate: u32,
 }
 
-#[derive(Serialize)]
-struct VolumeRequest {
-    volume: f32,
-}
-
-#[derive(Deserialize)]
-struct VolumeResponse {
-    volume: Option<f32>,
+/// A volume on the sink's 0.0-1.0 scale.
+fn volume_arg(s: &str) -> Result<f32, String> {
+    let volume: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=1.0).contains(&volume) {
+        return Err(format!("{volume} is outside 0.0..=1.0"));
+    }
+    Ok(volume)
 }
 
-fn list_devices() -> Result<()> {
-    let host = cpal::default_host();
-    println!("Available input devices:");
-    for device in host.input_devices()? {
-        if let Ok(name) = device.name() {
-            print!("  {name}");
-            if let Ok(cfg) = device.default_input_config() {
-                print!(
-                    " ({}ch, {}Hz, {:?})",
-                    cfg.channels(),
-                    cfg.sample_rate().0,
-                    cfg.sample_format()
-                );
-            }
-            println!();
-        }
+/// A number that must be more than zero.
+fn positive_arg(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(value > 0.0 && value.is_finite()) {
+        return Err(format!("{value} must be more than 0"));
     }
-    Ok(())
+    Ok(value)
 }
 
-fn find_device(name_filter: Option<&str>) -> Result<Device> {
-    let host = cpal::default_host();
-    match name_filter {
-        Some(filter) => {
-            let filter_lower = filter.to_lowercase();
-            host.input_devices()?
-                .find(|d| {
-                    d.name()
-                        .map(|n| n.to_lowercase().contains(&filter_lower))
-                        .unwrap_or(false)
-                })
-                .ok_or_else(|| anyhow!("No input device matching '{filter}'"))
-        }
-        None => host
-            .default_input_device()
-            .ok_or_else(|| anyhow!("No default input device")),
+/// A number that must be zero or more.
+fn non_negative_arg(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(value >= 0.0 && value.is_finite()) {
+        return Err(format!("{value} must be 0 or more"));
     }
+    Ok(value)
 }
 
-fn build_input_stream(
-    device: &Device,
-    sample_rate: u32,
-    tx: mpsc::UnboundedSender<Vec<f32>>,
-) -> Result<cpal::Stream> {
-    let supported = device.default_input_config()?;
-    let config = StreamConfig {
-        channels: 1, // mono is all we need
-        sample_rate: cpal::SampleRate(sample_rate),
-        buffer_size: cpal::BufferSize::Default,
-    };
-
-    let stream = match supported.sample_format() {
-        SampleFormat::F32 => device.build_input_stream(
-            &config,
-            move |data: &[f32], _| {
-                let _ = tx.send(data.to_vec());
-            },
-            |err| eprintln!("Audio error: {err}"),
-            None,
-        )?,
-        SampleFormat::I16 => {
-            let tx = tx;
-            device.build_input_stream(
-                &config,
-                move |data: &[i16], _| {
-                    let floats: Vec<f32> = data.iter().map(|&s| s as f32 / 32768.0).collect();
-                    let _ = tx.send(floats);
-                },
-                |err| eprintln!("Audio error: {err}"),
-                None,
-            )?
-        }
-        fmt => return Err(anyhow!("Unsupported sample format: {fmt:?}")),
-    };
-
-    Ok(stream)
+#[derive(Deserialize)]
+struct VolumeResponse {
+    volume: Option<f32>,
 }
 
 async fn run_calibration(args: &Args) -> Result<()> {
@@ -183,8 +585,17 @@ async fn run_calibration(args: &Args) -> Result<()> {
         args.calibrate
     );
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<f32>>();
-    let stream = build_input_stream(&device, args.sample_rate, tx)?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Batch>();
+    // Calibration counts windows itself and expects callback-sized messages
+    let (stream, _) = build_input_stream(
+        &device,
+        args.sample_rate,
+        Capture::Mono,
+        0,
+        input_gain(args),
+        tx,
+        log_stream_error,
+    )?;
     stream.play()?;
 
     let window_samples = (args.sample_rate as f32 * args.window / 1000.0) as usize;
@@ -198,7 +609,8 @@ async fn run_calibration(args: &Args) -> Result<()> {
 
     while Instant::now() < deadline {
         match tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
-            Ok(Some(samples)) => {
+            Ok(Some(batch)) => {
+                let samples = batch.into_float();
                 ring.extend(&samples);
                 samples_since_rms += samples.len();
 
@@ -208,9 +620,11 @@ async fn run_calibration(args: &Args) -> Result<()> {
                     let dbfs = dsp::rms_to_dbfs(rms);
                     let env = envelope.update(dbfs);
                     levels.push(env);
+                    let units = units(args);
                     eprint!(
-                        "\r  Envelope: {:+6.1} dBFS  |  Raw: {:+6.1} dBFS",
-                        env, dbfs
+                        "\r  Envelope: {:>11}  |  Raw: {:>11}",
+                        units.show(env),
+                        units.show(dbfs)
                     );
                 }
             }
@@ -245,139 +659,1342 @@ async fn run_calibration(args: &Args) -> Result<()> {
     let p50 = sorted[sorted.len() / 2];
     let p90 = sorted[sorted.len() * 9 / 10];
 
+    let units = units(args);
     println!("\nCalibration Results:");
-    println!("  Average:     {:+.1} dBFS", avg);
-    println!("  10th pctile: {:+.1} dBFS  (quiet dialogue)", p10);
-    println!("  50th pctile: {:+.1} dBFS  (median)", p50);
-    println!("  90th pctile: {:+.1} dBFS  (loud moments)", p90);
+    println!("  Average:     {}", units.show(avg));
+    println!("  10th pctile: {}  (quiet dialogue)", units.show(p10));
+    println!("  50th pctile: {}  (median)", units.show(p50));
+    println!("  90th pctile: {}  (loud moments)", units.show(p90));
     println!("  Dynamic range: {:.1} dB", p90 - p10);
-    println!("\nSuggested --target {:.1}", p50);
-    println!(
-        "Suggested --dead-zone {:.1}",
-        ((p90 - p10) / 6.0_f32).max(2.0)
-    );
+    let (target, dead_zone) = dsp::suggested_settings(p10, p50, p90);
+    println!("\nSuggested --target {}", units.number(target));
+    println!("Suggested --dead-zone {dead_zone:.1}");
 
     Ok(())
 }
 
-async fn run_main_loop(args: &Args) -> Result<()> {
+async fn run_gain_report(args: &Args) -> Result<()> {
     let device = find_device(args.device.as_deref())?;
     println!("Device: {}", device.name()?);
+    println!(
+        "Measuring input for {:.0}s — play typical content, including a few quiet moments.\n",
+        args.gain_report
+    );
 
-    let url = format!("http://{}:{}/volume", args.windows_ip, args.port);
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build()?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Batch>();
+    let (stream, _) = build_input_stream(
+        &device,
+        args.sample_rate,
+        Capture::Mono,
+        0,
+        input_gain(args),
+        tx,
+        log_stream_error,
+    )?;
+    stream.play()?;
+
+    let mut meter = GainMeter::new((args.sample_rate as f32 * args.window / 1000.0) as usize);
+    let deadline = Instant::now() + Duration::from_secs_f32(args.gain_report);
+    while Instant::now() < deadline {
+        match tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
+            Ok(Some(batch)) => meter.push(&batch.into_float()),
+            Ok(None) => break,
+            Err(_) => continue,
+        }
+    }
+    drop(stream);
+
+    let Some(levels) = meter.levels() else {
+        println!("No audio detected. Check microphone.");
+        return Ok(());
+    };
+    let units = units(args);
+    println!("Gain Report (at --input-gain {:.1}):", args.input_gain);
+    println!("  Headroom:    {:.1} dB", levels.headroom_db());
+    println!("  Noise floor: {}", units.show(levels.floor_dbfs));
+    match levels.typical_dbfs {
+        Some(t) => println!("  Typical:     {}", units.show(t)),
+        None => println!("  Typical:     (nothing above the floor)"),
+    }
+    println!();
+
+    let target = resolve_target(args)?;
+    match gainstage::advise(&levels, args.input_gain, target, args.silence_threshold) {
+        Advice::NoSignal => {
+            println!("Nothing rose above the noise floor. Play some content, or check the mic.")
+        }
+        Advice::Clipping => println!(
+            "The input is clipping. Turn the mic or OS input level down; --input-gain can't undo it."
+        ),
+        Advice::Keep => println!("Input level is fine. Keep --input-gain {:.1}", args.input_gain),
+        Advice::Change {
+            input_gain_db,
+            limited,
+        } => {
+            println!("Suggested --input-gain {input_gain_db:.1}");
+            match limited {
+                Some(Limit::Headroom) => {
+                    println!("  (limited by headroom: raise the mic or OS input level instead)")
+                }
+                Some(Limit::NoiseFloor) => println!(
+                    "  (limited by the noise floor: it must stay under --silence-threshold {:.0})",
+                    args.silence_threshold
+                ),
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
 
-    // Fetch initial volume
-    let initial_vol = match client.get(&url).send().await {
+/// The controller's current volume: None if it answered without one.
+async fn fetch_initial_volume(client: &reqwest::Client, url: &str) -> Result<Option<f32>> {
+    match client.get(url).send().await {
         Ok(resp) if resp.status().is_success() => {
             let data: VolumeResponse = resp.json().await.unwrap_or(VolumeResponse { volume: None });
-            let v = data.volume.unwrap_or(0.5);
-            println!("Connected. Current volume: {v:.2}");
-            v
+            match data.volume {
+                Some(v) => info!("Connected to {url}. Current volume: {v:.2}"),
+                None => info!("Connected to {url}. No current volume reported"),
+            }
+            Ok(data.volume)
         }
         Ok(resp) => {
-            println!("Controller returned {}. Starting at 0.5", resp.status());
-            0.5
+            warn!("Controller at {url} returned {}", resp.status());
+            Ok(None)
         }
         Err(e) => {
-            eprintln!("Cannot reach controller at {url}: {e}");
-            eprintln!("Start the controller on Windows first.");
-            return Err(anyhow!("Controller unreachable"));
+            warn!("Cannot reach controller at {url}: {e}");
+            Err(anyhow!("Controller unreachable"))
         }
-    };
+    }
+}
 
-    let config = CompressorConfig {
-        target_dbfs: args.target,
+/// A zone's starting volume from all its endpoints, combined by `policy`.
+/// Fails only if none of them can be reached.
+async fn fetch_zone_volume(
+    client: &reqwest::Client,
+    urls: &[String],
+    policy: ReadbackCombine,
+) -> Result<f32> {
+    let mut reported = Vec::with_capacity(urls.len());
+    let mut reachable = false;
+    for url in urls {
+        match fetch_initial_volume(client, url).await {
+            Ok(v) => {
+                reachable = true;
+                reported.push(v);
+            }
+            Err(_) => reported.push(None),
+        }
+    }
+    if !reachable {
+        warn!("Start the controller on Windows first.");
+        return Err(anyhow!("Controller unreachable"));
+    }
+    let v = policy.combine(&reported).unwrap_or(0.5);
+    info!("Starting at {v:.2}");
+    Ok(v)
+}
+
+/// The local output device as a zone's only sink, with its current volume.
+#[cfg(target_os = "macos")]
+fn coreaudio_output() -> Result<(f32, Vec<Box<dyn VolumeSink>>)> {
+    let sink = coreaudio::CoreAudioSink::default_output()?;
+    let v = sink.volume()?;
+    info!("CoreAudio output {}. Current volume: {v:.2}", sink.name());
+    Ok((v, vec![Box::new(sink)]))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn coreaudio_output() -> Result<(f32, Vec<Box<dyn VolumeSink>>)> {
+    Err(anyhow!("--output coreaudio is only available on macOS"))
+}
+
+/// A MIDI controller as a zone's only sink. A fader can't be read back, so
+/// it starts from the middle and moves on the first correction.
+fn midi_output(args: &Args) -> Result<(f32, Vec<Box<dyn VolumeSink>>)> {
+    let sink = midi::MidiSink::open(args.midi_port.as_deref(), args.midi_channel, args.midi_cc)?;
+    info!("MIDI output {}. Starting at 0.50", sink.name());
+    Ok((0.5, vec![Box::new(sink)]))
+}
+
+fn resolve_target(args: &Args) -> Result<f32> {
+    match &args.reference_wav {
+        Some(path) => {
+            let t = reference::measure_wav(path, args.window, args.silence_threshold)?;
+            info!("Reference {}: {t:+.1} dBFS", path.display());
+            Ok(t)
+        }
+        None => Ok(args
+            .target
+            .map_or(DEFAULT_TARGET_DBFS, |t| units(args).dbfs(t))),
+    }
+}
+
+fn units(args: &Args) -> Units {
+    Units::new(args.units, args.full_scale_spl)
+}
+
+fn score_scale(args: &Args) -> Result<ScoreScale> {
+    let units = units(args);
+    ScoreScale::new(
+        args.score_floor
+            .map_or(args.silence_threshold, |v| units.dbfs(v)),
+        args.score_ceiling.map_or(0.0, |v| units.dbfs(v)),
+    )
+}
+
+fn compressor_config(args: &Args, file: &FileConfig, target: f32) -> CompressorConfig {
+    CompressorConfig {
+        target_dbfs: target,
         dead_zone_db: args.dead_zone,
         hysteresis_db: args.hysteresis,
         attack_ms: args.attack,
         release_ms: args.release,
         max_slew_db_per_sec: args.max_slew,
+        duck_ratio: args.duck_ratio,
+        duck_max_db_per_sec: args.duck_max,
+        ramp: args.ramp_max.map(|max| RampConfig {
+            min_db_per_sec: args.ramp_min,
+            max_db_per_sec: max,
+            full_at_db: args.ramp_full,
+        }),
         silence_threshold_dbfs: args.silence_threshold,
         silence_hold_sec: args.silence_hold,
+        variance_window_sec: args.variance_window,
+        variance_threshold_db: args.variance_threshold,
+        reset_on_transition: args.reset_on_transition,
+        gap_hold_ms: args.gap_hold_ms,
+        hold_during_speech: args.hold_during_speech,
+        speech_only: false,
+        learned: None,
+        low_volume_compensation: args.low_volume_compensation,
+        regions: file
+            .regions
+            .iter()
+            .cloned()
+            .map(|mut r| {
+                let units = units(args);
+                r.from = r.from.map(|v| units.dbfs(v));
+                r.to = r.to.map(|v| units.dbfs(v));
+                r
+            })
+            .collect(),
+        scenes: file
+            .scenes
+            .iter()
+            .cloned()
+            .map(|mut s| {
+                let units = units(args);
+                s.level = s.level.map(|r| r.map(|v| units.dbfs(v)));
+                s.target = units.dbfs(s.target);
+                s
+            })
+            .collect(),
+        control_timescale: args.control_timescale,
+        recalibration_window_sec: file
+            .recalibrate
+            .as_ref()
+            .map_or(0.0, |r| r.window_minutes * 60.0),
+        adaptive: (args.adaptive_window > 0.0).then_some(AdaptiveConfig {
+            window_sec: args.adaptive_window,
+            update_sec: args.adaptive_update,
+            quiet_percentile: args.adaptive_quiet_pct,
+            loud_percentile: args.adaptive_loud_pct,
+        }),
+        median: (args.median_window > 0.0).then_some(MedianConfig {
+            window_sec: args.median_window,
+            above_db: args.median_above,
+            below_db: args.median_below,
+        }),
         rms_window_ms: args.window,
         sample_rate: args.sample_rate,
         vol_min: args.vol_min,
         vol_max: args.vol_max,
+    }
+}
+
+/// --accumulate-ms as a frame count at the capture rate.
+fn accumulate_frames(args: &Args) -> usize {
+    (args.sample_rate as f32 * args.accumulate_ms.max(0.0) / 1000.0) as usize
+}
+
+/// A send cooldown from --min-interval and friends.
+fn cooldown(args: &Args) -> Cooldown {
+    Cooldown::new(Duration::from_secs_f32(args.min_interval), args.cooldown_on)
+        .with_throttle(Duration::from_secs_f32(args.max_interval))
+        .with_adaptive(
+            Duration::from_secs_f32(args.adaptive_cooldown_max),
+            args.adaptive_cooldown_rate,
+        )
+        .with_retry_jitter(args.retry_jitter, fastrand::Rng::new())
+}
+
+/// --input-gain as a linear factor.
+fn input_gain(args: &Args) -> f32 {
+    10.0_f32.powf(args.input_gain / 20.0)
+}
+
+/// Zones from the config file, or a single zone from --device and
+/// --windows-ip/--port when the file defines none.
+fn zone_configs(args: &Args, file: &FileConfig) -> Vec<ZoneConfig> {
+    if !file.zones.is_empty() {
+        return file.zones.clone();
+    }
+    vec![ZoneConfig {
+        name: "default".to_string(),
+        device: args.device.clone(),
+        // --port fills in when the endpoint becomes a URL
+        endpoints: vec![args.windows_ip.clone()],
+        control: Default::default(),
+    }]
+}
+
+/// A capture's samples for its zones, measured once for all of them.
+#[derive(Clone)]
+struct Captured {
+    samples: Vec<f32>,
+    readings: Vec<Reading>,
+}
+
+impl Captured {
+    fn analyzed(analyzer: &mut Analyzer, samples: Vec<f32>) -> Self {
+        let readings = analyzer.push(&samples);
+        Self { samples, readings }
+    }
+
+    /// A Q15 batch: the RMS window sums the integers, the float copy is for
+    /// the filters and the zones.
+    #[cfg(feature = "fixed-point")]
+    fn analyzed_q15(analyzer: &mut Analyzer, q15: Vec<i16>) -> Self {
+        let samples = fixed::to_f32(&q15);
+        let readings = analyzer.push_q15(&q15, &samples);
+        Self { samples, readings }
+    }
+}
+
+/// What a capture needs to be reopened on the same channel after hotplug.
+struct Rebuild {
+    filter: Option<String>,
+    zone_tx: mpsc::UnboundedSender<Batch>,
+    capture: Capture,
+    channels: usize,
+    /// Zones listening to it
+    members: Vec<usize>,
+    /// The device it last opened
+    device_name: String,
+}
+
+async fn run_main_loop(args: &Args, file: &FileConfig) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()?;
+    let target = resolve_target(args)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<(usize, Captured)>();
+    let (outcome_tx, mut outcome_rx) = mpsc::unbounded_channel::<SendOutcome>();
+    // Volumes a WebSocket endpoint pushes after a change at the device
+    let (push_tx, mut push_rx) = mpsc::unbounded_channel::<(usize, f32)>();
+    let mut zones = Vec::new();
+    let mut streams = Vec::new();
+    // Zones naming the same device share one capture stream
+    let mut captures: Vec<(Option<String>, cpal::Device, Vec<usize>)> = Vec::new();
+    let coalesce = Duration::from_millis(args.coalesce_ms);
+    let mut learning = match &args.learn_file {
+        Some(path) => Some((path, LearnedTargets::load(path)?)),
+        None => None,
     };
-    let mut compressor = Compressor::new(config, initial_vol);
+    // With --input-url every zone listens to the stream
+    let mut listeners = Vec::new();
+    let zcs = zone_configs(args, file);
+    // Each zone's settings, for the analyzer of the capture it's on
+    let mut analysis = Vec::new();
+    for (i, zc) in zcs.iter().cloned().enumerate() {
+        let device_name = match &args.input_url {
+            Some(url) => {
+                if zc.device.is_some() {
+                    bail!(
+                        "Zone '{}' names a device, but --input-url replaces capture",
+                        zc.name
+                    );
+                }
+                listeners.push(i);
+                url.clone()
+            }
+            None => {
+                let capture = match captures.iter().position(|(name, ..)| *name == zc.device) {
+                    Some(c) => c,
+                    None => {
+                        let device = find_device(zc.device.as_deref())?;
+                        captures.push((zc.device.clone(), device, Vec::new()));
+                        captures.len() - 1
+                    }
+                };
+                captures[capture].2.push(i);
+                captures[capture].1.name()?
+            }
+        };
+        info!("Zone {}: device {device_name}", zc.name);
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<f32>>();
-    let stream = build_input_stream(&device, args.sample_rate, tx)?;
-    stream.play()?;
+        let (initial_vol, sinks) = match args.output {
+            Output::Http | Output::WsClient => {
+                let urls: Vec<String> = zc
+                    .endpoints
+                    .iter()
+                    .map(|e| config::endpoint_url(e, args.port))
+                    .collect();
+                let initial_vol = fetch_zone_volume(&client, &urls, args.readback_combine).await?;
+                let sinks: Vec<Box<dyn VolumeSink>> = urls
+                    .into_iter()
+                    .map(|url| match args.output {
+                        Output::WsClient => Box::new(
+                            ws::WsSink::new(ws::ws_url(&url)).with_pushes(i, push_tx.clone()),
+                        ) as Box<dyn VolumeSink>,
+                        _ => Box::new(HttpSink::new(client.clone(), url)),
+                    })
+                    .collect();
+                (initial_vol, sinks)
+            }
+            Output::CoreAudio => coreaudio_output()?,
+            Output::Midi => midi_output(args)?,
+        };
+        #[cfg(unix)]
+        let sinks = {
+            let mut sinks = sinks;
+            if let Some(path) = &args.fifo {
+                sinks.push(Box::new(fifo::FifoSink::new(path, &zc.name)?));
+            }
+            sinks
+        };
+        let learned = learning.as_ref().map(|(_, learned)| learned);
+        let cc = zone_compressor_config(args, file, target, &zc, &device_name, learned);
+        analysis.push(cc.clone());
+        let params = (!file.params.is_empty())
+            .then(|| ParamSet::new(&file.params, args.sample_rate, 1000.0 / args.window))
+            .transpose()?;
+        if let Some(params) = &params {
+            info!("Zone {}: params {}", zc.name, params.describe());
+        }
+        let zone = Zone::new(
+            zc.name,
+            Compressor::new(cc, initial_vol),
+            initial_vol,
+            SendGate::new(args.volume_steps, args.send_deadband),
+            cooldown(args),
+            SettleTracker::new(Duration::from_secs_f32(args.settle_time), Instant::now()),
+            Sender::spawn(i, sinks, coalesce, outcome_tx.clone()),
+        )
+        .with_display(DisplaySmoother::new(
+            args.display_smoothing,
+            1000.0 / args.window,
+        ))
+        .with_score(score_scale(args)?)
+        .with_units(units(args));
+        zones.push(match params {
+            Some(params) => zone.with_params(params),
+            None => zone,
+        });
+    }
+    let (hotplug_tx, mut hotplug_rx) = mpsc::unbounded_channel::<usize>();
+    let mut rebuilds = Vec::new();
+    for (k, (filter, device, members)) in captures.into_iter().enumerate() {
+        let (zone_tx, mut zone_rx) = mpsc::unbounded_channel::<Batch>();
+        // Balance needs the channels apart; they're mixed down here instead
+        let capture = match args.imbalance_warn_db {
+            Some(_) => Capture::Interleaved,
+            None => Capture::Mono,
+        };
+        let (stream, channels) = build_input_stream(
+            &device,
+            args.sample_rate,
+            capture,
+            accumulate_frames(args),
+            input_gain(args),
+            zone_tx.clone(),
+            device_events(k, &hotplug_tx),
+        )?;
+        stream.play()?;
+        streams.push(stream);
+        let device_name = device.name()?;
+        rebuilds.push(Rebuild {
+            filter,
+            zone_tx,
+            capture,
+            channels,
+            members: members.clone(),
+            device_name: device_name.clone(),
+        });
+        let mut balance = args.imbalance_warn_db.filter(|_| channels >= 2).map(|db| {
+            let window = (args.sample_rate as f32 * imbalance::WINDOW_SEC) as usize;
+            imbalance::ImbalanceMeter::new(channels, db, args.silence_threshold, window)
+        });
+        let mut chain = preprocess::Chain::new(&file.preprocess, args.sample_rate)?;
+        let mut analyzer = Analyzer::new(&analysis[members[0]]);
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(batch) = zone_rx.recv().await {
+                let captured = match batch {
+                    // The integer window can't see through the chain's filters
+                    #[cfg(feature = "fixed-point")]
+                    Batch::Q15(q15) if chain.is_empty() => {
+                        Captured::analyzed_q15(&mut analyzer, q15)
+                    }
+                    batch => {
+                        let samples = batch.into_float();
+                        let mut samples = match capture {
+                            Capture::Interleaved => {
+                                if let Some(meter) = &mut balance {
+                                    report_imbalance(&device_name, meter, &samples);
+                                }
+                                mix::downmix(&samples, channels)
+                            }
+                            Capture::Mono => samples,
+                        };
+                        chain.process(&mut samples);
+                        Captured::analyzed(&mut analyzer, samples)
+                    }
+                };
+                for &i in &members {
+                    if tx.send((i, captured.clone())).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    if let Some(url) = &args.input_url {
+        let (stream_tx, mut stream_rx) = mpsc::unbounded_channel::<Vec<f32>>();
+        let input = netinput::NetInput {
+            url: url.clone(),
+            sample_rate: args.sample_rate,
+            accumulate_frames: accumulate_frames(args),
+            input_gain: input_gain(args),
+        };
+        tokio::spawn(netinput::run(input, stream_tx));
+        let mut chain = preprocess::Chain::new(&file.preprocess, args.sample_rate)?;
+        let mut analyzer = Analyzer::new(&analysis[0]);
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(mut samples) = stream_rx.recv().await {
+                chain.process(&mut samples);
+                let captured = Captured::analyzed(&mut analyzer, samples);
+                for &i in &listeners {
+                    if tx.send((i, captured.clone())).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    drop(tx);
 
-    println!(
-        "Target: {:+.1} dBFS | Dead zone: +/-{:.1} dB | Attack: {:.0}ms | Release: {:.0}ms",
-        args.target, args.dead_zone, args.attack, args.release
+    let registration = args
+        .register_url
+        .as_deref()
+        .map(|url| (url, Registration::new(capabilities(args))));
+    if let Some((url, info)) = &registration {
+        register::register(&client, url, info).await;
+    }
+
+    let mut budget = args.max_sends_per_session.map(|max| {
+        let reset_every = args
+            .send_budget_reset_hours
+            .map(|h| Duration::from_secs_f32(h * 3600.0));
+        SendBudget::new(max, reset_every)
+    });
+    let (nudge_tx, mut nudge_rx) = mpsc::unbounded_channel::<status::Nudge>();
+    let status = SharedStatus::default();
+    status.lock().unwrap().zones = zones.iter().map(Zone::status).collect();
+    status.lock().unwrap().sends_remaining = budget.as_ref().map(SendBudget::remaining);
+    status.lock().unwrap().units = args.units;
+    if let Some(port) = args.status_port {
+        let nudges = learning.is_some().then(|| nudge_tx.clone());
+        let addr = status::serve(([0, 0, 0, 0], port).into(), status.clone(), nudges).await?;
+        info!("Status: http://{addr}/status");
+    }
+
+    let units = units(args);
+    info!(
+        "Target: {} | Dead zone: +/-{:.1} dB | Attack: {:.0}ms | Release: {:.0}ms",
+        units.show(target),
+        args.dead_zone,
+        args.attack,
+        args.release
     );
-    println!("Listening... Ctrl+C to stop.\n");
+    info!("Listening... Ctrl+C to stop.");
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
     ctrlc_handler(r);
 
-    let min_interval = Duration::from_secs_f32(args.min_interval);
-    let mut last_send = Instant::now() - min_interval; // allow immediate first send
-    let mut last_sent_vol: Option<f32> = None;
+    let monitor = match args.monitor_output.as_deref() {
+        Some(name) => {
+            let filter = (name != "default").then_some(name);
+            let device = find_output_device(filter)?;
+            info!("Monitor: {}", device.name()?);
+            let (stream, feed) = monitor::build_monitor_stream(&device, args.sample_rate)?;
+            stream.play()?;
+            Some((stream, feed))
+        }
+        None => None,
+    };
+
+    let mut schedule = file
+        .recalibrate
+        .as_ref()
+        .map(|r| RecalibrationSchedule::new(r, args.timezone))
+        .transpose()?;
+    if let Some(schedule) = &mut schedule {
+        // Starts the clock
+        schedule.due(chrono::Utc::now());
+        print_next_recalibration(schedule);
+    }
+
+    let mut killswitch = args.killswitch_file.clone().map(KillSwitch::new);
+    let mut process_pause = args
+        .pause_while_process
+        .clone()
+        .map(|name| ProcessPause::spawn(name, Box::new(pause::process_running)));
+
+    let mut selftests: Vec<Option<SelfTest>> = zones
+        .iter()
+        .map(|_| {
+            args.selftest_min_level
+                .map(|min| SelfTest::new(min, args.sample_rate))
+        })
+        .collect();
+
+    #[cfg(unix)]
+    let mut shm = args
+        .shm
+        .as_deref()
+        .map(|name| shm::ShmRing::create(name, shm::DEFAULT_SLOTS))
+        .transpose()?;
+
+    let mut notches: Vec<Option<NotchDetector>> = zones
+        .iter()
+        .map(|_| {
+            args.notch_center
+                .map(|center| {
+                    NotchDetector::new(
+                        center,
+                        args.notch_bandwidth,
+                        args.notch_threshold,
+                        args.sample_rate,
+                        (args.sample_rate as f32 * args.window / 1000.0) as usize,
+                    )
+                })
+                .transpose()
+        })
+        .collect::<Result<_>>()?;
+
+    let mut inactivity: Vec<Option<InactivityMonitor>> = zones
+        .iter()
+        .map(|_| {
+            args.inactivity_warn
+                .map(|min| InactivityMonitor::new(Duration::from_secs_f32(min * 60.0)))
+        })
+        .collect();
+
+    let mut standbys: Vec<Option<Standby>> = zones
+        .iter()
+        .map(|_| {
+            (args.standby_after > 0.0).then(|| {
+                let after = Duration::from_secs_f32(args.standby_after * 60.0);
+                Standby::new(after, args.silence_threshold, args.sample_rate)
+            })
+        })
+        .collect();
+
+    let started = Instant::now();
+    let mut heartbeat = args.heartbeat_url.as_deref().map(|url| {
+        let interval = Duration::from_secs_f32(args.heartbeat_interval);
+        (url, HeartbeatTimer::new(interval, started))
+    });
+    let (beat_tx, mut beat_rx) = mpsc::unbounded_channel::<Result<()>>();
+    let mut beat_in_flight = false;
+    let mut beat_health = BeatHealth::new(args.heartbeat_failure);
+
+    let bus = EventBus::new(256);
+    let dashboard = args.tui.then(|| {
+        let (rx, running) = (bus.subscribe(), running.clone());
+        std::thread::spawn(move || tui::run(rx, running, units))
+    });
+
+    let mut hotplug = Debouncer::new(Duration::from_millis(args.hotplug_debounce));
 
     while running.load(Ordering::Relaxed) {
-        match tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
-            Ok(Some(samples)) => {
-                if let Some(result) = compressor.process(&samples) {
-                    let now = Instant::now();
-                    let vol_changed = last_sent_vol
-                        .map(|v| (result.volume - v).abs() > 0.005)
-                        .unwrap_or(true);
-
-                    if vol_changed && now.duration_since(last_send) >= min_interval {
-                        let req = VolumeRequest {
-                            volume: result.volume,
-                        };
-                        match client.post(&url).json(&req).send().await {
-                            Ok(resp) if resp.status().is_success() => {
-                                last_send = now;
-                                last_sent_vol = Some(result.volume);
-                            }
-                            Ok(resp) => {
-                                eprint!("\rController: {}", resp.status());
-                            }
-                            Err(_) => {} // will retry next cycle
+        for k in hotplug.due(Instant::now()) {
+            let rebuild = &mut rebuilds[k];
+            let rebuilt = find_device(rebuild.filter.as_deref()).and_then(|device| {
+                let (stream, now) = build_input_stream(
+                    &device,
+                    args.sample_rate,
+                    rebuild.capture,
+                    accumulate_frames(args),
+                    input_gain(args),
+                    rebuild.zone_tx.clone(),
+                    device_events(k, &hotplug_tx),
+                )?;
+                stream.play()?;
+                Ok((stream, device.name()?, now))
+            });
+            match rebuilt {
+                Ok((stream, name, now)) => {
+                    streams[k] = stream;
+                    info!("Capture reopened on {name}");
+                    let channels = rebuild.channels;
+                    if rebuild.capture == Capture::Interleaved && now != channels {
+                        warn!("{name} now has {now} channel(s), not {channels}; restart to follow");
+                    }
+                    // A different device may bring different `devices` settings
+                    let profile = |name| file.device_profile(name).map(|(key, _)| key);
+                    if profile(&name) != profile(&rebuild.device_name) {
+                        if profile(&name).is_none() {
+                            info!("No device settings for {name}");
+                        }
+                        let learned = learning.as_ref().map(|(_, learned)| learned);
+                        for &i in &rebuild.members {
+                            let cc =
+                                zone_compressor_config(args, file, target, &zcs[i], &name, learned);
+                            zones[i].reconfigure(cc);
                         }
                     }
+                    rebuild.device_name = name;
+                }
+                Err(e) => {
+                    warn!("Reopening capture: {e}; trying again");
+                    hotplug.event(k, Instant::now());
+                }
+            }
+        }
 
-                    let status = if result.silent {
-                        "SILENT"
-                    } else if result.delta_db > 0.01 {
-                        "  UP  "
-                    } else if result.delta_db < -0.01 {
-                        " DOWN "
-                    } else {
-                        "  OK  "
-                    };
+        if let Some((url, timer)) = &mut heartbeat {
+            // One at a time: a slow server shouldn't pile them up
+            if timer.due(Instant::now()) && !beat_in_flight {
+                beat_in_flight = true;
+                let (client, url, tx) = (client.clone(), url.to_string(), beat_tx.clone());
+                let beat = Beat::new(started, Instant::now());
+                tokio::spawn(async move {
+                    let _ = tx.send(heartbeat::send(&client, &url, &beat).await);
+                });
+            }
+        }
 
-                    let sent_marker = if last_sent_vol == Some(result.volume) {
+        let (i, captured) = tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            Some(outcome) = outcome_rx.recv() => {
+                let zone = &mut zones[outcome.zone];
+                zone.complete(&outcome, Instant::now());
+                status.lock().unwrap().zones[outcome.zone] = zone.status();
+                // The dashboard follows the first zone
+                let first = outcome.zone == 0;
+                if first && outcome.failures.is_empty() {
+                    bus.publish(Event::Sent {
+                        volume: outcome.volume,
+                    });
+                }
+                for (endpoint, error) in outcome.failures {
+                    if !args.tui {
+                        warn!("{endpoint}: {error}");
+                    }
+                    if first {
+                        bus.publish(Event::SendFailed { error });
+                    }
+                }
+                continue;
+            }
+            Some(result) = beat_rx.recv() => {
+                beat_in_flight = false;
+                match beat_health.record(result.is_ok()) {
+                    Some(Transition::Engaged) => warn!("Heartbeats failing: holding"),
+                    Some(Transition::Released) => info!("Heartbeats back: resuming"),
+                    None => {}
+                }
+                if let Err(e) = result {
+                    let error = format!("heartbeat: {e}");
+                    if !args.tui {
+                        warn!("{error}");
+                    }
+                    if beat_health.marks_unhealthy() {
+                        // Same as a failed send: every zone's controller is suspect
+                        for zone in &mut zones {
+                            zone.mark_unhealthy();
+                        }
+                        status.lock().unwrap().zones = zones.iter().map(Zone::status).collect();
+                    }
+                    bus.publish(Event::SendFailed { error });
+                }
+                continue;
+            }
+            Some(nudge) = nudge_rx.recv() => {
+                if let Some((path, learned)) = &mut learning {
+                    apply_nudge(&mut zones, learned, &nudge);
+                    if let Err(e) = learned.save(path) {
+                        error!("{e:#}");
+                    }
+                }
+                continue;
+            }
+            Some((i, volume)) = push_rx.recv() => {
+                if zones[i].adopt(volume) && !args.tui {
+                    info!("{}: volume set to {volume:.2} at the device", zones[i].name);
+                }
+                status.lock().unwrap().zones[i] = zones[i].status();
+                continue;
+            }
+            Some(k) = hotplug_rx.recv() => {
+                hotplug.event(k, Instant::now());
+                continue;
+            }
+            // Its own branch, so the hold and --killswitch-volume take effect
+            // even while no audio arrives
+            Some(change) = async {
+                match &mut killswitch {
+                    Some(ks) => Some(ks.changed().await),
+                    None => None,
+                }
+            } => {
+                if let Some(ks) = &killswitch {
+                    killswitch_changed(ks, change, &mut zones, args.killswitch_volume);
+                    status.lock().unwrap().zones = zones.iter().map(Zone::status).collect();
+                }
+                continue;
+            }
+            _ = tokio::time::sleep(Duration::from_millis(100)) => continue,
+        };
+        let samples = captured.samples.as_slice();
+
+        if let Some(verdict) = selftests[i].as_mut().and_then(|t| t.push(samples)) {
+            selftests[i] = None;
+            report_selftest(
+                &zones[i].name,
+                verdict,
+                args.selftest_min_level.unwrap_or_default(),
+            );
+        }
+
+        #[cfg(unix)]
+        if let Some(ring) = &mut shm {
+            ring.write(&shm::Features::measure(i, samples));
+        }
+
+        let now = Instant::now();
+        if let Some(pause) = &mut process_pause {
+            match pause.poll() {
+                Some(Transition::Engaged) => {
+                    info!("{} is running: pausing", pause.name());
+                }
+                Some(Transition::Released) => {
+                    info!("{} exited: resuming", pause.name());
+                }
+                None => {}
+            }
+        }
+        if let Some(standby) = &mut standbys[i] {
+            match standby.observe(samples, now) {
+                Some(Transition::Engaged) => {
+                    info!(
+                        "{}: silent for {} min, standing by",
+                        zones[i].name, args.standby_after
+                    );
+                }
+                Some(Transition::Released) => {
+                    info!("{}: audio is back, waking", zones[i].name);
+                    zones[i].warm_up();
+                }
+                None => {}
+            }
+            if standby.asleep() {
+                continue;
+            }
+        }
+        if let Some(notch) = &mut notches[i] {
+            match notch.push(samples, now) {
+                Some(Transition::Engaged) => {
+                    info!(
+                        "{}: tone at {:.0} Hz, volume to {:.2}",
+                        zones[i].name,
+                        args.notch_center.unwrap_or_default(),
+                        args.notch_volume
+                    );
+                    zones[i].force(args.notch_volume);
+                }
+                Some(Transition::Released) => {
+                    info!("{}: tone gone, resuming", zones[i].name);
+                }
+                None => {}
+            }
+        }
+        let held = beat_health.holds()
+            || killswitch.as_ref().is_some_and(KillSwitch::engaged)
+            || process_pause.as_ref().is_some_and(ProcessPause::paused)
+            || notches[i].as_ref().is_some_and(NotchDetector::active);
+
+        if let Some(schedule) = &mut schedule {
+            if schedule.due(chrono::Utc::now()) {
+                for zone in &mut zones {
+                    match zone.recalibrate() {
+                        Some(r) => info!(
+                            "{}: recalibrated target {} -> {}, dead zone {:.1} -> {:.1} dB",
+                            zone.name,
+                            units.show(r.old_target_dbfs),
+                            units.show(r.target_dbfs),
+                            r.old_dead_zone_db,
+                            r.dead_zone_db
+                        ),
+                        None => warn!("{}: not enough audio to recalibrate", zone.name),
+                    }
+                }
+                print_next_recalibration(schedule);
+            }
+        }
+
+        if let Some((_, feed)) = monitor.as_ref().filter(|_| i == 0) {
+            feed.push(samples);
+        }
+
+        let zone = &mut zones[i];
+        let was_saturated = zone.saturated();
+        let was_scene = zone.scene();
+        let leveled = level(zone, &captured, held, budget.as_mut(), now);
+        if let Some(budget) = &budget {
+            // A period restart refills it without any send
+            status.lock().unwrap().sends_remaining = Some(budget.remaining());
+        }
+        let Some(result) = leveled else {
+            continue;
+        };
+        if result.scene != was_scene && !args.tui {
+            match result.scene {
+                Some(s) => info!("{}: scene {}", zone.name, zone.scene_name(s)),
+                None => info!("{}: no scene matches", zone.name),
+            }
+        }
+        if let Some(monitor) = &mut inactivity[i] {
+            report_inactivity(&zone.name, monitor, &result, args, now);
+        }
+        if result.saturated != was_saturated && !args.tui {
+            match result.saturated {
+                Some(dsp::Saturation::Max) => {
+                    warn!("{}: saturated at --vol-max {:.2}", zone.name, args.vol_max)
+                }
+                Some(dsp::Saturation::Min) => {
+                    warn!("{}: saturated at --vol-min {:.2}", zone.name, args.vol_min)
+                }
+                None => info!("{}: no longer saturated", zone.name),
+            }
+        }
+        if i == 0 {
+            bus.publish(Event::Reading {
+                envelope_dbfs: result.envelope_dbfs,
+                display_dbfs: zone.display_dbfs().unwrap_or(result.envelope_dbfs),
+                target_dbfs: result.target_dbfs,
+                delta_db: result.delta_db,
+                volume: result.volume,
+                silent: result.silent,
+                loudness: result.loudness,
+                score: zone.score().unwrap_or(0),
+            });
+        }
+        if zone.check_settled(now) {
+            if i == 0 {
+                bus.publish(Event::Settled);
+            }
+            if !args.tui {
+                info!("{}: settled", zone.name);
+            }
+        }
+        status.lock().unwrap().zones[i] = zone.status();
+
+        // The live line only makes sense on a terminal
+        if args.tui || !args.log_target.live() {
+            continue;
+        }
+
+        if zones.len() > 1 {
+            let line: Vec<String> = zones
+                .iter()
+                .map(|z| {
+                    let s = z.status();
+                    let marker = if s.last_sent == Some(z.gate().effective(s.volume)) {
                         "*"
                     } else {
                         " "
                     };
+                    format!(
+                        "{}: {:>11} {:.3}{marker}",
+                        s.name,
+                        units.show(z.display_dbfs().unwrap_or(f32::NAN)),
+                        s.volume
+                    )
+                })
+                .collect();
+            eprint!("\r{}", line.join(" | "));
+            continue;
+        }
 
-                    eprint!(
-                        "\r[{status}] Env: {:+6.1} dBFS | \u{0394}: {:+5.2} dB | Vol: {:.3} {sent_marker}",
-                        result.envelope_dbfs, result.delta_db, result.volume
-                    );
+        let status = if held {
+            " HELD "
+        } else if result.silent {
+            "SILENT"
+        } else if result.delta_db > 0.01 {
+            "  UP  "
+        } else if result.delta_db < -0.01 {
+            " DOWN "
+        } else {
+            "  OK  "
+        };
+
+        let gate = zones[i].gate();
+        let sent_marker = if gate.last_sent() == Some(gate.effective(result.volume)) {
+            "*"
+        } else {
+            " "
+        };
+        let volatile_marker = if result.volatile { "~" } else { " " };
+
+        eprint!(
+            "\r[{status}]{volatile_marker}Env: {:>11} | \u{0394}: {:+5.2} dB | Vol: {:.3} {sent_marker}",
+            units.show(zones[i].display_dbfs().unwrap_or(result.envelope_dbfs)),
+            result.delta_db,
+            result.volume
+        );
+    }
+
+    running.store(false, Ordering::Relaxed);
+    drop(streams);
+    if let Some(handle) = dashboard {
+        if let Ok(Err(e)) = handle.join() {
+            error!("Dashboard error: {e}");
+        }
+    }
+    info!("Stopping.");
+    if let Some((url, info)) = &registration {
+        register::deregister(&client, url, info).await;
+    }
+    Ok(())
+}
+
+/// The kill switch file came or went: on engage, hold every zone and send
+/// it `volume` (--killswitch-volume).
+fn killswitch_changed(
+    ks: &KillSwitch,
+    change: Transition,
+    zones: &mut [Zone],
+    volume: Option<f32>,
+) {
+    match change {
+        Transition::Engaged => {
+            info!("Kill switch {} present: holding", ks.path().display());
+            for zone in zones {
+                // Now, not at the next capture: a release before one arrives
+                // still resumes from what the sink has
+                zone.hold(true);
+                if let Some(volume) = volume {
+                    zone.force(volume);
+                }
+            }
+        }
+        Transition::Released => info!("Kill switch removed: resuming"),
+    }
+}
+
+/// One control update for `zone`: analyze `samples` and send the volume
+/// unless `held` or out of `budget`. While held the volume stands still,
+/// and on release it continues from what was last sent.
+fn level(
+    zone: &mut Zone,
+    captured: &Captured,
+    held: bool,
+    mut budget: Option<&mut SendBudget>,
+    now: Instant,
+) -> Option<ProcessResult> {
+    if budget.as_mut().is_some_and(|b| b.refresh(now)) {
+        info!("Send budget reset");
+    }
+    let held = held || budget.as_deref().is_some_and(|b| !b.available());
+    zone.hold(held);
+    let result = zone.apply(&captured.samples, &captured.readings)?;
+    if !held && zone.dispatch(result.volume, now).is_some() && budget.is_some_and(SendBudget::spend)
+    {
+        warn!("Send budget exhausted: holding volume");
+    }
+    Some(result)
+}
+
+/// Move the target for what the nudged zones are playing (once per content
+/// type, however many zones play it), then hand every zone the updated
+/// offsets: they share one file.
+fn apply_nudge(zones: &mut [Zone], learned: &mut LearnedTargets, nudge: &status::Nudge) {
+    let playing: BTreeSet<ContentType> = zones
+        .iter()
+        .filter(|zone| nudge.zone.as_ref().is_none_or(|name| *name == zone.name))
+        .filter_map(Zone::content_type)
+        .collect();
+    for content in playing {
+        let offset = learned.nudge(content, nudge.db);
+        info!(
+            "{} target now {offset:+.1} dB from --target",
+            content.name()
+        );
+    }
+    for zone in zones {
+        zone.set_learned(learned.clone());
+    }
+}
+
+/// Stream errors on capture `k`, logged and passed on for a rebuild.
+fn device_events(
+    k: usize,
+    tx: &mpsc::UnboundedSender<usize>,
+) -> impl FnMut(cpal::StreamError) + Send + 'static {
+    let tx = tx.clone();
+    move |err| {
+        log_stream_error(err);
+        let _ = tx.send(k);
+    }
+}
+
+/// A zone's settings: the flags, then the `devices` entry for the device
+/// it listens on, then the zone's own overrides.
+fn zone_compressor_config(
+    args: &Args,
+    file: &FileConfig,
+    target: f32,
+    zc: &ZoneConfig,
+    device_name: &str,
+    learned: Option<&LearnedTargets>,
+) -> CompressorConfig {
+    let mut cc = compressor_config(args, file, target);
+    apply_device_profile(args, file, device_name, &mut cc);
+    zc.control.apply(&mut cc, units(args));
+    cc.learned = learned.cloned();
+    cc
+}
+
+/// The config file's `devices` settings for whichever input is in use.
+fn apply_device_profile(
+    args: &Args,
+    file: &FileConfig,
+    device_name: &str,
+    cc: &mut CompressorConfig,
+) {
+    if let Some((key, control)) = file.device_profile(device_name) {
+        control.apply(cc, units(args));
+        info!("Device settings '{key}' for {device_name}");
+    }
+}
+
+async fn run_per_channel_loop(args: &Args, file: &FileConfig) -> Result<()> {
+    // One capture driving one controller's channels: nothing here runs zones
+    let zone_only = [
+        ("zones", !file.zones.is_empty()),
+        ("recalibrate", file.recalibrate.is_some()),
+        ("preprocess", !file.preprocess.is_empty()),
+        ("params", !file.params.is_empty()),
+    ];
+    if let Some((key, _)) = zone_only.iter().find(|(_, set)| *set) {
+        bail!("config {key:?} is not supported with --per-channel");
+    }
+    let device = find_device(args.device.as_deref())?;
+    info!("Device: {}", device.name()?);
+
+    let master_url = config::endpoint_url(&args.windows_ip, args.port);
+    let url = format!("{master_url}/channels");
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(2))
+        .build()?;
+
+    let initial_vol = fetch_zone_volume(
+        &client,
+        std::slice::from_ref(&master_url),
+        ReadbackCombine::First,
+    )
+    .await?;
+    let target = resolve_target(args)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<Batch>();
+    let (stream, input_channels) = build_input_stream(
+        &device,
+        args.sample_rate,
+        Capture::Interleaved,
+        accumulate_frames(args),
+        input_gain(args),
+        tx,
+        log_stream_error,
+    )?;
+    stream.play()?;
+
+    let map: Vec<usize> = if args.channel_map.is_empty() {
+        (0..input_channels).collect()
+    } else {
+        args.channel_map.clone()
+    };
+    let levelled = input_channels.min(map.len());
+    if let Some(channel) = args.dialogue_channel.filter(|&c| c >= levelled) {
+        bail!("--dialogue-channel {channel} is not among the {levelled} channel(s) levelled");
+    }
+    let mut cc = compressor_config(args, file, target);
+    apply_device_profile(args, file, &device.name()?, &mut cc);
+    let mut comps = ChannelCompressors::new(
+        cc,
+        &map,
+        input_channels,
+        initial_vol,
+        args.volume_steps,
+        args.send_deadband,
+        args.dialogue_channel.map(|channel| Dialogue {
+            channel,
+            boost_db: args.dialogue_boost,
+        }),
+    );
+
+    info!(
+        "Per-channel: {input_channels} input channel(s) -> outputs {map:?} | Target: {}",
+        units(args).show(target)
+    );
+    if let Some(channel) = args.dialogue_channel {
+        info!(
+            "Dialogue: input {channel}, levelled on speech to {}",
+            units(args).show(target + args.dialogue_boost)
+        );
+    }
+    info!("Listening... Ctrl+C to stop.");
+
+    let running = Arc::new(AtomicBool::new(true));
+    ctrlc_handler(running.clone());
+
+    let mut cooldown = cooldown(args);
+
+    while running.load(Ordering::Relaxed) {
+        match tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
+            Ok(Some(batch)) => {
+                if comps
+                    .process(&batch.into_float())
+                    .iter()
+                    .all(Option::is_none)
+                {
+                    continue;
                 }
+
+                let now = Instant::now();
+                if cooldown.ready(now) {
+                    if let Some(req) = comps.pending_request() {
+                        let ok = match client.post(&url).json(&req).send().await {
+                            Ok(resp) if resp.status().is_success() => {
+                                comps.mark_sent(&req);
+                                true
+                            }
+                            Ok(resp) => {
+                                warn!("Controller: {}", resp.status());
+                                false
+                            }
+                            Err(_) => false, // will retry next cycle
+                        };
+                        cooldown.record(now, ok);
+                        cooldown.record_latency(now.elapsed());
+                    }
+                }
+
+                if !args.log_target.live() {
+                    continue;
+                }
+                let line: Vec<String> = comps
+                    .volumes()
+                    .map(|(ch, v)| format!("Ch{ch}: {:.3}", v.unwrap_or(initial_vol)))
+                    .collect();
+                eprint!("\r{}", line.join(" | "));
             }
             Ok(None) => break,
             Err(_) => continue,
         }
     }
 
-    eprintln!("\nStopping.");
+    info!("Stopping.");
     Ok(())
 }
 
+fn report_selftest(zone: &str, verdict: Verdict, min_dbfs: f32) {
+    match verdict {
+        Verdict::Pass { level_dbfs } => {
+            info!("{zone}: self-test ok, input at {level_dbfs:+.1} dBFS");
+        }
+        Verdict::TooQuiet { level_dbfs } => {
+            warn!(
+                "{zone}: input only reached {level_dbfs:+.1} dBFS during the self-test \
+                 (need {min_dbfs:+.1}). Is the mic muted or its input gain too low? \
+                 Check --device against --list-devices."
+            );
+        }
+    }
+}
+
+fn report_imbalance(device: &str, meter: &mut imbalance::ImbalanceMeter, interleaved: &[f32]) {
+    match meter.push(interleaved) {
+        Some(Transition::Engaged) => {
+            let db = meter.imbalance_db();
+            let side = if db > 0.0 { "left" } else { "right" };
+            warn!(
+                "{device}: {side} channel {:.1} dB louder than the other",
+                db.abs()
+            );
+        }
+        Some(Transition::Released) => info!("{device}: channels balanced again"),
+        None => {}
+    }
+}
+
+fn report_inactivity(
+    zone: &str,
+    monitor: &mut InactivityMonitor,
+    result: &dsp::ProcessResult,
+    args: &Args,
+    now: Instant,
+) {
+    let change = monitor.observe(result.envelope_dbfs, result.thresholds, result.silent, now);
+    match change {
+        Some(Transition::Engaged) => {
+            let t = result.thresholds;
+            warn!(
+                "{zone}: {:.0} min of audio without going under {:+.1} or over \
+                 {:+.1} dBFS, so nothing has been adjusted. Try a smaller --dead-zone, or \
+                 --calibrate to fit the thresholds to this content; if the level never moves, \
+                 check --device.",
+                args.inactivity_warn.unwrap_or_default(),
+                t.quiet_dbfs,
+                t.loud_dbfs
+            );
+        }
+        Some(Transition::Released) => info!("{zone}: leveling again"),
+        None => {}
+    }
+}
+
+fn print_next_recalibration(schedule: &RecalibrationSchedule) {
+    if let Some(next) = schedule.next() {
+        info!("Next recalibration: {}", next.format("%Y-%m-%d %H:%M"));
+    }
+}
+
+/// Features advertised in the startup registration.
+fn capabilities(args: &Args) -> Vec<&'static str> {
+    let mut caps = vec!["volume"];
+    if args.volume_steps.is_some() {
+        caps.push("volume-steps");
+    }
+    if args.variance_window > 0.0 {
+        caps.push("variance");
+    }
+    if args.heartbeat_url.is_some() {
+        caps.push("heartbeat");
+    }
+    caps
+}
+
 fn ctrlc_handler(running: Arc<AtomicBool>) {
     let _ = std::thread::spawn(move || {
         // Simple signal handling without pulling in ctrlc crate
@@ -393,14 +2010,238 @@ fn ctrlc_handler(running: Arc<AtomicBool>) {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let rotation = args.log_file.clone().map(|path| logging::Rotation {
+        path,
+        max_bytes: args.log_max_mb * 1024 * 1024,
+        keep: args.log_keep,
+    });
+    logging::init(args.log_target, rotation)?;
 
     if args.list_devices {
-        return list_devices();
+        list_devices()?;
+        if let Err(e) = midi::list_ports() {
+            println!("No MIDI outputs ({e})");
+        }
+        return Ok(());
     }
 
     if args.calibrate > 0.0 {
         return run_calibration(&args).await;
     }
 
-    run_main_loop(&args).await
+    if args.gain_report > 0.0 {
+        return run_gain_report(&args).await;
+    }
+
+    if args.ramp_max.is_some_and(|max| args.ramp_min > max) {
+        bail!("--ramp-min must not be more than --ramp-max");
+    }
+
+    let file = match &args.config {
+        Some(path) => FileConfig::load(path)?,
+        None => FileConfig::default(),
+    };
+
+    if args.per_channel {
+        return run_per_channel_loop(&args, &file).await;
+    }
+
+    run_main_loop(&args, &file).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::tests::RecordingSink;
+    use crate::zone::tests::TestZone;
+
+    /// One window of audio at about -6 dBFS, far over the test target.
+    const LOUD: [f32; 400] = [0.5; 400];
+
+    /// `LOUD` as its capture would hand it over.
+    fn loud(analyzer: &mut Analyzer) -> Captured {
+        Captured::analyzed(analyzer, LOUD.to_vec())
+    }
+
+    #[test]
+    fn per_channel_rejects_what_it_ignores() {
+        let parse = |extra: &[&str]| {
+            Args::try_parse_from(["audilator", "--per-channel"].iter().chain(extra))
+        };
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["--channel-map", "0,1", "--min-interval", "1"]).is_ok());
+        for flag in [
+            &["--status-port", "8080"][..],
+            &["--killswitch-file", "/tmp/hold"],
+            &["--pause-while-process", "game"],
+            &["--max-sends-per-session", "100"],
+            &["--heartbeat-url", "http://x/beat"],
+            &["--standby-after", "60"],
+            &["--notch-center", "3000"],
+        ] {
+            assert!(parse(flag).is_err(), "{flag:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reopened_device_brings_its_own_settings() {
+        let args = Args::try_parse_from(["audilator"]).unwrap();
+        let file: FileConfig = serde_json::from_str(
+            r#"{"devices": {"HDMI": {"target": -28, "release": 4000}, "USB": {"target": -22}}}"#,
+        )
+        .unwrap();
+        let mut zc = zone_configs(&args, &file).remove(0);
+        zc.control.dead_zone = Some(3.0);
+        let config = |device| zone_compressor_config(&args, &file, -25.0, &zc, device, None);
+
+        let hdmi = config("HDMI Capture");
+        assert_eq!((hdmi.target_dbfs, hdmi.release_ms), (-28.0, 4000.0));
+        let usb = config("USB Audio");
+        assert_eq!((usb.target_dbfs, usb.release_ms), (-22.0, args.release));
+        // The zone's own overrides win on any device
+        assert_eq!((hdmi.dead_zone_db, usb.dead_zone_db), (3.0, 3.0));
+
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        z.process(&LOUD);
+        let volume = z.zone.status().volume;
+        z.zone.reconfigure(usb);
+        let r = z.process(&LOUD).unwrap();
+        assert_eq!(r.target_dbfs, -22.0);
+        // Picks up from where the volume was
+        assert!((20.0 * (r.volume / volume).log10()).abs() <= 1.6);
+    }
+
+    #[tokio::test]
+    async fn kill_switch_freezes_the_volume_and_resumes_from_the_sink() {
+        let path = std::env::temp_dir().join(format!("audilator-hold-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut ks = KillSwitch::new(path.clone());
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        let start = Instant::now();
+        // One 50 ms window per update
+        let mut updates = 0u32;
+        let mut at = move || {
+            updates += 1;
+            start + Duration::from_millis(50) * updates
+        };
+        // What the main loop's kill switch branch does, with no audio arriving
+        async fn wait(ks: &mut KillSwitch, zone: &mut Zone, volume: Option<f32>) {
+            let change = tokio::time::timeout(Duration::from_secs(2), ks.changed())
+                .await
+                .expect("kill switch change");
+            killswitch_changed(ks, change, std::slice::from_mut(zone), volume);
+        }
+
+        for _ in 0..8 {
+            level(
+                &mut z.zone,
+                &loud(&mut z.analyzer),
+                ks.engaged(),
+                None,
+                at(),
+            );
+            z.finish().await;
+        }
+
+        // Held with loud audio still playing: nothing sent, nothing drifts
+        std::fs::write(&path, b"").unwrap();
+        wait(&mut ks, &mut z.zone, None).await;
+        assert!(ks.engaged());
+        let before = *sink.sent().last().unwrap();
+        let sends = sink.sent().len();
+        for _ in 0..40 {
+            let result = level(
+                &mut z.zone,
+                &loud(&mut z.analyzer),
+                ks.engaged(),
+                None,
+                at(),
+            )
+            .unwrap();
+            assert!((result.volume - before).abs() < 0.001);
+        }
+        assert_eq!(sink.sent().len(), sends);
+
+        // Released: one step down from where the sink was left
+        std::fs::remove_file(&path).unwrap();
+        wait(&mut ks, &mut z.zone, None).await;
+        assert!(!ks.engaged());
+        let mut resumed = None;
+        while resumed.is_none() {
+            level(
+                &mut z.zone,
+                &loud(&mut z.analyzer),
+                ks.engaged(),
+                None,
+                at(),
+            );
+            resumed = z.finish().await.map(|o| o.volume);
+        }
+        let resumed = resumed.unwrap();
+        assert!(
+            resumed < before && resumed > before * 0.8,
+            "{resumed} vs {before}"
+        );
+
+        // --killswitch-volume goes out on engage, with capture stalled, and
+        // is where leveling resumes
+        std::fs::write(&path, b"").unwrap();
+        wait(&mut ks, &mut z.zone, Some(0.8)).await;
+        z.finish().await;
+        assert_eq!(sink.sent().last(), Some(&0.8));
+        std::fs::remove_file(&path).unwrap();
+        wait(&mut ks, &mut z.zone, Some(0.8)).await;
+        let mut resumed = None;
+        while resumed.is_none() {
+            level(
+                &mut z.zone,
+                &loud(&mut z.analyzer),
+                ks.engaged(),
+                None,
+                at(),
+            );
+            resumed = z.finish().await.map(|o| o.volume);
+        }
+        let resumed = resumed.unwrap();
+        assert!(resumed < 0.8 && resumed > 0.8 * 0.8, "{resumed}");
+    }
+
+    #[tokio::test]
+    async fn exhausted_budget_freezes_until_the_period_restarts() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        let mut budget = SendBudget::new(3, Some(Duration::from_secs(60)));
+        let start = Instant::now();
+
+        for n in 0..40 {
+            let now = start + Duration::from_millis(50) * n;
+            level(
+                &mut z.zone,
+                &loud(&mut z.analyzer),
+                false,
+                Some(&mut budget),
+                now,
+            );
+            z.finish().await;
+        }
+        let sent = sink.sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(budget.remaining(), 0);
+        assert!((z.zone.status().volume - sent[2]).abs() < 0.001);
+
+        // A new period: one step on from the last volume sent
+        let now = start + Duration::from_secs(60);
+        level(
+            &mut z.zone,
+            &loud(&mut z.analyzer),
+            false,
+            Some(&mut budget),
+            now,
+        );
+        let resumed = z.finish().await.unwrap().volume;
+        assert!(resumed < sent[2] && resumed > sent[2] * 0.8, "{resumed}");
+        assert_eq!(budget.remaining(), 2);
+    }
 }
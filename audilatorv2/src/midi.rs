@@ -0,0 +1,183 @@
+//! Volumes as MIDI control changes, so the leveler can ride a hardware
+//! mixer's fader instead of a computer's volume.
+
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::sink::{SinkFuture, VolumeSink};
+
+/// Channel volume, which most mixers map to the fader.
+pub const DEFAULT_CC: u8 = 7;
+
+const CLIENT_NAME: &str = "audilator";
+
+/// 0.0-1.0 onto a controller's 0-127.
+pub fn cc_value(volume: f32) -> u8 {
+    (volume.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// Where control changes go: a midir connection, or a recorder in tests.
+pub trait MidiPort: Send {
+    fn send(&mut self, message: &[u8]) -> Result<()>;
+}
+
+impl MidiPort for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        MidiOutputConnection::send(self, message).map_err(|e| anyhow!("{e}"))
+    }
+}
+
+struct Port {
+    port: Box<dyn MidiPort>,
+    /// The last value sent. 128 steps are coarse, so many volume changes
+    /// land on the same value and aren't worth a message.
+    last: Option<u8>,
+}
+
+/// One controller on one channel of a MIDI output.
+pub struct MidiSink {
+    name: String,
+    /// Status byte: control change on the channel
+    status: u8,
+    controller: u8,
+    port: Mutex<Port>,
+}
+
+impl MidiSink {
+    /// `channel` is 1-16 as printed on the hardware; `controller` 0-127.
+    pub fn new(name: String, port: Box<dyn MidiPort>, channel: u8, controller: u8) -> Self {
+        Self {
+            name,
+            status: 0xB0 | (channel.clamp(1, 16) - 1),
+            controller: controller.min(127),
+            port: Mutex::new(Port { port, last: None }),
+        }
+    }
+
+    /// Connect to the first output whose name contains `filter` (any output
+    /// without one).
+    pub fn open(filter: Option<&str>, channel: u8, controller: u8) -> Result<Self> {
+        let output = MidiOutput::new(CLIENT_NAME).map_err(|e| anyhow!("{e}"))?;
+        let filter_lower = filter.map(str::to_lowercase);
+        let (port, name) = output
+            .ports()
+            .into_iter()
+            .filter_map(|p| output.port_name(&p).ok().map(|name| (p, name)))
+            .find(|(_, name)| {
+                filter_lower
+                    .as_ref()
+                    .is_none_or(|f| name.to_lowercase().contains(f))
+            })
+            .ok_or_else(|| match filter {
+                Some(f) => anyhow!("No MIDI output matching '{f}'"),
+                None => anyhow!("No MIDI outputs"),
+            })?;
+        let connection = output
+            .connect(&port, CLIENT_NAME)
+            .map_err(|e| anyhow!("Connecting to MIDI output {name}: {e}"))?;
+        let name = format!("{name} ch {channel} cc {controller}");
+        Ok(Self::new(name, Box::new(connection), channel, controller))
+    }
+
+    fn send(&self, volume: f32) -> Result<()> {
+        let value = cc_value(volume);
+        let mut port = self.port.lock().unwrap();
+        if port.last == Some(value) {
+            return Ok(());
+        }
+        port.port.send(&[self.status, self.controller, value])?;
+        port.last = Some(value);
+        Ok(())
+    }
+}
+
+impl VolumeSink for MidiSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+        let result = self.send(volume);
+        Box::pin(async move { result })
+    }
+}
+
+pub fn list_ports() -> Result<()> {
+    let output = MidiOutput::new(CLIENT_NAME).map_err(|e| anyhow!("{e}"))?;
+    println!("Available MIDI outputs:");
+    for port in output.ports() {
+        if let Ok(name) = output.port_name(&port) {
+            println!("  {name}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    #[derive(Clone, Default)]
+    struct RecordingPort(Arc<StdMutex<Vec<Vec<u8>>>>);
+
+    impl MidiPort for RecordingPort {
+        fn send(&mut self, message: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn volume_maps_onto_the_cc_range() {
+        assert_eq!(cc_value(0.0), 0);
+        assert_eq!(cc_value(0.5), 64);
+        assert_eq!(cc_value(1.0), 127);
+        assert_eq!(cc_value(-0.2), 0);
+        assert_eq!(cc_value(1.5), 127);
+    }
+
+    #[tokio::test]
+    async fn sends_control_changes_and_skips_repeats() {
+        let port = RecordingPort::default();
+        let sink = MidiSink::new("test".into(), Box::new(port.clone()), 3, 11);
+        for v in [0.5, 0.501, 0.6, 0.6, 0.0] {
+            sink.set_volume(v).await.unwrap();
+        }
+        // 0.501 rounds to the same 64 as 0.5
+        assert_eq!(
+            *port.0.lock().unwrap(),
+            vec![vec![0xB2, 11, 64], vec![0xB2, 11, 76], vec![0xB2, 11, 0]]
+        );
+    }
+
+    /// Through a real virtual port, where the system has a MIDI sequencer.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reaches_a_virtual_port() {
+        use midir::os::unix::VirtualInput;
+        use midir::MidiInput;
+
+        let received = Arc::new(StdMutex::new(Vec::new()));
+        let log = received.clone();
+        let Ok(input) = MidiInput::new("audilator-test") else {
+            eprintln!("No MIDI sequencer here; skipping");
+            return;
+        };
+        let Ok(_connection) = input.create_virtual(
+            "audilator-test-in",
+            move |_, message, _| log.lock().unwrap().push(message.to_vec()),
+            (),
+        ) else {
+            eprintln!("No virtual MIDI ports here; skipping");
+            return;
+        };
+        let sink = MidiSink::open(Some("audilator-test-in"), 1, DEFAULT_CC).unwrap();
+        sink.set_volume(1.0).await.unwrap();
+        sink.set_volume(1.0).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(*received.lock().unwrap(), vec![vec![0xB0, 7, 127]]);
+    }
+}
@@ -0,0 +1,260 @@
+use cpal::{FromSample, Sample};
+
+/// Average interleaved frames down to mono f32.
+///
+/// Whatever the driver hands us, the output holds:
+/// - exactly `data.len() / channels` samples (a trailing partial frame is dropped)
+/// - every sample finite and within [-1.0, 1.0]
+///
+/// A channel count of 0 is treated as mono.
+pub fn downmix<T>(data: &[T], channels: usize) -> Vec<f32>
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    let mut out = Vec::with_capacity(data.len() / channels.max(1));
+    downmix_into(data, channels, &mut out);
+    out
+}
+
+/// `downmix`, appending to `out` instead of allocating.
+pub fn downmix_into<T>(data: &[T], channels: usize, out: &mut Vec<f32>)
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    if channels <= 1 {
+        // Mono mics are the common case: no frames to average
+        out.extend(data.iter().map(|&s| sanitize(f32::from_sample(s))));
+        return;
+    }
+    out.extend(mix_frames(data, channels));
+}
+
+/// The general path of `downmix`: average each frame.
+pub fn mix_frames<T>(data: &[T], channels: usize) -> impl Iterator<Item = f32> + '_
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    let channels = channels.max(1);
+    data.chunks_exact(channels).map(move |frame| {
+        frame
+            .iter()
+            .map(|&s| sanitize(f32::from_sample(s)))
+            .sum::<f32>()
+            / channels as f32
+    })
+}
+
+/// Convert to f32 frames without mixing, appending to `out`. Same guarantees
+/// as `downmix`, except `data.len() / channels` whole frames are appended.
+pub fn interleaved_into<T>(data: &[T], channels: usize, out: &mut Vec<f32>)
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    let channels = channels.max(1);
+    let whole = data.len() - data.len() % channels;
+    out.extend(data[..whole].iter().map(|&s| sanitize(f32::from_sample(s))));
+}
+
+/// Scale by a linear `gain`, clipping at full scale like a hotter input would.
+pub fn apply_gain(samples: &mut [f32], gain: f32) {
+    if gain == 1.0 {
+        return;
+    }
+    for s in samples {
+        *s = (*s * gain).clamp(-1.0, 1.0);
+    }
+}
+
+/// Collects converted callback buffers until at least `min_samples` are
+/// ready, so the analyzer gets fewer, larger messages. With 0 every
+/// non-empty buffer goes straight through.
+pub struct Accumulator<S = f32> {
+    pending: Vec<S>,
+    min_samples: usize,
+}
+
+impl<S> Accumulator<S> {
+    pub fn new(min_samples: usize) -> Self {
+        Self {
+            pending: Vec::with_capacity(min_samples),
+            min_samples,
+        }
+    }
+
+    /// Append with `fill`; returns the batch once it holds enough samples.
+    pub fn push(&mut self, fill: impl FnOnce(&mut Vec<S>)) -> Option<Vec<S>> {
+        fill(&mut self.pending);
+        if self.pending.is_empty() || self.pending.len() < self.min_samples {
+            return None;
+        }
+        // Batches come out about the same size, so size the next one to match
+        let next = Vec::with_capacity(self.pending.len());
+        Some(std::mem::replace(&mut self.pending, next))
+    }
+}
+
+/// Split interleaved frames into one buffer per channel.
+pub fn deinterleave(data: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    let channels = channels.max(1);
+    let mut out = vec![Vec::with_capacity(data.len() / channels); channels];
+    for frame in data.chunks_exact(channels) {
+        for (ch, &s) in out.iter_mut().zip(frame) {
+            ch.push(s);
+        }
+    }
+    out
+}
+
+/// Replace NaN/inf with silence and clamp to full scale.
+fn sanitize(s: f32) -> f32 {
+    if s.is_finite() {
+        s.clamp(-1.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    pub(crate) fn any_sample() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            -1.0f32..=1.0,
+            any::<f32>(),
+            Just(f32::NAN),
+            Just(f32::INFINITY),
+            Just(f32::NEG_INFINITY),
+            Just(f32::MAX),
+            Just(f32::MIN),
+        ]
+    }
+
+    #[test]
+    fn downmix_averages_frames() {
+        let out = downmix(&[1.0f32, 0.0, -0.5, -0.5], 2);
+        assert_eq!(out, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn downmix_drops_partial_frame() {
+        let out = downmix(&[0.1f32, 0.1, 0.1, 0.1, 0.1], 2);
+        assert_eq!(out.len(), 2);
+    }
+
+    #[test]
+    fn downmix_zero_channels_is_mono() {
+        let out = downmix(&[0.25f32, -0.25], 0);
+        assert_eq!(out, vec![0.25, -0.25]);
+    }
+
+    #[test]
+    fn interleaved_keeps_whole_frames() {
+        let mut out = vec![0.25];
+        interleaved_into(&[0.5f32, f32::NAN, -0.5, 2.0, 0.1], 2, &mut out);
+        assert_eq!(out, vec![0.25, 0.5, 0.0, -0.5, 1.0]);
+    }
+
+    #[test]
+    fn deinterleave_splits_channels() {
+        let out = deinterleave(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3);
+        assert_eq!(out, vec![vec![1.0, 4.0], vec![2.0, 5.0], vec![3.0, 6.0]]);
+    }
+
+    #[test]
+    fn downmix_sanitizes_non_finite() {
+        let out = downmix(&[f32::NAN, f32::INFINITY, 2.0], 1);
+        assert_eq!(out, vec![0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn accumulator_batches_until_full() {
+        let mut acc = Accumulator::new(5);
+        assert_eq!(acc.push(|out| out.extend([0.1, 0.2])), None);
+        assert_eq!(acc.push(|out| out.extend([0.3, 0.4])), None);
+        assert_eq!(
+            acc.push(|out| out.extend([0.5, 0.6])),
+            Some(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6])
+        );
+        assert_eq!(acc.push(|out| out.push(0.7)), None);
+    }
+
+    #[test]
+    fn accumulator_zero_passes_through() {
+        let mut acc = Accumulator::new(0);
+        assert_eq!(acc.push(|out| out.push(0.1)), Some(vec![0.1]));
+        assert_eq!(acc.push(|_| {}), None);
+    }
+
+    #[test]
+    fn gain_scales_and_clips() {
+        let mut samples = [0.1, -0.3, 0.6];
+        apply_gain(&mut samples, 2.0);
+        assert_eq!(samples, [0.2, -0.6, 1.0]);
+    }
+
+    proptest! {
+        #[test]
+        fn accumulated_batches_preserve_samples(
+            buffers in prop::collection::vec(prop::collection::vec(any::<i16>(), 0..256), 0..32),
+            min_samples in 0usize..1024,
+        ) {
+            let mut acc = Accumulator::new(min_samples);
+            let mut batched = Vec::new();
+            for buf in &buffers {
+                if let Some(batch) = acc.push(|out| downmix_into(buf, 2, out)) {
+                    prop_assert!(batch.len() >= min_samples);
+                    batched.extend(batch);
+                }
+            }
+            batched.extend(acc.pending);
+            let direct: Vec<f32> = buffers.iter().flat_map(|b| downmix(b, 2)).collect();
+            prop_assert_eq!(batched, direct);
+        }
+
+        #[test]
+        fn downmix_f32_is_bounded(
+            data in prop::collection::vec(any_sample(), 0..1024),
+            channels in 0usize..9,
+        ) {
+            let out = downmix(&data, channels);
+            prop_assert_eq!(out.len(), data.len() / channels.max(1));
+            prop_assert!(out.iter().all(|s| s.is_finite() && (-1.0..=1.0).contains(s)));
+        }
+
+        #[test]
+        fn downmix_i16_is_bounded(
+            data in prop::collection::vec(any::<i16>(), 0..2048),
+            channels in 0usize..9,
+        ) {
+            let out = downmix(&data, channels);
+            prop_assert_eq!(out.len(), data.len() / channels.max(1));
+            prop_assert!(out.iter().all(|s| (-1.0..=1.0).contains(s)));
+        }
+
+        #[test]
+        fn downmix_u16_is_bounded(
+            data in prop::collection::vec(any::<u16>(), 0..2048),
+            channels in 0usize..9,
+        ) {
+            let out = downmix(&data, channels);
+            prop_assert!(out.iter().all(|s| (-1.0..=1.0).contains(s)));
+        }
+
+        #[test]
+        fn mono_fast_path_matches_general_path(
+            data in prop::collection::vec(any_sample(), 0..1024),
+        ) {
+            let fast = downmix(&data, 1);
+            let general: Vec<f32> = mix_frames(&data, 1).collect();
+            prop_assert_eq!(fast.len(), general.len());
+            // Bitwise, so NaN-sanitized and signed-zero samples must agree too
+            prop_assert!(fast.iter().zip(&general).all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use cpal::traits::DeviceTrait;
+use cpal::{Device, FromSample, SampleFormat, SizedSample, StreamConfig};
+
+/// Seconds of audio the bridge holds before dropping the oldest; bounds the
+/// monitoring delay if the output runs slower than the input.
+const BRIDGE_SEC: f32 = 0.5;
+
+/// Bridge from the analysis path to the monitor output: the main loop pushes
+/// the mono samples it analyzes, the output callback drains them.
+#[derive(Clone)]
+pub struct MonitorFeed {
+    buf: Arc<Mutex<VecDeque<f32>>>,
+    capacity: usize,
+}
+
+impl MonitorFeed {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buf: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, samples: &[f32]) {
+        let mut buf = self.buf.lock().unwrap();
+        buf.extend(samples);
+        let excess = buf.len().saturating_sub(self.capacity);
+        buf.drain(..excess);
+    }
+
+    /// Fill interleaved output frames, copying each sample to every channel.
+    /// Plays silence when the input falls behind.
+    fn fill<T>(&self, out: &mut [T], channels: usize)
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let mut buf = self.buf.lock().unwrap();
+        for frame in out.chunks_mut(channels.max(1)) {
+            let s = T::from_sample(buf.pop_front().unwrap_or(0.0));
+            frame.fill(s);
+        }
+    }
+}
+
+/// Open `device` for playing back what the analyzer hears.
+pub fn build_monitor_stream(
+    device: &Device,
+    sample_rate: u32,
+) -> Result<(cpal::Stream, MonitorFeed)> {
+    let supported = device.default_output_config()?;
+    let config = StreamConfig {
+        channels: supported.channels(),
+        sample_rate: cpal::SampleRate(sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let feed = MonitorFeed::new((sample_rate as f32 * BRIDGE_SEC) as usize);
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(device, &config, feed.clone()),
+        SampleFormat::I16 => build_stream::<i16>(device, &config, feed.clone()),
+        SampleFormat::U16 => build_stream::<u16>(device, &config, feed.clone()),
+        fmt => Err(anyhow!("Unsupported sample format: {fmt:?}")),
+    }?;
+    Ok((stream, feed))
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    feed: MonitorFeed,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| feed.fill(data, channels),
+        |err| tracing::error!("Monitor error: {err}"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::find_output_device;
+
+    #[test]
+    fn bridge_duplicates_to_channels_and_pads_with_silence() {
+        let feed = MonitorFeed::new(8);
+        feed.push(&[0.1, 0.2, 0.3]);
+        let mut out = [1.0f32; 8];
+        feed.fill(&mut out, 2);
+        assert_eq!(out, [0.1, 0.1, 0.2, 0.2, 0.3, 0.3, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bridge_drops_oldest_when_full() {
+        let feed = MonitorFeed::new(3);
+        feed.push(&[0.1, 0.2, 0.3, 0.4, 0.5]);
+        let mut out = [0.0f32; 3];
+        feed.fill(&mut out, 1);
+        assert_eq!(out, [0.3, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn output_stream_builds() {
+        // Machines without audio hardware have nothing to open
+        let Ok(device) = find_output_device(None) else {
+            return;
+        };
+        let Ok(supported) = device.default_output_config() else {
+            return;
+        };
+        let (_stream, _feed) = build_monitor_stream(&device, supported.sample_rate().0).unwrap();
+    }
+}
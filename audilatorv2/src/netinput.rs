@@ -0,0 +1,488 @@
+//! Analysis input from a network audio stream (`--input-url`) instead of a
+//! local device, so the controller can level audio it never hears itself.
+//!
+//! The stream is an HTTP response body of uncompressed PCM, e.g. an Icecast
+//! mount or `ffmpeg ... -f wav` behind any HTTP server. Its format comes from
+//! the response: a WAV header at the start of the body (16-bit or float), or
+//! an `audio/L16;rate=N;channels=N` content type (big-endian, RFC 2586). The
+//! request's Accept header asks for L16 at our rate and mono; whatever
+//! arrives is mixed to mono and resampled to `--sample-rate`, then batched
+//! like a capture callback. A dropped or stalled stream is reopened with
+//! backoff.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::mix::{apply_gain, downmix_into, Accumulator};
+
+/// A stream sending nothing for this long counts as dropped.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+/// First wait before reconnecting, doubled on each failure in a row.
+const RECONNECT_MIN: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+/// Give up looking for the WAV data chunk after this many bytes.
+const MAX_HEADER: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    I16Le,
+    I16Be,
+    F32Le,
+}
+
+impl Encoding {
+    fn bytes(self) -> usize {
+        match self {
+            Self::I16Le | Self::I16Be => 2,
+            Self::F32Le => 4,
+        }
+    }
+
+    fn decode(self, b: &[u8]) -> f32 {
+        match self {
+            Self::I16Le => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+            Self::I16Be => i16::from_be_bytes([b[0], b[1]]) as f32 / 32768.0,
+            Self::F32Le => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StreamFormat {
+    encoding: Encoding,
+    channels: usize,
+    sample_rate: u32,
+}
+
+/// `audio/L16;rate=44100;channels=2`. The RFC defaults to mono; the rate is
+/// required.
+fn parse_l16(content_type: &str) -> Option<StreamFormat> {
+    let mut parts = content_type.split(';').map(str::trim);
+    if !parts.next()?.eq_ignore_ascii_case("audio/L16") {
+        return None;
+    }
+    let (mut rate, mut channels) = (None, 1);
+    for (key, value) in parts.filter_map(|p| p.split_once('=')) {
+        match key.trim().to_ascii_lowercase().as_str() {
+            "rate" => rate = value.trim().parse().ok(),
+            "channels" => channels = value.trim().parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(StreamFormat {
+        encoding: Encoding::I16Be,
+        channels,
+        sample_rate: rate?,
+    })
+}
+
+/// The format and the length of the header, once `bytes` holds all of it
+/// (None: need more). Streamed WAVs carry placeholder sizes, so the data
+/// chunk is taken to run until the stream ends.
+fn parse_wav_header(bytes: &[u8]) -> Result<Option<(StreamFormat, usize)>> {
+    if bytes.len() < 12 {
+        return Ok(None);
+    }
+    if &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("not a WAV stream");
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at =
+        |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let mut format = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let (id, size) = (&bytes[at..at + 4], u32_at(at + 4) as usize);
+        let body = at + 8;
+        if id == b"data" {
+            let format = format.ok_or_else(|| anyhow!("WAV data before its fmt chunk"))?;
+            return Ok(Some((format, body)));
+        }
+        if body + size > bytes.len() {
+            break;
+        }
+        if id == b"fmt " {
+            if size < 16 {
+                bail!("WAV fmt chunk too short");
+            }
+            // WAVE_FORMAT_EXTENSIBLE keeps the real tag in its sub-format GUID
+            let tag = match u16_at(body) {
+                0xFFFE if size >= 26 => u16_at(body + 24),
+                tag => tag,
+            };
+            let bits = u16_at(body + 14);
+            let encoding = match (tag, bits) {
+                (1, 16) => Encoding::I16Le,
+                (3, 32) => Encoding::F32Le,
+                _ => bail!("unsupported WAV encoding (format {tag}, {bits}-bit)"),
+            };
+            format = Some(StreamFormat {
+                encoding,
+                channels: u16_at(body + 2) as usize,
+                sample_rate: u32_at(body + 4),
+            });
+        }
+        // Chunks are padded to an even length
+        at = body + size + (size & 1);
+    }
+    if bytes.len() > MAX_HEADER {
+        bail!("no WAV data chunk in the first {MAX_HEADER} bytes");
+    }
+    Ok(None)
+}
+
+/// Linear interpolation from one rate to another. Plenty for a level meter.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output between `prev` (0) and the next input (1)
+    pos: f64,
+    prev: f32,
+}
+
+impl Resampler {
+    fn new(from: u32, to: u32) -> Self {
+        Self {
+            step: from as f64 / to as f64,
+            pos: 0.0,
+            prev: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        for &x in input {
+            while self.pos < 1.0 {
+                out.push(self.prev + (x - self.prev) * self.pos as f32);
+                self.pos += self.step;
+            }
+            self.pos -= 1.0;
+            self.prev = x;
+        }
+    }
+}
+
+/// Turns body bytes into mono samples at the analysis rate, carrying
+/// partial frames between chunks.
+struct Decoder {
+    format: StreamFormat,
+    carry: Vec<u8>,
+    interleaved: Vec<f32>,
+    mono: Vec<f32>,
+    resampler: Option<Resampler>,
+}
+
+impl Decoder {
+    fn new(format: StreamFormat, sample_rate: u32) -> Result<Self> {
+        if format.channels == 0 || format.sample_rate == 0 {
+            bail!(
+                "stream reports {} channel(s) at {} Hz",
+                format.channels,
+                format.sample_rate
+            );
+        }
+        Ok(Self {
+            format,
+            carry: Vec::new(),
+            interleaved: Vec::new(),
+            mono: Vec::new(),
+            resampler: (format.sample_rate != sample_rate)
+                .then(|| Resampler::new(format.sample_rate, sample_rate)),
+        })
+    }
+
+    fn decode(&mut self, bytes: &[u8], out: &mut Vec<f32>) {
+        self.carry.extend_from_slice(bytes);
+        let size = self.format.encoding.bytes();
+        let whole = self.carry.len() / (size * self.format.channels) * size * self.format.channels;
+        self.interleaved.clear();
+        self.interleaved.extend(
+            self.carry[..whole]
+                .chunks_exact(size)
+                .map(|b| self.format.encoding.decode(b)),
+        );
+        self.carry.drain(..whole);
+        match &mut self.resampler {
+            Some(resampler) => {
+                self.mono.clear();
+                downmix_into(&self.interleaved, self.format.channels, &mut self.mono);
+                resampler.process(&self.mono, out);
+            }
+            None => downmix_into(&self.interleaved, self.format.channels, out),
+        }
+    }
+}
+
+/// Where and how to pull the stream.
+pub struct NetInput {
+    pub url: String,
+    pub sample_rate: u32,
+    /// Batch size as for a capture (0: each chunk on its own)
+    pub accumulate_frames: usize,
+    /// Linear gain, as --input-gain
+    pub input_gain: f32,
+}
+
+/// Pull the stream until `tx` closes, reconnecting whenever it drops.
+pub async fn run(input: NetInput, tx: mpsc::UnboundedSender<Vec<f32>>) {
+    let client = match reqwest::Client::builder()
+        .connect_timeout(STALL_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return warn!("{}: {e}", input.url),
+    };
+    let mut wait = RECONNECT_MIN;
+    while !tx.is_closed() {
+        let mut delivered = false;
+        match pull(&client, &input, &tx, &mut delivered).await {
+            Ok(()) => warn!("{}: stream ended, reconnecting", input.url),
+            Err(e) => warn!("{}: {e:#}, reconnecting in {wait:?}", input.url),
+        }
+        if delivered {
+            wait = RECONNECT_MIN;
+        }
+        tokio::time::sleep(wait).await;
+        if !delivered {
+            wait = (wait * 2).min(RECONNECT_MAX);
+        }
+    }
+}
+
+/// One connection, until the stream ends or fails. Sets `delivered` once
+/// any audio went out.
+async fn pull(
+    client: &reqwest::Client,
+    input: &NetInput,
+    tx: &mpsc::UnboundedSender<Vec<f32>>,
+    delivered: &mut bool,
+) -> Result<()> {
+    let accept = format!(
+        "audio/L16;rate={};channels=1, audio/wav;q=0.9, */*;q=0.1",
+        input.sample_rate
+    );
+    let mut resp = client
+        .get(&input.url)
+        .header(reqwest::header::ACCEPT, accept)
+        .send()
+        .await?
+        .error_for_status()?;
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let (format, mut pending) = match parse_l16(&content_type) {
+        Some(format) => (format, Vec::new()),
+        None => {
+            let mut head = Vec::new();
+            loop {
+                if let Some((format, len)) = parse_wav_header(&head)
+                    .with_context(|| format!("unsupported stream ({content_type})"))?
+                {
+                    break (format, head.split_off(len));
+                }
+                match next_chunk(&mut resp).await? {
+                    Some(chunk) => head.extend_from_slice(&chunk),
+                    None => return Ok(()),
+                }
+            }
+        }
+    };
+    info!(
+        "{}: {:?} {}ch {}Hz",
+        input.url, format.encoding, format.channels, format.sample_rate
+    );
+
+    let mut decoder = Decoder::new(format, input.sample_rate)?;
+    let mut batch = Accumulator::new(input.accumulate_frames);
+    loop {
+        let sent = batch.push(|out| {
+            let start = out.len();
+            decoder.decode(&pending, out);
+            apply_gain(&mut out[start..], input.input_gain);
+        });
+        if let Some(samples) = sent.filter(|s| !s.is_empty()) {
+            if tx.send(samples).is_err() {
+                return Ok(());
+            }
+            *delivered = true;
+        }
+        match next_chunk(&mut resp).await? {
+            Some(chunk) => pending = chunk,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// The next piece of the body, or None at its end.
+async fn next_chunk(resp: &mut reqwest::Response) -> Result<Option<Vec<u8>>> {
+    let chunk = tokio::time::timeout(STALL_TIMEOUT, resp.chunk())
+        .await
+        .map_err(|_| anyhow!("no audio for {STALL_TIMEOUT:?}"))?
+        .context("reading stream")?;
+    Ok(chunk.map(|c| c.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::{rms_to_dbfs, RingBuffer};
+    use std::f32::consts::PI;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A streaming WAV header: sizes unknown, as a live encoder sends it.
+    fn wav_header(channels: u16, rate: u32) -> Vec<u8> {
+        let mut h = Vec::new();
+        h.extend_from_slice(b"RIFF\xff\xff\xff\xffWAVE");
+        h.extend_from_slice(b"LIST\x04\x00\x00\x00INFO");
+        h.extend_from_slice(b"fmt \x10\x00\x00\x00\x01\x00");
+        h.extend_from_slice(&channels.to_le_bytes());
+        h.extend_from_slice(&rate.to_le_bytes());
+        h.extend_from_slice(&(rate * channels as u32 * 2).to_le_bytes());
+        h.extend_from_slice(&(channels * 2).to_le_bytes());
+        h.extend_from_slice(&16u16.to_le_bytes());
+        h.extend_from_slice(b"data\xff\xff\xff\xff");
+        h
+    }
+
+    /// `seconds` of a 1 kHz sine at `amplitude` on every channel, as i16.
+    fn sine(channels: usize, rate: u32, amplitude: f32, seconds: f32) -> Vec<i16> {
+        let n = (rate as f32 * seconds) as usize;
+        (0..n)
+            .flat_map(|i| {
+                let s = amplitude * (2.0 * PI * 1000.0 * i as f32 / rate as f32).sin();
+                std::iter::repeat_n((s * 32767.0) as i16, channels)
+            })
+            .collect()
+    }
+
+    /// Serves `body` in small pieces under `content_type` to each
+    /// connection, then hangs up. Counts connections.
+    async fn stream_server(
+        content_type: &'static str,
+        body: Vec<u8>,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let body = body.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nConnection: close\r\n\r\n"
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    // Odd-sized pieces split frames across chunks
+                    for piece in body.chunks(1001) {
+                        if socket.write_all(piece).await.is_err() {
+                            return;
+                        }
+                        socket.flush().await.unwrap();
+                    }
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    async fn collect(url: String, sample_rate: u32, samples: usize) -> Vec<f32> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let input = NetInput {
+            url,
+            sample_rate,
+            accumulate_frames: 480,
+            input_gain: 1.0,
+        };
+        let task = tokio::spawn(run(input, tx));
+        let mut got = Vec::new();
+        while got.len() < samples {
+            let batch = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("stream stalled")
+                .unwrap();
+            got.extend(batch);
+        }
+        task.abort();
+        got
+    }
+
+    fn rms_dbfs(samples: &[f32]) -> f32 {
+        let mut ring = RingBuffer::new(samples.len());
+        ring.extend(samples);
+        rms_to_dbfs(ring.rms())
+    }
+
+    #[tokio::test]
+    async fn streams_wav_and_reconnects_when_it_drops() {
+        // 0.5 s of stereo per connection; -20 dBFS RMS is amplitude 0.1414
+        let mut body = wav_header(2, 48000);
+        for s in sine(2, 48000, 0.1414, 0.5) {
+            body.extend_from_slice(&s.to_le_bytes());
+        }
+        let (url, connections) = stream_server("audio/wav", body).await;
+
+        let got = collect(url, 48000, 60000).await;
+        assert!((rms_dbfs(&got) + 20.0).abs() < 0.2, "{}", rms_dbfs(&got));
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[tokio::test]
+    async fn resamples_l16_to_the_analysis_rate() {
+        let mut body = Vec::new();
+        for s in sine(1, 16000, 0.5, 1.0) {
+            body.extend_from_slice(&s.to_be_bytes());
+        }
+        let (url, _) = stream_server("audio/L16; rate=16000; channels=1", body).await;
+
+        // A second at 16 kHz is a second at 48 kHz
+        let got = collect(url, 48000, 48000).await;
+        let first = &got[..47000];
+        let expected = 20.0 * (0.5f32 / 2f32.sqrt()).log10();
+        assert!(
+            (rms_dbfs(first) - expected).abs() < 0.3,
+            "{}",
+            rms_dbfs(first)
+        );
+    }
+
+    #[test]
+    fn negotiates_formats_from_the_response() {
+        assert_eq!(
+            parse_l16("audio/L16;rate=44100;channels=2"),
+            Some(StreamFormat {
+                encoding: Encoding::I16Be,
+                channels: 2,
+                sample_rate: 44100
+            })
+        );
+        assert_eq!(parse_l16("audio/l16; rate=8000").unwrap().channels, 1);
+        assert_eq!(parse_l16("audio/L16"), None);
+        assert_eq!(parse_l16("audio/mpeg"), None);
+
+        let header = wav_header(2, 44100);
+        assert!(parse_wav_header(&header[..30]).unwrap().is_none());
+        let (format, len) = parse_wav_header(&header).unwrap().unwrap();
+        assert_eq!((format.channels, format.sample_rate), (2, 44100));
+        assert_eq!(len, header.len());
+        assert!(parse_wav_header(b"ID3\x04\x00\x00\x00\x00\x00\x00\x00\x00").is_err());
+
+        // A chunk declared bigger than any header gives up once past the limit
+        let mut huge = b"RIFF\xff\xff\xff\xffWAVEjunk\x00\x00\x00\x10".to_vec();
+        assert!(parse_wav_header(&huge).unwrap().is_none());
+        huge.resize(MAX_HEADER + 1, 0);
+        assert!(parse_wav_header(&huge).is_err());
+    }
+}
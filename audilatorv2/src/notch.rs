@@ -0,0 +1,167 @@
+//! Reacts to energy in one narrow band, e.g. a doorbell or alarm tone, no
+//! matter how loud everything else is. A single band needs no spectrum: two
+//! cascaded band-pass biquads isolate it, and their output's RMS per window
+//! is compared with the threshold.
+
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::dsp::rms_to_dbfs;
+use crate::loudness::Biquad;
+use crate::transition::Transition;
+
+/// How long the band must stay quiet before the response is released, so a
+/// beeping alarm holds it through the gaps between beeps.
+const RELEASE_AFTER: Duration = Duration::from_secs(2);
+
+/// Band-limited level detector. Engages when the band's level reaches the
+/// threshold; releases once it has been under for RELEASE_AFTER.
+pub struct NotchDetector {
+    stages: [Biquad; 2],
+    threshold_dbfs: f32,
+    window_samples: usize,
+    energy: f64,
+    count: usize,
+    last_above: Option<Instant>,
+    active: bool,
+}
+
+impl NotchDetector {
+    pub fn new(
+        center_hz: f32,
+        bandwidth_hz: f32,
+        threshold_dbfs: f32,
+        sample_rate: u32,
+        window_samples: usize,
+    ) -> Result<Self> {
+        let nyquist = sample_rate as f32 / 2.0;
+        if !(center_hz > 0.0 && center_hz < nyquist) {
+            return Err(anyhow!(
+                "--notch-center must be between 0 and {nyquist} Hz at this sample rate"
+            ));
+        }
+        if bandwidth_hz <= 0.0 {
+            return Err(anyhow!("--notch-bandwidth must be positive"));
+        }
+        // RBJ band-pass, 0 dB at the center
+        let w0 = 2.0 * PI * center_hz as f64 / sample_rate as f64;
+        let q = center_hz as f64 / bandwidth_hz as f64;
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let stage = || {
+            Biquad::new(
+                [alpha / a0, 0.0, -alpha / a0],
+                [-2.0 * w0.cos() / a0, (1.0 - alpha) / a0],
+            )
+        };
+        Ok(Self {
+            stages: [stage(), stage()],
+            threshold_dbfs,
+            window_samples: window_samples.max(1),
+            energy: 0.0,
+            count: 0,
+            last_above: None,
+            active: false,
+        })
+    }
+
+    /// Feed captured samples. Returns the change, if any.
+    pub fn push(&mut self, samples: &[f32], now: Instant) -> Option<Transition> {
+        let mut change = None;
+        for &s in samples {
+            let [first, second] = &mut self.stages;
+            let y = second.process(first.process(s as f64));
+            self.energy += y * y;
+            self.count += 1;
+            if self.count >= self.window_samples {
+                let level = rms_to_dbfs((self.energy / self.count as f64).sqrt() as f32);
+                self.energy = 0.0;
+                self.count = 0;
+                change = self.update(level, now).or(change);
+            }
+        }
+        change
+    }
+
+    fn update(&mut self, level_dbfs: f32, now: Instant) -> Option<Transition> {
+        if level_dbfs >= self.threshold_dbfs {
+            self.last_above = Some(now);
+            if !self.active {
+                self.active = true;
+                return Some(Transition::Engaged);
+            }
+        } else if self.active
+            && self
+                .last_above
+                .is_some_and(|t| now.duration_since(t) >= RELEASE_AFTER)
+        {
+            self.active = false;
+            return Some(Transition::Released);
+        }
+        None
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 8000;
+
+    fn tone(hz: f32, amplitude: f32, secs: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|n| amplitude * (2.0 * std::f32::consts::PI * hz * n as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn detector() -> NotchDetector {
+        NotchDetector::new(1000.0, 100.0, -40.0, RATE, 400).unwrap()
+    }
+
+    #[test]
+    fn tone_in_the_band_triggers() {
+        let mut notch = detector();
+        // About -23 dBFS RMS, well over the threshold
+        let change = notch.push(&tone(1000.0, 0.1, 0.5), Instant::now());
+        assert_eq!(change, Some(Transition::Engaged));
+        assert!(notch.active());
+    }
+
+    #[test]
+    fn loud_tone_outside_the_band_does_not() {
+        let mut notch = detector();
+        // Near full scale, an octave away
+        assert_eq!(notch.push(&tone(2000.0, 0.7, 0.5), Instant::now()), None);
+        // and an octave below
+        assert_eq!(notch.push(&tone(500.0, 0.7, 0.5), Instant::now()), None);
+        assert!(!notch.active());
+    }
+
+    #[test]
+    fn releases_after_the_band_goes_quiet() {
+        let mut notch = detector();
+        let start = Instant::now();
+        notch.push(&tone(1000.0, 0.1, 0.5), start);
+        // Brief gap between beeps: still engaged. The filters ring into its first window.
+        let silence = vec![0.0; RATE as usize / 2];
+        let gap = start + Duration::from_secs(1);
+        assert_eq!(notch.push(&silence, gap), None);
+        assert_eq!(notch.push(&silence, gap + RELEASE_AFTER / 2), None);
+        assert_eq!(
+            notch.push(&silence, gap + RELEASE_AFTER),
+            Some(Transition::Released)
+        );
+    }
+
+    #[test]
+    fn rejects_centers_past_nyquist() {
+        assert!(NotchDetector::new(5000.0, 100.0, -40.0, RATE, 400).is_err());
+        assert!(NotchDetector::new(1000.0, 0.0, -40.0, RATE, 400).is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Default minimum change in volume scalar worth sending when not quantizing.
+pub const DEFAULT_SEND_DEADBAND: f32 = 0.005;
+/// Decimal places of a volume as sent; finer differences never reach the device.
+const WIRE_DECIMALS: i32 = 4;
+
+/// Which sends start the minimum-interval cooldown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CooldownOn {
+    /// Only sends the controller accepted (a failed send can retry at once)
+    Success,
+    /// Every send, successful or not (go easy on a struggling controller)
+    Attempt,
+}
+
+/// While throttling, sends wait at least this many recent round-trips apart.
+const THROTTLE_LATENCY_MULTIPLE: f32 = 4.0;
+/// Weight of the newest round-trip in the running latency.
+const LATENCY_SMOOTHING: f32 = 0.3;
+/// How far back the adaptive cooldown looks at sends.
+const ADAPT_WINDOW: Duration = Duration::from_secs(60);
+/// Sends per window that count as calm: the adaptive cooldown stays at
+/// the minimum interval until there are more.
+const CALM_SENDS: usize = 4;
+
+/// Enforces the minimum interval between sends.
+pub struct Cooldown {
+    interval: Duration,
+    policy: CooldownOn,
+    last: Option<Instant>,
+    /// Cap on the latency-stretched interval (zero: no throttling)
+    max_interval: Duration,
+    latency: Option<Duration>,
+    /// Fraction of the interval randomized after a failure (zero: none)
+    retry_jitter: f32,
+    rng: fastrand::Rng,
+    /// Wait before retrying the last failed send
+    retry_wait: Option<Duration>,
+    /// Cap on the frequency-stretched interval (zero: not adaptive)
+    adaptive_max: Duration,
+    adaptive_rate: f32,
+    /// Sends that started the cooldown within ADAPT_WINDOW
+    recent: VecDeque<Instant>,
+}
+
+impl Cooldown {
+    pub fn new(interval: Duration, policy: CooldownOn) -> Self {
+        Self {
+            interval,
+            policy,
+            last: None,
+            max_interval: Duration::ZERO,
+            latency: None,
+            retry_jitter: 0.0,
+            rng: fastrand::Rng::new(),
+            retry_wait: None,
+            adaptive_max: Duration::ZERO,
+            adaptive_rate: 0.0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Lengthen the interval while sends have been frequent: each send in
+    /// the last minute beyond a calm few multiplies it by 1 + `rate`, up to
+    /// `max_interval`. As they age out it comes back down to the minimum,
+    /// so volatile content is fought less and stable content still gets
+    /// prompt corrections.
+    pub fn with_adaptive(mut self, max_interval: Duration, rate: f32) -> Self {
+        self.adaptive_max = max_interval;
+        self.adaptive_rate = rate.max(0.0);
+        self
+    }
+
+    /// After a failed send, wait a random part of the interval before the
+    /// retry: between (1 - jitter) and 1 times it, so 1 is full jitter.
+    /// Controllers failing together (a restarted server) then come back
+    /// spread out rather than in lockstep. Counts failures whatever the
+    /// `CooldownOn` policy.
+    pub fn with_retry_jitter(mut self, jitter: f32, rng: fastrand::Rng) -> Self {
+        self.retry_jitter = jitter.clamp(0.0, 1.0);
+        self.rng = rng;
+        self
+    }
+
+    /// Stretch the interval to a few recent round-trips when the controller
+    /// is slow, up to `max_interval`. It shrinks back as latency recovers.
+    pub fn with_throttle(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    pub fn ready(&self, now: Instant) -> bool {
+        let wait = self.retry_wait.unwrap_or_else(|| self.interval_at(now));
+        self.last
+            .map(|last| now.duration_since(last) >= wait)
+            .unwrap_or(true)
+    }
+
+    /// Feed back the outcome of a send made at `now`.
+    pub fn record(&mut self, now: Instant, success: bool) {
+        self.retry_wait = None;
+        if !success && self.retry_jitter > 0.0 {
+            let spread = 1.0 - self.retry_jitter + self.retry_jitter * self.rng.f32();
+            self.retry_wait = Some(self.interval_at(now).mul_f32(spread));
+            self.last = Some(now);
+        } else if success || self.policy == CooldownOn::Attempt {
+            self.last = Some(now);
+            if !self.adaptive_max.is_zero() {
+                self.recent.push_back(now);
+                while self
+                    .recent
+                    .front()
+                    .is_some_and(|&t| now.duration_since(t) > ADAPT_WINDOW)
+                {
+                    self.recent.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Feed back how long a send took.
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.latency = Some(match self.latency {
+            Some(avg) => avg.mul_f32(1.0 - LATENCY_SMOOTHING) + latency.mul_f32(LATENCY_SMOOTHING),
+            None => latency,
+        });
+    }
+
+    /// Smoothed round-trip of recent sends.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Minimum time between sends as of the last one, after any adaptation
+    /// and throttling.
+    pub fn interval(&self) -> Duration {
+        self.interval_at(self.last.unwrap_or_else(Instant::now))
+    }
+
+    fn interval_at(&self, now: Instant) -> Duration {
+        let base = self.adapted(now);
+        match self.latency {
+            Some(latency) if !self.max_interval.is_zero() => {
+                let throttled = latency
+                    .mul_f32(THROTTLE_LATENCY_MULTIPLE)
+                    .min(self.max_interval);
+                base.max(throttled)
+            }
+            _ => base,
+        }
+    }
+
+    /// The minimum interval stretched by recent send frequency.
+    fn adapted(&self, now: Instant) -> Duration {
+        if self.adaptive_max.is_zero() {
+            return self.interval;
+        }
+        let recent = self
+            .recent
+            .iter()
+            .filter(|&&t| now.saturating_duration_since(t) <= ADAPT_WINDOW)
+            .count();
+        let excess = recent.saturating_sub(CALM_SENDS) as i32;
+        let stretched = self.interval.as_secs_f32() * (1.0 + self.adaptive_rate).powi(excess);
+        Duration::from_secs_f32(stretched.min(self.adaptive_max.as_secs_f32())).max(self.interval)
+    }
+}
+
+/// Tracks how long the volume has gone without a send, to report when
+/// leveling has converged on the current content.
+pub struct SettleTracker {
+    settle_time: Duration,
+    since: Instant,
+    settled: bool,
+}
+
+impl SettleTracker {
+    pub fn new(settle_time: Duration, now: Instant) -> Self {
+        Self {
+            settle_time,
+            since: now,
+            settled: false,
+        }
+    }
+
+    /// A send went out; start the dwell again.
+    pub fn record_send(&mut self, now: Instant) {
+        self.since = now;
+        self.settled = false;
+    }
+
+    /// True exactly once per quiet period, when it first exceeds the settle time.
+    pub fn update(&mut self, now: Instant) -> bool {
+        if self.settled || now.duration_since(self.since) < self.settle_time {
+            return false;
+        }
+        self.settled = true;
+        true
+    }
+
+    pub fn settled(&self) -> bool {
+        self.settled
+    }
+}
+
+/// Decides which computed volumes are worth sending to the controller.
+/// Optionally snaps values to a fixed number of device steps first.
+pub struct SendGate {
+    steps: Option<u32>,
+    deadband: f32,
+    last_sent: Option<f32>,
+}
+
+impl SendGate {
+    /// `deadband` is the smallest change worth sending when not quantizing.
+    pub fn new(steps: Option<u32>, deadband: f32) -> Self {
+        Self {
+            steps,
+            deadband,
+            last_sent: None,
+        }
+    }
+
+    /// Snap a 0.0-1.0 volume to the nearest of `steps` levels (step / steps).
+    pub fn quantize(&self, volume: f32) -> f32 {
+        match self.steps {
+            Some(n) if n > 0 => (volume.clamp(0.0, 1.0) * n as f32).round() / n as f32,
+            _ => volume,
+        }
+    }
+
+    /// The value that would actually go on the wire for `volume`: quantized,
+    /// clamped to the device range and rounded to wire precision.
+    pub fn effective(&self, volume: f32) -> f32 {
+        let scale = 10f32.powi(WIRE_DECIMALS);
+        (self.quantize(volume).clamp(0.0, 1.0) * scale).round() / scale
+    }
+
+    /// Value to send for `volume`, or None if the device wouldn't change.
+    /// A value identical to the last sent one is never resent, whatever the deadband.
+    pub fn pending(&self, volume: f32) -> Option<f32> {
+        let v = self.effective(volume);
+        let changed = match self.last_sent {
+            None => true,
+            Some(last) if v == last => false,
+            Some(_) if self.steps.is_some() => true,
+            Some(last) => (v - last).abs() > self.deadband,
+        };
+        changed.then_some(v)
+    }
+
+    /// Record a value the controller accepted.
+    pub fn mark_sent(&mut self, volume: f32) {
+        self.last_sent = Some(volume);
+    }
+
+    pub fn last_sent(&self) -> Option<f32> {
+        self.last_sent
+    }
+}
+
+/// Caps how many volumes are sent in a session, for metered links and as a
+/// backstop against runaway sending. Optionally starts over every period.
+pub struct SendBudget {
+    max: u64,
+    used: u64,
+    reset_every: Option<Duration>,
+    period_start: Option<Instant>,
+}
+
+impl SendBudget {
+    pub fn new(max: u64, reset_every: Option<Duration>) -> Self {
+        Self {
+            max,
+            used: 0,
+            reset_every,
+            period_start: None,
+        }
+    }
+
+    /// Start a new period if one is due. True when that ends an exhaustion.
+    pub fn refresh(&mut self, now: Instant) -> bool {
+        let start = *self.period_start.get_or_insert(now);
+        match self.reset_every {
+            Some(every) if now.duration_since(start) >= every => {
+                let was_exhausted = !self.available();
+                self.used = 0;
+                self.period_start = Some(now);
+                was_exhausted
+            }
+            _ => false,
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        self.used < self.max
+    }
+
+    /// Count a send. True if it was the last one allowed.
+    pub fn spend(&mut self) -> bool {
+        self.used = (self.used + 1).min(self.max);
+        !self.available()
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.max - self.used
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_stop_when_the_budget_runs_out() {
+        let start = Instant::now();
+        let mut budget = SendBudget::new(3, None);
+        let mut sent = 0;
+        let mut exhausted_at = None;
+        for n in 0..10 {
+            budget.refresh(start + Duration::from_secs(n));
+            if budget.available() {
+                sent += 1;
+                if budget.spend() {
+                    exhausted_at = Some(n);
+                }
+            }
+        }
+        assert_eq!(sent, 3);
+        assert_eq!(exhausted_at, Some(2));
+        assert_eq!(budget.remaining(), 0);
+        // No period: never comes back
+        assert!(!budget.refresh(start + Duration::from_secs(86_400)));
+        assert!(!budget.available());
+    }
+
+    #[test]
+    fn budget_refills_each_period() {
+        let start = Instant::now();
+        let hour = Duration::from_secs(3600);
+        let mut budget = SendBudget::new(2, Some(hour));
+        budget.refresh(start);
+        budget.spend();
+        assert_eq!(budget.remaining(), 1);
+        assert!(budget.spend());
+        assert!(!budget.refresh(start + hour / 2));
+        assert!(!budget.available());
+        assert!(budget.refresh(start + hour));
+        assert_eq!(budget.remaining(), 2);
+        // A period that didn't run out refills quietly
+        budget.spend();
+        assert!(!budget.refresh(start + hour * 2));
+        assert_eq!(budget.remaining(), 2);
+    }
+
+    #[test]
+    fn retries_are_jittered_within_bounds() {
+        let interval = Duration::from_secs(1);
+        let waits: Vec<Duration> = (0..20)
+            .map(|zone| {
+                let mut cooldown = Cooldown::new(interval, CooldownOn::Success)
+                    .with_retry_jitter(0.5, fastrand::Rng::with_seed(zone));
+                cooldown.record(Instant::now(), false);
+                cooldown.retry_wait.unwrap()
+            })
+            .collect();
+        assert!(waits.iter().all(|w| (interval / 2..=interval).contains(w)));
+        assert!(waits.iter().any(|&w| w != waits[0]));
+    }
+
+    #[test]
+    fn jittered_retry_waits_then_success_restores_the_interval() {
+        let start = Instant::now();
+        let mut cooldown = Cooldown::new(Duration::from_secs(1), CooldownOn::Success)
+            .with_retry_jitter(1.0, fastrand::Rng::with_seed(7));
+        cooldown.record(start, false);
+        let wait = cooldown.retry_wait.unwrap();
+        assert!(!cooldown.ready(start));
+        assert!(cooldown.ready(start + wait));
+
+        cooldown.record(start + wait, true);
+        assert!(!cooldown.ready(start + wait + Duration::from_millis(999)));
+        assert!(cooldown.ready(start + wait + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn quantize_snaps_to_steps() {
+        let gate = SendGate::new(Some(30), DEFAULT_SEND_DEADBAND);
+        assert_eq!(gate.quantize(0.0), 0.0);
+        assert_eq!(gate.quantize(1.0), 1.0);
+        assert!((gate.quantize(0.51) - 15.0 / 30.0).abs() < 1e-6);
+        assert!((gate.quantize(0.52) - 16.0 / 30.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn quantize_passthrough_without_steps() {
+        let gate = SendGate::new(None, DEFAULT_SEND_DEADBAND);
+        assert_eq!(gate.quantize(0.1234), 0.1234);
+    }
+
+    #[test]
+    fn same_step_changes_are_suppressed() {
+        let mut gate = SendGate::new(Some(10), DEFAULT_SEND_DEADBAND);
+        let first = gate.pending(0.5).unwrap();
+        gate.mark_sent(first);
+        // 0.53 and 0.46 both round to step 5
+        assert_eq!(gate.pending(0.53), None);
+        assert_eq!(gate.pending(0.46), None);
+        // 0.56 rounds to step 6
+        assert!((gate.pending(0.56).unwrap() - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn failed_send_keeps_cooldown_open_on_success_policy() {
+        let start = Instant::now();
+        let mut cd = Cooldown::new(Duration::from_millis(500), CooldownOn::Success);
+        assert!(cd.ready(start));
+        cd.record(start, false);
+        assert!(cd.ready(start + Duration::from_millis(50)));
+        cd.record(start, true);
+        assert!(!cd.ready(start + Duration::from_millis(50)));
+        assert!(cd.ready(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn failed_send_starts_cooldown_on_attempt_policy() {
+        let start = Instant::now();
+        let mut cd = Cooldown::new(Duration::from_millis(500), CooldownOn::Attempt);
+        cd.record(start, false);
+        assert!(!cd.ready(start + Duration::from_millis(50)));
+        assert!(cd.ready(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn throttle_follows_latency_up_to_cap() {
+        let base = Duration::from_millis(500);
+        let max = Duration::from_secs(3);
+        let mut cd = Cooldown::new(base, CooldownOn::Success).with_throttle(max);
+        assert_eq!(cd.interval(), base);
+
+        // Fast controller: the minimum interval stands
+        for _ in 0..10 {
+            cd.record_latency(Duration::from_millis(20));
+        }
+        assert_eq!(cd.interval(), base);
+
+        // Struggling: ~4 round-trips apart
+        for _ in 0..20 {
+            cd.record_latency(Duration::from_millis(400));
+        }
+        let slow = cd.interval();
+        assert!(slow > Duration::from_millis(1500) && slow <= Duration::from_millis(1600));
+
+        let start = Instant::now();
+        cd.record(start, true);
+        assert!(!cd.ready(start + base));
+        assert!(cd.ready(start + Duration::from_millis(1600)));
+
+        // Very slow hits the cap
+        for _ in 0..20 {
+            cd.record_latency(Duration::from_secs(5));
+        }
+        assert_eq!(cd.interval(), max);
+
+        // And it backs off once the controller recovers
+        for _ in 0..30 {
+            cd.record_latency(Duration::from_millis(20));
+        }
+        assert_eq!(cd.interval(), base);
+    }
+
+    #[test]
+    fn adaptive_cooldown_follows_send_frequency() {
+        let base = Duration::from_millis(500);
+        let max = Duration::from_secs(4);
+        let mut cd = Cooldown::new(base, CooldownOn::Success).with_adaptive(max, 0.5);
+        let start = Instant::now();
+
+        // A calm few sends leave the minimum alone
+        let mut t = start;
+        for _ in 0..CALM_SENDS {
+            cd.record(t, true);
+            t += Duration::from_secs(2);
+        }
+        assert_eq!(cd.interval(), base);
+
+        // A burst stretches it, each send further, up to the cap
+        let mut last = base;
+        for _ in 0..3 {
+            cd.record(t, true);
+            assert!(cd.interval() > last, "{:?} <= {last:?}", cd.interval());
+            last = cd.interval();
+            t += last;
+        }
+        for _ in 0..10 {
+            cd.record(t, true);
+            t += cd.interval();
+        }
+        assert_eq!(cd.interval(), max);
+        assert!(!cd.ready(t - cd.interval() + Duration::from_secs(2)));
+
+        // Calm: once the burst ages out, back to the minimum
+        let calm = t + ADAPT_WINDOW;
+        assert!(cd.ready(calm));
+        cd.record(calm, true);
+        assert_eq!(cd.interval(), base);
+        assert!(cd.ready(calm + base));
+    }
+
+    #[test]
+    fn latency_is_tracked_without_throttle() {
+        let mut cd = Cooldown::new(Duration::from_millis(500), CooldownOn::Success);
+        cd.record_latency(Duration::from_secs(2));
+        assert_eq!(cd.latency(), Some(Duration::from_secs(2)));
+        assert_eq!(cd.interval(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn small_changes_suppressed_without_steps() {
+        let mut gate = SendGate::new(None, DEFAULT_SEND_DEADBAND);
+        gate.mark_sent(0.5);
+        assert_eq!(gate.pending(0.503), None);
+        assert_eq!(gate.pending(0.51), Some(0.51));
+    }
+
+    #[test]
+    fn identical_effective_value_is_not_resent() {
+        // Even with no deadband at all
+        let mut gate = SendGate::new(None, 0.0);
+        let first = gate.pending(0.5).unwrap();
+        gate.mark_sent(first);
+        assert_eq!(gate.pending(0.5), None);
+        // Differs only below wire precision
+        assert_eq!(gate.pending(0.500_01), None);
+        assert_eq!(gate.pending(0.501), Some(0.501));
+
+        // Clamped to the same device value
+        gate.mark_sent(gate.pending(1.2).unwrap());
+        assert_eq!(gate.last_sent(), Some(1.0));
+        assert_eq!(gate.pending(1.5), None);
+    }
+
+    #[test]
+    fn settles_after_quiet_period_and_resets_on_send() {
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+        let mut settle = SettleTracker::new(Duration::from_secs(5), start);
+
+        assert!(!settle.update(secs(4)));
+        assert!(settle.update(secs(5)));
+        assert!(settle.settled());
+        // Fires once per quiet period
+        assert!(!settle.update(secs(6)));
+
+        settle.record_send(secs(7));
+        assert!(!settle.settled());
+        assert!(!settle.update(secs(11)));
+        assert!(settle.update(secs(12)));
+    }
+}
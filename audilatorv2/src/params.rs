@@ -0,0 +1,427 @@
+//! Controls the server accepts besides the master volume, from the config
+//! file's `params` list, e.g. a dialogue enhancer and a bass level:
+//!
+//! ```json
+//! {"params": [
+//!   {"name": "dialogue", "feature": "speech_band", "target": -4},
+//!   {"name": "bass", "feature": "bass", "target": -10, "rate": 0.02}
+//! ]}
+//! ```
+//!
+//! Each one has its own small controller watching one feature of the audio,
+//! independent of the volume and of each other: while the feature sits
+//! below `target` (beyond `dead_zone`) the parameter rises at `rate` per
+//! second, and above it, falls. The volume still follows overall loudness.
+//! Values go out with the volume in one payload:
+//! `{"volume": 0.42, "params": {"bass": 0.31, "dialogue": 0.6}}`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::preprocess::{Chain, ProcessorConfig, SampleProcessor};
+
+/// Edges of the speech band, Hz: where intelligibility lives.
+const SPEECH_LOW_HZ: f32 = 300.0;
+const SPEECH_HIGH_HZ: f32 = 3400.0;
+/// Upper edge of the bass band, as for scenes.
+const BASS_HZ: f32 = 200.0;
+/// Floor for a band's share of silence.
+const FLOOR_DB: f32 = -80.0;
+/// Smallest change in a parameter worth sending.
+const PARAM_DEADBAND: f32 = 0.01;
+/// Decimal places of a parameter as sent, as for the volume.
+const WIRE_DECIMALS: i32 = 4;
+
+/// What a parameter's controller listens to.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Envelope in dBFS, as the volume sees it
+    Level,
+    /// Share of energy in the speech band (300-3400 Hz) in dB, 0 = all
+    SpeechBand,
+    /// Share of energy below 200 Hz in dB, 0 = all bass
+    Bass,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ParamConfig {
+    /// Key in the payload's `params`
+    pub name: String,
+    pub feature: Feature,
+    /// Feature value to hold, dB. Below it the parameter rises.
+    pub target: f32,
+    /// dB either side of `target` left alone
+    #[serde(default = "default_dead_zone")]
+    pub dead_zone: f32,
+    /// Change per second while outside the dead zone
+    #[serde(default = "default_rate")]
+    pub rate: f32,
+    /// [low, high] the parameter stays within
+    #[serde(default = "default_range")]
+    pub range: [f32; 2],
+    /// Starting value (default: the middle of `range`)
+    #[serde(default)]
+    pub initial: Option<f32>,
+}
+
+fn default_dead_zone() -> f32 {
+    2.0
+}
+
+fn default_rate() -> f32 {
+    0.05
+}
+
+fn default_range() -> [f32; 2] {
+    [0.0, 1.0]
+}
+
+pub fn validate(params: &[ParamConfig]) -> Result<()> {
+    for (i, param) in params.iter().enumerate() {
+        if param.name == "volume" {
+            bail!("Parameter name 'volume' is taken by the master volume");
+        }
+        if params[..i].iter().any(|p| p.name == param.name) {
+            bail!("Duplicate parameter '{}'", param.name);
+        }
+        let [low, high] = param.range;
+        if low >= high {
+            bail!("Parameter '{}' has a range with low >= high", param.name);
+        }
+        if param.initial.is_some_and(|v| !(low..=high).contains(&v)) {
+            bail!("Parameter '{}' starts outside its range", param.name);
+        }
+        if param.rate < 0.0 || param.dead_zone < 0.0 {
+            bail!(
+                "Parameter '{}' needs a non-negative rate and dead_zone",
+                param.name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// One update's measurements.
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+    pub level_dbfs: f32,
+    pub speech_band_db: f32,
+    pub bass_db: f32,
+}
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Level => "level",
+            Self::SpeechBand => "speech_band",
+            Self::Bass => "bass",
+        }
+    }
+}
+
+impl Features {
+    pub fn get(&self, feature: Feature) -> f32 {
+        match feature {
+            Feature::Level => self.level_dbfs,
+            Feature::SpeechBand => self.speech_band_db,
+            Feature::Bass => self.bass_db,
+        }
+    }
+}
+
+/// Band energies of the audio since the last update.
+struct FeatureMeter {
+    speech: Chain,
+    bass: Chain,
+    scratch: Vec<f32>,
+    speech_energy: f64,
+    bass_energy: f64,
+    total_energy: f64,
+}
+
+impl FeatureMeter {
+    fn new(sample_rate: u32) -> Result<Self> {
+        let speech = [
+            ProcessorConfig::Highpass { hz: SPEECH_LOW_HZ },
+            ProcessorConfig::Lowpass { hz: SPEECH_HIGH_HZ },
+        ];
+        Ok(Self {
+            speech: Chain::new(&speech, sample_rate)?,
+            bass: Chain::new(&[ProcessorConfig::Lowpass { hz: BASS_HZ }], sample_rate)?,
+            scratch: Vec::new(),
+            speech_energy: 0.0,
+            bass_energy: 0.0,
+            total_energy: 0.0,
+        })
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        let energy = |s: &[f32]| s.iter().map(|&x| (x as f64).powi(2)).sum::<f64>();
+        self.total_energy += energy(samples);
+        for (chain, total) in [
+            (&mut self.speech, &mut self.speech_energy),
+            (&mut self.bass, &mut self.bass_energy),
+        ] {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(samples);
+            chain.process(&mut self.scratch);
+            *total += energy(&self.scratch);
+        }
+    }
+
+    /// The features since the last call, which starts the next period.
+    fn take(&mut self, level_dbfs: f32) -> Features {
+        let share = |band: f64| {
+            if self.total_energy > 0.0 && band > 0.0 {
+                ((10.0 * (band / self.total_energy).log10()) as f32).max(FLOOR_DB)
+            } else {
+                FLOOR_DB
+            }
+        };
+        let features = Features {
+            level_dbfs,
+            speech_band_db: share(self.speech_energy),
+            bass_db: share(self.bass_energy),
+        };
+        self.speech_energy = 0.0;
+        self.bass_energy = 0.0;
+        self.total_energy = 0.0;
+        features
+    }
+}
+
+struct Controller {
+    config: ParamConfig,
+    value: f32,
+    last_sent: Option<f32>,
+}
+
+impl Controller {
+    fn update(&mut self, measured: f32, dt: f32) {
+        let error = self.config.target - measured;
+        if error.abs() > self.config.dead_zone {
+            let [low, high] = self.config.range;
+            self.value = (self.value + self.config.rate * dt * error.signum()).clamp(low, high);
+        }
+    }
+
+    fn wire_value(&self) -> f32 {
+        let scale = 10f32.powi(WIRE_DECIMALS);
+        (self.value * scale).round() / scale
+    }
+}
+
+/// A parameter's state in `/status`.
+#[derive(Serialize, Clone, Debug)]
+pub struct ParamStatus {
+    pub name: String,
+    /// The feature driving it
+    pub feature: Feature,
+    /// Last measured value of that feature, dB
+    pub feature_db: Option<f32>,
+    pub value: f32,
+    pub last_sent: Option<f32>,
+}
+
+/// A zone's parameters and the measurements they follow.
+pub struct ParamSet {
+    meter: FeatureMeter,
+    controllers: Vec<Controller>,
+    update_rate_hz: f32,
+    features: Option<Features>,
+}
+
+impl ParamSet {
+    pub fn new(configs: &[ParamConfig], sample_rate: u32, update_rate_hz: f32) -> Result<Self> {
+        let controllers = configs
+            .iter()
+            .map(|config| Controller {
+                value: config
+                    .initial
+                    .unwrap_or((config.range[0] + config.range[1]) / 2.0),
+                config: config.clone(),
+                last_sent: None,
+            })
+            .collect();
+        Ok(Self {
+            meter: FeatureMeter::new(sample_rate)?,
+            controllers,
+            update_rate_hz,
+            features: None,
+        })
+    }
+
+    /// Feed samples as they arrive.
+    pub fn push(&mut self, samples: &[f32]) {
+        self.meter.push(samples);
+    }
+
+    /// One analysis update. Silence says nothing about the balance of the
+    /// content, so every parameter holds through it.
+    pub fn update(&mut self, level_dbfs: f32, silent: bool) {
+        let features = self.meter.take(level_dbfs);
+        self.features = Some(features);
+        if silent {
+            return;
+        }
+        let dt = 1.0 / self.update_rate_hz;
+        for c in &mut self.controllers {
+            c.update(features.get(c.config.feature), dt);
+        }
+    }
+
+    /// Whether any parameter moved enough since it was last sent.
+    pub fn changed(&self) -> bool {
+        self.controllers.iter().any(|c| match c.last_sent {
+            None => true,
+            Some(last) => (c.wire_value() - last).abs() > PARAM_DEADBAND,
+        })
+    }
+
+    /// Every parameter's current value as sent.
+    pub fn values(&self) -> BTreeMap<String, f32> {
+        self.controllers
+            .iter()
+            .map(|c| (c.config.name.clone(), c.wire_value()))
+            .collect()
+    }
+
+    /// Record values the controller accepted.
+    pub fn mark_sent(&mut self, values: &BTreeMap<String, f32>) {
+        for c in &mut self.controllers {
+            if let Some(&v) = values.get(&c.config.name) {
+                c.last_sent = Some(v);
+            }
+        }
+    }
+
+    /// "name <- feature" for each parameter, for the startup log.
+    pub fn describe(&self) -> String {
+        let pairs: Vec<String> = self
+            .controllers
+            .iter()
+            .map(|c| format!("{} <- {}", c.config.name, c.config.feature.name()))
+            .collect();
+        pairs.join(", ")
+    }
+
+    pub fn status(&self) -> Vec<ParamStatus> {
+        self.controllers
+            .iter()
+            .map(|c| ParamStatus {
+                name: c.config.name.clone(),
+                feature: c.config.feature,
+                feature_db: self.features.map(|f| f.get(c.config.feature)),
+                value: c.value,
+                last_sent: c.last_sent,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speech::tests::RATE;
+    use std::f32::consts::PI;
+
+    fn param(name: &str, feature: Feature, target: f32) -> ParamConfig {
+        ParamConfig {
+            name: name.into(),
+            feature,
+            target,
+            dead_zone: default_dead_zone(),
+            rate: 0.1,
+            range: default_range(),
+            initial: None,
+        }
+    }
+
+    fn tone(hz: f32, amplitude: f32, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| amplitude * (2.0 * PI * hz * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// 20 updates a second of `chunk` for `seconds`.
+    fn run(set: &mut ParamSet, chunk: &[f32], seconds: usize) {
+        for _ in 0..seconds * 20 {
+            set.push(chunk);
+            set.update(-20.0, false);
+        }
+    }
+
+    #[test]
+    fn each_parameter_follows_its_own_feature() {
+        let configs = [
+            param("dialogue", Feature::SpeechBand, -1.0),
+            param("bass", Feature::Bass, -1.0),
+        ];
+        validate(&configs).unwrap();
+        let mut set = ParamSet::new(&configs, RATE, 20.0).unwrap();
+        let window = RATE as usize / 20;
+
+        // All bass: the speech band is starved, bass is on target
+        run(&mut set, &tone(60.0, 0.3, window), 2);
+        let status = set.status();
+        assert!(status[0].feature_db.unwrap() < -20.0);
+        assert!(status[1].feature_db.unwrap() > -2.0);
+        let values = set.values();
+        assert!((values["dialogue"] - 0.7).abs() < 0.01, "{values:?}");
+        assert!((values["bass"] - 0.5).abs() < 0.01, "{values:?}");
+
+        // All speech band: now only bass moves, and dialogue holds
+        run(&mut set, &tone(1000.0, 0.3, window), 2);
+        let values = set.values();
+        assert!((values["dialogue"] - 0.7).abs() < 0.01, "{values:?}");
+        assert!((values["bass"] - 0.7).abs() < 0.01, "{values:?}");
+
+        // Pinned at the top of the range, and held through silence
+        run(&mut set, &tone(1000.0, 0.3, window), 10);
+        assert_eq!(set.values()["bass"], 1.0);
+        for _ in 0..40 {
+            set.push(&vec![0.0; window]);
+            set.update(-80.0, true);
+        }
+        assert_eq!(set.values()["bass"], 1.0);
+    }
+
+    #[test]
+    fn only_moved_parameters_count_as_changed() {
+        let configs = [param("bass", Feature::Level, -20.0)];
+        let mut set = ParamSet::new(&configs, RATE, 20.0).unwrap();
+        assert!(set.changed());
+        set.mark_sent(&set.values());
+        assert!(!set.changed());
+
+        // On target: nothing to send
+        run(&mut set, &tone(60.0, 0.1, 100), 1);
+        assert!(!set.changed());
+
+        set.update(-40.0, false);
+        assert!(!set.changed(), "a 0.005 step is under the deadband");
+        set.update(-40.0, false);
+        set.update(-40.0, false);
+        assert!(set.changed());
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        assert!(validate(&[param("volume", Feature::Level, -20.0)]).is_err());
+        let twice = [
+            param("a", Feature::Bass, 0.0),
+            param("a", Feature::Level, 0.0),
+        ];
+        assert!(validate(&twice).is_err());
+        let mut p = param("a", Feature::Bass, 0.0);
+        p.range = [0.5, 0.5];
+        assert!(validate(std::slice::from_ref(&p)).is_err());
+        p.range = [0.0, 0.4];
+        p.initial = Some(0.5);
+        assert!(validate(&[p]).is_err());
+    }
+}
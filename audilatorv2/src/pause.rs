@@ -0,0 +1,195 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::transition::Transition;
+
+/// How often to scan the process list. Slower than the kill switch: listing
+/// processes costs more than checking a file.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Whether a process with this name is running.
+pub type ProcessCheck = Box<dyn Fn(&str) -> bool + Send>;
+
+/// Holds all adjustments while a named process runs, so a game or music
+/// player can own the volume without the leveler fighting it.
+pub struct ProcessPause {
+    name: String,
+    scans: mpsc::Receiver<bool>,
+    paused: bool,
+}
+
+impl ProcessPause {
+    /// Scan for `name` every `POLL_INTERVAL` on a thread of its own: a
+    /// process listing (`ps`, `tasklist`) can take long enough to stall
+    /// the audio loop.
+    pub fn spawn(name: String, check: ProcessCheck) -> Self {
+        Self::with_interval(name, check, POLL_INTERVAL)
+    }
+
+    fn with_interval(name: String, check: ProcessCheck, interval: Duration) -> Self {
+        let (tx, scans) = mpsc::channel();
+        let target = name.clone();
+        // Ends once the pause is dropped and a send fails
+        std::thread::spawn(move || {
+            while tx.send(check(&target)).is_ok() {
+                std::thread::sleep(interval);
+            }
+        });
+        Self {
+            name,
+            scans,
+            paused: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Take the latest finished scan, without waiting for one. Returns the
+    /// change, if any.
+    pub fn poll(&mut self) -> Option<Transition> {
+        let running = self.scans.try_iter().last()?;
+        if running == self.paused {
+            return None;
+        }
+        self.paused = running;
+        Some(if running {
+            Transition::Engaged
+        } else {
+            Transition::Released
+        })
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// Names match case-insensitively, with or without a trailing ".exe".
+fn matches(candidate: &str, name: &str) -> bool {
+    let strip = |s: &str| {
+        let s = s.trim();
+        s.strip_suffix(".exe")
+            .or_else(|| s.strip_suffix(".EXE"))
+            .unwrap_or(s)
+            .to_string()
+    };
+    strip(candidate).eq_ignore_ascii_case(&strip(name))
+}
+
+/// Linux: every /proc/<pid>/comm. The kernel truncates these to 15 bytes.
+#[cfg(target_os = "linux")]
+pub fn process_running(name: &str) -> bool {
+    let truncated: String = name.chars().take(15).collect();
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        std::fs::read_to_string(entry.path().join("comm"))
+            .is_ok_and(|comm| matches(&comm, &truncated))
+    })
+}
+
+/// macOS: `ps` with bare command names.
+#[cfg(target_os = "macos")]
+pub fn process_running(name: &str) -> bool {
+    command_lists(name, "ps", &["-Ac", "-o", "comm="], |line| line)
+}
+
+/// Windows: `tasklist` as CSV, image name first.
+#[cfg(windows)]
+pub fn process_running(name: &str) -> bool {
+    command_lists(name, "tasklist", &["/FO", "CSV", "/NH"], |line| {
+        line.split(',').next().unwrap_or("").trim_matches('"')
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn process_running(_name: &str) -> bool {
+    false
+}
+
+#[cfg(any(target_os = "macos", windows))]
+fn command_lists(name: &str, program: &str, args: &[&str], field: fn(&str) -> &str) -> bool {
+    std::process::Command::new(program)
+        .args(args)
+        .output()
+        .is_ok_and(|out| {
+            String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .any(|line| matches(field(line), name))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use std::time::Instant;
+
+    /// Poll until a change arrives, for up to a second.
+    fn next_change(pause: &mut ProcessPause) -> Option<Transition> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Some(change) = pause.poll() {
+                return Some(change);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn pauses_while_process_runs() {
+        let running = Arc::new(AtomicBool::new(false));
+        let seen = running.clone();
+        let mut pause = ProcessPause::with_interval(
+            "game".into(),
+            Box::new(move |name| name == "game" && seen.load(Ordering::Relaxed)),
+            Duration::from_millis(5),
+        );
+        assert_eq!(next_change(&mut pause), None);
+        assert!(!pause.paused());
+
+        running.store(true, Ordering::Relaxed);
+        assert_eq!(next_change(&mut pause), Some(Transition::Engaged));
+        assert!(pause.paused());
+
+        running.store(false, Ordering::Relaxed);
+        assert_eq!(next_change(&mut pause), Some(Transition::Released));
+        assert!(!pause.paused());
+    }
+
+    #[test]
+    fn slow_scans_do_not_block_the_poll() {
+        let mut pause = ProcessPause::spawn(
+            "game".into(),
+            Box::new(|_| {
+                std::thread::sleep(Duration::from_millis(500));
+                true
+            }),
+        );
+        let start = Instant::now();
+        assert_eq!(pause.poll(), None);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(next_change(&mut pause), Some(Transition::Engaged));
+    }
+
+    #[test]
+    fn names_match_loosely() {
+        assert!(matches("Spotify.exe", "spotify"));
+        assert!(matches("spotify\n", "Spotify.exe"));
+        assert!(!matches("spotifyd", "spotify"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_own_process() {
+        let comm = std::fs::read_to_string("/proc/self/comm").unwrap();
+        assert!(process_running(comm.trim()));
+        assert!(!process_running("no-such-process-audilator"));
+    }
+}
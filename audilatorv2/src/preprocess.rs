@@ -0,0 +1,303 @@
+//! An ordered chain of filters in front of the analyzer, from the config
+//! file's `preprocess` list:
+//!
+//! ```json
+//! {"preprocess": [{"type": "dc_block"}, {"type": "highpass", "hz": 80}, {"type": "gain", "db": 6}]}
+//! ```
+//!
+//! Each stage sees the output of the one before, so order matters: gain
+//! before `sanitize` clips, after it doesn't.
+//!
+//! Two steps stay in the capture callback rather than becoming default
+//! stages. Conversion sanitizes each channel before the downmix, since one
+//! channel's NaN would otherwise silence the mix. `--input-gain` then scales
+//! and clips, like a preamp. `--calibrate`, `--gain-report` and
+//! `--per-channel` read the capture without a chain, and have to hear the
+//! levels the leveler hears.
+
+use std::f64::consts::PI;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::loudness::Biquad;
+
+/// Butterworth: maximally flat, no resonant bump at the corner.
+const BUTTERWORTH_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+/// Pole of the DC blocker: corner around 40 Hz at 48 kHz.
+const DC_BLOCK_POLE: f32 = 0.995;
+/// IEC 61672 A-weighting pole frequencies, Hz.
+const A_WEIGHT_POLES: [f64; 4] = [20.598997, 107.65265, 737.86223, 12194.217];
+
+pub trait SampleProcessor: Send {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProcessorConfig {
+    /// Plain gain, unclipped (follow with `sanitize` to clip)
+    Gain { db: f32 },
+    /// Remove a DC offset, e.g. from a cheap USB mic
+    DcBlock,
+    /// Second-order Butterworth high-pass
+    Highpass { hz: f32 },
+    /// Second-order Butterworth low-pass
+    Lowpass { hz: f32 },
+    /// Weight by the ear's sensitivity at moderate levels, 0 dB at 1 kHz
+    AWeighting,
+    /// Non-finite samples to silence, the rest clamped to full scale
+    Sanitize,
+}
+
+impl ProcessorConfig {
+    pub fn build(&self, sample_rate: u32) -> Result<Box<dyn SampleProcessor>> {
+        let corner = |hz: f32| {
+            let nyquist = sample_rate as f32 / 2.0;
+            if hz > 0.0 && hz < nyquist {
+                Ok(2.0 * PI * hz as f64 / sample_rate as f64)
+            } else {
+                Err(anyhow!(
+                    "preprocess: {hz} Hz must be between 0 and {nyquist} at this sample rate"
+                ))
+            }
+        };
+        Ok(match *self {
+            Self::Gain { db } => Box::new(Gain(10f32.powf(db / 20.0))),
+            Self::DcBlock => Box::new(DcBlock::default()),
+            Self::Highpass { hz } => {
+                let (w0, alpha) = rbj(corner(hz)?);
+                let a0 = 1.0 + alpha;
+                let b = (1.0 + w0.cos()) / 2.0 / a0;
+                Box::new(Filter(vec![Biquad::new(
+                    [b, -2.0 * b, b],
+                    [-2.0 * w0.cos() / a0, (1.0 - alpha) / a0],
+                )]))
+            }
+            Self::Lowpass { hz } => {
+                let (w0, alpha) = rbj(corner(hz)?);
+                let a0 = 1.0 + alpha;
+                let b = (1.0 - w0.cos()) / 2.0 / a0;
+                Box::new(Filter(vec![Biquad::new(
+                    [b, 2.0 * b, b],
+                    [-2.0 * w0.cos() / a0, (1.0 - alpha) / a0],
+                )]))
+            }
+            Self::AWeighting => Box::new(a_weighting(sample_rate)),
+            Self::Sanitize => Box::new(Sanitize),
+        })
+    }
+}
+
+/// The stages in config order.
+pub struct Chain(Vec<Box<dyn SampleProcessor>>);
+
+impl Chain {
+    pub fn new(configs: &[ProcessorConfig], sample_rate: u32) -> Result<Self> {
+        configs
+            .iter()
+            .map(|c| c.build(sample_rate))
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    #[cfg(feature = "fixed-point")]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl SampleProcessor for Chain {
+    fn process(&mut self, samples: &mut [f32]) {
+        for stage in &mut self.0 {
+            stage.process(samples);
+        }
+    }
+}
+
+struct Gain(f32);
+
+impl SampleProcessor for Gain {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples {
+            *s *= self.0;
+        }
+    }
+}
+
+/// y[n] = x[n] - x[n-1] + R * y[n-1]
+#[derive(Default)]
+struct DcBlock {
+    x1: f32,
+    y1: f32,
+}
+
+impl SampleProcessor for DcBlock {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples {
+            let y = *s - self.x1 + DC_BLOCK_POLE * self.y1;
+            self.x1 = *s;
+            self.y1 = y;
+            *s = y;
+        }
+    }
+}
+
+struct Filter(Vec<Biquad>);
+
+impl SampleProcessor for Filter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples {
+            let mut x = *s as f64;
+            for stage in &mut self.0 {
+                x = stage.process(x);
+            }
+            *s = x as f32;
+        }
+    }
+}
+
+struct Sanitize;
+
+impl SampleProcessor for Sanitize {
+    fn process(&mut self, samples: &mut [f32]) {
+        for s in samples {
+            *s = if s.is_finite() {
+                s.clamp(-1.0, 1.0)
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/// RBJ cookbook w0 and alpha for a Butterworth corner at `w0`.
+fn rbj(w0: f64) -> (f64, f64) {
+    (w0, w0.sin() / (2.0 * BUTTERWORTH_Q))
+}
+
+/// The analog A-weighting curve as three bilinear-transformed sections:
+/// s^2/(s+w1)^2, s^2/((s+w2)(s+w3)) and 1/(s+w4)^2, scaled to 0 dB at 1 kHz.
+/// Poles are prewarped so the 12 kHz one stays put at 44.1/48 kHz.
+fn a_weighting(sample_rate: u32) -> Filter {
+    let k = 2.0 * sample_rate as f64;
+    let [w1, w2, w3, w4] = A_WEIGHT_POLES.map(|f| k * (PI * f / sample_rate as f64).tan());
+    // (b2 s^2 + b1 s + b0) / (s^2 + a1 s + a0) into z
+    let bilinear = |b: [f64; 3], a: [f64; 2]| {
+        let [b2, b1, b0] = b;
+        let [a1, a0] = a;
+        let d = k * k + a1 * k + a0;
+        (
+            [
+                (b2 * k * k + b1 * k + b0) / d,
+                (2.0 * b0 - 2.0 * b2 * k * k) / d,
+                (b2 * k * k - b1 * k + b0) / d,
+            ],
+            [(2.0 * a0 - 2.0 * k * k) / d, (k * k - a1 * k + a0) / d],
+        )
+    };
+    let mut sections = [
+        bilinear([1.0, 0.0, 0.0], [2.0 * w1, w1 * w1]),
+        bilinear([1.0, 0.0, 0.0], [w2 + w3, w2 * w3]),
+        bilinear([0.0, 0.0, 1.0], [2.0 * w4, w4 * w4]),
+    ];
+    let w = 2.0 * PI * 1000.0 / sample_rate as f64;
+    let at_1k: f64 = sections.iter().map(|(b, a)| magnitude(b, a, w)).product();
+    for b in &mut sections[0].0 {
+        *b /= at_1k;
+    }
+    Filter(sections.map(|(b, a)| Biquad::new(b, a)).into())
+}
+
+/// |H(e^jw)| of a biquad with a0 = 1.
+fn magnitude(b: &[f64; 3], a: &[f64; 2], w: f64) -> f64 {
+    let eval = |c: [f64; 3]| {
+        let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+        let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+        re.hypot(im)
+    };
+    eval(*b) / eval([1.0, a[0], a[1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::rms_to_dbfs;
+
+    const RATE: u32 = 48000;
+
+    fn chain(json: &str) -> Chain {
+        let configs: Vec<ProcessorConfig> = serde_json::from_str(json).unwrap();
+        Chain::new(&configs, RATE).unwrap()
+    }
+
+    /// Level in dB of a unit sine at `hz` after the chain, once settled.
+    fn response_db(chain: &mut Chain, hz: f32) -> f32 {
+        let mut samples: Vec<f32> = (0..RATE)
+            .map(|n| (2.0 * std::f32::consts::PI * hz * n as f32 / RATE as f32).sin())
+            .collect();
+        chain.process(&mut samples);
+        let tail = &samples[RATE as usize / 2..];
+        let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+        rms_to_dbfs(rms * std::f32::consts::SQRT_2)
+    }
+
+    #[test]
+    fn stages_run_in_config_order() {
+        let mut clip_then_gain = chain(r#"[{"type": "sanitize"}, {"type": "gain", "db": 6}]"#);
+        let mut gain_then_clip = chain(r#"[{"type": "gain", "db": 6}, {"type": "sanitize"}]"#);
+        let mut a = [0.8, f32::NAN];
+        let mut b = a;
+        clip_then_gain.process(&mut a);
+        gain_then_clip.process(&mut b);
+        assert!((a[0] - 1.596).abs() < 0.001, "{a:?}");
+        assert_eq!(a[1], 0.0);
+        assert_eq!(b[0], 1.0);
+        assert_eq!(b[1], 0.0);
+    }
+
+    #[test]
+    fn highpass_and_lowpass_compose_into_a_band() {
+        let json = r#"[{"type": "highpass", "hz": 200}, {"type": "lowpass", "hz": 4000}]"#;
+        let mut band = chain(json);
+        assert!(response_db(&mut band, 1000.0).abs() < 0.5);
+        assert!(response_db(&mut chain(json), 50.0) < -20.0);
+        assert!(response_db(&mut chain(json), 16000.0) < -15.0);
+        // Each corner is 3 dB down
+        let mut hp = chain(r#"[{"type": "highpass", "hz": 200}]"#);
+        assert!((response_db(&mut hp, 200.0) + 3.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn a_weighting_matches_the_standard_curve() {
+        // IEC 61672 table values. The bilinear transform flattens the top
+        // octave a little; class 1 allows +2/-3 dB at 10 kHz.
+        for (hz, expected, tolerance) in [
+            (1000.0, 0.0, 0.1),
+            (100.0, -19.1, 0.3),
+            (10000.0, -2.5, 1.0),
+        ] {
+            let db = response_db(&mut chain(r#"[{"type": "a_weighting"}]"#), hz);
+            assert!((db - expected).abs() < tolerance, "{hz} Hz: {db}");
+        }
+    }
+
+    #[test]
+    fn dc_block_removes_an_offset() {
+        let mut dc = chain(r#"[{"type": "dc_block"}]"#);
+        let mut samples = vec![0.3; RATE as usize];
+        dc.process(&mut samples);
+        assert!(samples.last().unwrap().abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_bad_stages() {
+        assert!(serde_json::from_str::<Vec<ProcessorConfig>>(r#"[{"type": "reverb"}]"#).is_err());
+        assert!(serde_json::from_str::<Vec<ProcessorConfig>>(
+            r#"[{"type": "gain", "db": 1, "x": 2}]"#
+        )
+        .is_err());
+        let nyquist = [ProcessorConfig::Lowpass { hz: 30000.0 }];
+        assert!(Chain::new(&nyquist, RATE).is_err());
+    }
+}
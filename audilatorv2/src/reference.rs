@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+
+use crate::dsp::{rms_to_dbfs, RingBuffer};
+use crate::mix::downmix;
+
+/// Integrated loudness of a mono buffer in dBFS: the energy average of
+/// RMS windows louder than `gate_dbfs`. None if nothing passes the gate.
+pub fn integrated_dbfs(
+    samples: &[f32],
+    sample_rate: u32,
+    window_ms: f32,
+    gate_dbfs: f32,
+) -> Option<f32> {
+    let window_samples = ((sample_rate as f32 * window_ms / 1000.0) as usize).max(1);
+    let mut ring = RingBuffer::new(window_samples);
+    let mut energy = 0.0_f64;
+    let mut windows = 0usize;
+
+    for chunk in samples.chunks_exact(window_samples) {
+        ring.extend(chunk);
+        let rms = ring.rms();
+        if rms_to_dbfs(rms) > gate_dbfs {
+            energy += (rms as f64) * (rms as f64);
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        return None;
+    }
+    Some(rms_to_dbfs((energy / windows as f64).sqrt() as f32))
+}
+
+/// Measure a WAV file's integrated loudness (all channels mixed to mono).
+pub fn measure_wav(path: &Path, window_ms: f32, gate_dbfs: f32) -> Result<f32> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Cannot open reference WAV {}", path.display()))?;
+    let spec = reader.spec();
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let mono = downmix(&interleaved, spec.channels as usize);
+
+    integrated_dbfs(&mono, spec.sample_rate, window_ms, gate_dbfs)
+        .ok_or_else(|| anyhow!("Reference WAV {} is silent", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::tests::{feed_level, test_config};
+    use crate::dsp::Compressor;
+
+    fn sine(amplitude: f32, freq: f32, sample_rate: u32, secs: f32) -> Vec<f32> {
+        let n = (sample_rate as f32 * secs) as usize;
+        (0..n)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn integrated_level_of_sine() {
+        // RMS of a 0.5 sine is 0.3536 -> -9.03 dBFS
+        let samples = sine(0.5, 440.0, 8000, 2.0);
+        let db = integrated_dbfs(&samples, 8000, 50.0, -60.0).unwrap();
+        assert!((db - (-9.03)).abs() < 0.1, "got {db}");
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut samples = sine(0.5, 440.0, 8000, 1.0);
+        samples.extend(vec![0.0; 8000 * 3]);
+        let db = integrated_dbfs(&samples, 8000, 50.0, -60.0).unwrap();
+        assert!((db - (-9.03)).abs() < 0.1, "got {db}");
+        assert_eq!(integrated_dbfs(&[0.0; 8000], 8000, 50.0, -60.0), None);
+    }
+
+    #[test]
+    fn reference_wav_sets_target() {
+        let path = std::env::temp_dir().join(format!("audilator-ref-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        // -20 dBFS DC on both channels
+        let level = (0.1 * 32767.0) as i16;
+        for _ in 0..8000 * 2 {
+            writer.write_sample(level).unwrap();
+            writer.write_sample(level).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let target = measure_wav(&path, 50.0, -60.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((target - (-20.0)).abs() < 0.1, "got {target}");
+
+        // Content at the reference loudness needs no correction
+        let mut config = test_config();
+        config.target_dbfs = target;
+        let mut comp = Compressor::new(config, 0.5);
+        feed_level(&mut comp, -20.0, 2.0);
+        assert!(feed_level(&mut comp, -20.0, 5.0)
+            .iter()
+            .all(|r| r.delta_db == 0.0));
+    }
+}
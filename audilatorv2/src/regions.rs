@@ -0,0 +1,175 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// A band of envelope loudness with its own response, e.g. "very quiet"
+/// boosts hard while "normal" holds.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    /// Lower bound in dBFS (omit on the first region)
+    #[serde(default)]
+    pub from: Option<f32>,
+    /// Upper bound in dBFS (omit on the last region)
+    #[serde(default)]
+    pub to: Option<f32>,
+    /// Volume change in dB/sec while in this region (positive boosts,
+    /// negative ducks, 0 holds). With `volume` set only the magnitude is used.
+    pub rate: f32,
+    /// Volume (0.0-1.0) this region steers towards and stops at
+    #[serde(default)]
+    pub volume: Option<f32>,
+}
+
+/// Regions must run from -inf to +inf in ascending order without gaps or
+/// overlaps, each bound shared with its neighbour.
+pub fn validate(regions: &[Region]) -> Result<()> {
+    let (Some(first), Some(last)) = (regions.first(), regions.last()) else {
+        return Ok(());
+    };
+    if first.from.is_some() {
+        bail!("Region '{}' is first and must not set 'from'", first.name);
+    }
+    if last.to.is_some() {
+        bail!("Region '{}' is last and must not set 'to'", last.name);
+    }
+    for r in regions {
+        if let (Some(from), Some(to)) = (r.from, r.to) {
+            if from >= to {
+                bail!("Region '{}' has from >= to", r.name);
+            }
+        }
+        if r.volume.is_some_and(|v| !(0.0..=1.0).contains(&v)) {
+            bail!("Region '{}' volume must be 0.0-1.0", r.name);
+        }
+    }
+    for pair in regions.windows(2) {
+        match (pair[0].to, pair[1].from) {
+            (Some(to), Some(from)) if to == from => {}
+            _ => bail!(
+                "Regions '{}' and '{}' must meet ('to' of one equals 'from' of the next)",
+                pair[0].name,
+                pair[1].name
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Validated regions, replacing the dead zone model in the compressor.
+pub struct RegionMap {
+    regions: Vec<Region>,
+    update_rate: f32,
+}
+
+impl RegionMap {
+    pub fn new(regions: Vec<Region>, update_rate: f32) -> Self {
+        Self {
+            regions,
+            update_rate,
+        }
+    }
+
+    /// Region containing `level_dbfs` (lower bound inclusive).
+    pub fn find(&self, level_dbfs: f32) -> &Region {
+        self.regions
+            .iter()
+            .find(|r| r.to.is_none_or(|to| level_dbfs < to))
+            .unwrap_or_else(|| self.regions.last().unwrap())
+    }
+
+    /// Volume change in dB for one update at `level_dbfs` and current `volume`.
+    pub fn step(&self, level_dbfs: f32, volume: f32) -> f32 {
+        let region = self.find(level_dbfs);
+        match region.volume {
+            None => region.rate / self.update_rate,
+            Some(goal) => {
+                let to_goal = 20.0 * (goal.max(1e-6) / volume.max(1e-6)).log10();
+                let max_step = self.max_step(level_dbfs);
+                to_goal.clamp(-max_step, max_step)
+            }
+        }
+    }
+
+    /// Largest change in dB one update may make at `level_dbfs`.
+    pub fn max_step(&self, level_dbfs: f32) -> f32 {
+        (self.find(level_dbfs).rate / self.update_rate).abs()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn region(name: &str, from: Option<f32>, to: Option<f32>, rate: f32) -> Region {
+        Region {
+            name: name.to_string(),
+            from,
+            to,
+            rate,
+            volume: None,
+        }
+    }
+
+    pub(crate) fn five_regions() -> Vec<Region> {
+        vec![
+            region("very quiet", None, Some(-50.0), 20.0),
+            region("quiet", Some(-50.0), Some(-35.0), 4.0),
+            region("normal", Some(-35.0), Some(-18.0), 0.0),
+            region("loud", Some(-18.0), Some(-10.0), -8.0),
+            region("very loud", Some(-10.0), None, -40.0),
+        ]
+    }
+
+    #[test]
+    fn readings_select_their_region() {
+        let map = RegionMap::new(five_regions(), 20.0);
+        for (level, name, step) in [
+            (-70.0, "very quiet", 1.0),
+            (-50.0, "quiet", 0.2),
+            (-25.0, "normal", 0.0),
+            (-12.0, "loud", -0.4),
+            (-3.0, "very loud", -2.0),
+        ] {
+            assert_eq!(map.find(level).name, name);
+            assert!((map.step(level, 0.5) - step).abs() < 1e-6, "{name}");
+        }
+    }
+
+    #[test]
+    fn volume_goal_stops_the_ramp() {
+        let mut regions = five_regions();
+        regions[4].volume = Some(0.2);
+        let map = RegionMap::new(regions, 20.0);
+        // Far above the goal: full rate down
+        assert!((map.step(-3.0, 0.8) + 2.0).abs() < 1e-6);
+        // Just above: only the remaining distance
+        let step = map.step(-3.0, 0.21);
+        assert!(step < 0.0 && step > -1.0);
+        // Below the goal: back up towards it
+        assert!(map.step(-3.0, 0.1) > 0.0);
+    }
+
+    #[test]
+    fn validation_requires_contiguous_ordered_regions() {
+        assert!(validate(&five_regions()).is_ok());
+        assert!(validate(&[]).is_ok());
+
+        let mut gap = five_regions();
+        gap[2].from = Some(-34.0);
+        assert!(validate(&gap).is_err());
+
+        let mut reversed = five_regions();
+        reversed.reverse();
+        assert!(validate(&reversed).is_err());
+
+        let mut bounded = five_regions();
+        bounded[0].from = Some(-90.0);
+        assert!(validate(&bounded).is_err());
+
+        let mut empty = five_regions();
+        empty[1].to = Some(-50.0);
+        empty[2].from = Some(-50.0);
+        assert!(validate(&empty).is_err());
+    }
+}
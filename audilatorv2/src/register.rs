@@ -0,0 +1,103 @@
+use serde::Serialize;
+
+/// Announcement sent to `--register-url` at startup and shutdown.
+#[derive(Serialize)]
+pub struct Registration {
+    pub hostname: String,
+    pub version: &'static str,
+    pub capabilities: Vec<&'static str>,
+}
+
+impl Registration {
+    pub fn new(capabilities: Vec<&'static str>) -> Self {
+        Self {
+            hostname: hostname(),
+            version: env!("CARGO_PKG_VERSION"),
+            capabilities,
+        }
+    }
+}
+
+/// POST the registration. Failures are reported but never fatal.
+pub async fn register(client: &reqwest::Client, url: &str, info: &Registration) -> bool {
+    match client.post(url).json(info).send().await {
+        Ok(resp) if resp.status().is_success() => {
+            tracing::info!("Registered with {url}");
+            true
+        }
+        Ok(resp) => {
+            tracing::warn!("Registration rejected by {url}: {}", resp.status());
+            false
+        }
+        Err(e) => {
+            tracing::warn!("Registration failed ({url}): {e}");
+            false
+        }
+    }
+}
+
+/// DELETE the registration on graceful shutdown. Failures are reported but never fatal.
+pub async fn deregister(client: &reqwest::Client, url: &str, info: &Registration) -> bool {
+    match client.delete(url).json(info).send().await {
+        Ok(resp) if resp.status().is_success() => true,
+        Ok(resp) => {
+            tracing::warn!("Deregistration rejected by {url}: {}", resp.status());
+            false
+        }
+        Err(e) => {
+            tracing::warn!("Deregistration failed ({url}): {e}");
+            false
+        }
+    }
+}
+
+pub fn hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::MockServer;
+
+    #[tokio::test]
+    async fn registers_and_deregisters() {
+        let server = MockServer::start(200).await;
+        let url = server.url("/controllers");
+        let client = reqwest::Client::new();
+        let info = Registration::new(vec!["volume"]);
+
+        assert!(register(&client, &url, &info).await);
+        assert!(deregister(&client, &url, &info).await);
+
+        let reqs = server.requests();
+        assert_eq!(reqs.len(), 2);
+        assert_eq!(reqs[0].method, "POST");
+        assert_eq!(reqs[1].method, "DELETE");
+        assert!(reqs.iter().all(|r| r.path == "/controllers"));
+
+        let body: serde_json::Value = serde_json::from_str(&reqs[0].body).unwrap();
+        assert_eq!(body["hostname"], info.hostname.as_str());
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["capabilities"][0], "volume");
+    }
+
+    #[tokio::test]
+    async fn failures_are_not_fatal() {
+        let server = MockServer::start(503).await;
+        let client = reqwest::Client::new();
+        let info = Registration::new(vec![]);
+        assert!(!register(&client, &server.url("/"), &info).await);
+
+        // Nothing listening on this port
+        let url = MockServer::closed_url().await;
+        assert!(!register(&client, &url, &info).await);
+        assert!(!deregister(&client, &url, &info).await);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::dsp::VarianceTracker;
+
+/// Seconds of envelope history for the dynamics feature.
+const DYNAMICS_WINDOW_SEC: f32 = 5.0;
+/// A new scene must win this long before it takes over.
+const SWITCH_AFTER_SEC: f32 = 3.0;
+/// Content further than this (summed dB outside a scene's ranges) from every
+/// scene matches none of them.
+const MAX_MISS_DB: f32 = 6.0;
+/// Upper edge of the bass band.
+const BASS_HZ: f32 = 200.0;
+/// Floor for the bass share of silence.
+const FLOOR_DB: f32 = -80.0;
+
+/// A kind of content, e.g. "dialogue" or "action", recognized by its
+/// signature and levelled to its own target. Omitted ranges match anything.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    pub name: String,
+    /// Envelope [low, high]
+    #[serde(default)]
+    pub level: Option<[f32; 2]>,
+    /// Envelope std-dev in dB over the last few seconds [low, high]
+    #[serde(default)]
+    pub dynamics: Option<[f32; 2]>,
+    /// Share of energy below 200 Hz in dB, 0 = all bass [low, high]
+    #[serde(default)]
+    pub bass: Option<[f32; 2]>,
+    /// Target while this scene plays
+    pub target: f32,
+}
+
+pub fn validate(scenes: &[Scene]) -> Result<()> {
+    for (i, scene) in scenes.iter().enumerate() {
+        if scenes[..i].iter().any(|s| s.name == scene.name) {
+            bail!("Duplicate scene '{}'", scene.name);
+        }
+        let ranges = [scene.level, scene.dynamics, scene.bass];
+        if ranges.iter().flatten().any(|[low, high]| low > high) {
+            bail!("Scene '{}' has a range with low > high", scene.name);
+        }
+    }
+    Ok(())
+}
+
+/// What the classifier looks at, once per update.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneFeatures {
+    pub level_dbfs: f32,
+    pub dynamics_db: f32,
+    pub bass_db: f32,
+}
+
+/// The best-matching scene for `features`, if any is close enough. Earlier
+/// scenes win ties.
+pub fn classify(scenes: &[Scene], features: &SceneFeatures) -> Option<usize> {
+    let outside = |range: Option<[f32; 2]>, value: f32| {
+        range.map_or(0.0, |[low, high]| (low - value).max(value - high).max(0.0))
+    };
+    scenes
+        .iter()
+        .map(|s| {
+            outside(s.level, features.level_dbfs)
+                + outside(s.dynamics, features.dynamics_db)
+                + outside(s.bass, features.bass_db)
+        })
+        .enumerate()
+        .filter(|&(_, miss)| miss <= MAX_MISS_DB)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// The bass feature, measured on samples as they arrive.
+pub struct BassMeter {
+    coeff: f32,
+    state: f32,
+    bass_energy: f64,
+    total_energy: f64,
+}
+
+impl BassMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            coeff: 1.0 - (-2.0 * std::f32::consts::PI * BASS_HZ / sample_rate as f32).exp(),
+            state: 0.0,
+            bass_energy: 0.0,
+            total_energy: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            self.state += self.coeff * (s - self.state);
+            self.bass_energy += (self.state as f64).powi(2);
+            self.total_energy += (s as f64).powi(2);
+        }
+    }
+
+    /// Share of energy below 200 Hz since the last call, in dB.
+    pub fn take(&mut self) -> f32 {
+        let bass_db = if self.total_energy > 0.0 {
+            ((10.0 * (self.bass_energy / self.total_energy).log10()) as f32).max(FLOOR_DB)
+        } else {
+            FLOOR_DB
+        };
+        self.bass_energy = 0.0;
+        self.total_energy = 0.0;
+        bass_db
+    }
+}
+
+/// Tracks which scene is playing from the envelope and the bass feature.
+pub struct SceneClassifier {
+    scenes: Vec<Scene>,
+    dynamics: VarianceTracker,
+    active: Option<usize>,
+    /// Scene winning the most recent updates, and for how many
+    candidate: (Option<usize>, usize),
+    switch_after: usize,
+}
+
+impl SceneClassifier {
+    pub fn new(scenes: Vec<Scene>, update_rate_hz: f32) -> Self {
+        Self {
+            scenes,
+            dynamics: VarianceTracker::new(DYNAMICS_WINDOW_SEC, 0.0, update_rate_hz),
+            active: None,
+            candidate: (None, 0),
+            switch_after: (SWITCH_AFTER_SEC * update_rate_hz) as usize,
+        }
+    }
+
+    /// One analysis update at envelope `env`, with `bass_db` from a
+    /// `BassMeter`. Returns the active scene.
+    pub fn update(&mut self, env: f32, bass_db: f32) -> Option<usize> {
+        self.dynamics.push(env);
+        let features = SceneFeatures {
+            level_dbfs: env,
+            dynamics_db: self.dynamics.std_dev(),
+            bass_db,
+        };
+
+        let best = classify(&self.scenes, &features);
+        self.candidate = match self.candidate {
+            (scene, n) if scene == best => (scene, n + 1),
+            _ => (best, 1),
+        };
+        if self.candidate.1 >= self.switch_after {
+            self.active = best;
+        }
+        self.active
+    }
+
+    pub fn scene(&self, index: usize) -> &Scene {
+        &self.scenes[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenes() -> Vec<Scene> {
+        serde_json::from_str(
+            r#"[
+                {"name": "dialogue", "level": [-40, -20], "dynamics": [2, 8], "bass": [-40, -8], "target": -22},
+                {"name": "action", "level": [-25, 0], "dynamics": [6, 30], "bass": [-8, 0], "target": -30},
+                {"name": "music", "dynamics": [0, 3], "target": -26}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    fn features(level_dbfs: f32, dynamics_db: f32, bass_db: f32) -> SceneFeatures {
+        SceneFeatures {
+            level_dbfs,
+            dynamics_db,
+            bass_db,
+        }
+    }
+
+    #[test]
+    fn signatures_pick_scenes() {
+        let s = scenes();
+        assert_eq!(classify(&s, &features(-30.0, 5.0, -15.0)), Some(0));
+        assert_eq!(classify(&s, &features(-12.0, 12.0, -3.0)), Some(1));
+        assert_eq!(classify(&s, &features(-28.0, 1.0, -10.0)), Some(2));
+        // Nothing like any of them
+        assert_eq!(classify(&s, &features(-70.0, 20.0, -60.0)), None);
+    }
+
+    #[test]
+    fn nearest_wins_when_none_fits() {
+        let s = scenes();
+        // Dialogue, a little too quiet (2 dB off) beats music (3 dB too dynamic)
+        assert_eq!(classify(&s, &features(-42.0, 6.0, -15.0)), Some(0));
+    }
+
+    #[test]
+    fn rejects_bad_scenes() {
+        let mut s = scenes();
+        validate(&s).unwrap();
+        s[1].name = "dialogue".into();
+        assert!(validate(&s).is_err());
+        let mut s = scenes();
+        s[0].level = Some([-20.0, -40.0]);
+        assert!(validate(&s).is_err());
+    }
+
+    const RATE: u32 = 8000;
+
+    /// `secs` of a tone at `hz`, updating every 50ms, with the envelope
+    /// alternating by `swing_db` around `level_dbfs`.
+    fn run(
+        (classifier, bass): &mut (SceneClassifier, BassMeter),
+        hz: f32,
+        level_dbfs: f32,
+        swing_db: f32,
+        secs: f32,
+    ) -> Option<usize> {
+        let mut active = None;
+        let mut phase = 0.0_f32;
+        for n in 0..(secs * 20.0) as usize {
+            let amplitude = 10f32.powf(level_dbfs / 20.0);
+            let chunk: Vec<f32> = (0..RATE as usize / 20)
+                .map(|_| {
+                    phase += 2.0 * std::f32::consts::PI * hz / RATE as f32;
+                    amplitude * phase.sin()
+                })
+                .collect();
+            bass.push(&chunk);
+            let swing = if n % 2 == 0 { swing_db } else { -swing_db };
+            active = classifier.update(level_dbfs + swing, bass.take());
+        }
+        active
+    }
+
+    #[test]
+    fn synthetic_signals_map_to_scenes() {
+        let mut c = (SceneClassifier::new(scenes(), 20.0), BassMeter::new(RATE));
+        // Midrange, moderate level, some movement: dialogue
+        assert_eq!(run(&mut c, 1000.0, -30.0, 4.0, 6.0), Some(0));
+        // Bass-heavy, loud and jumpy: action
+        assert_eq!(run(&mut c, 60.0, -12.0, 10.0, 6.0), Some(1));
+        // Steady: music
+        assert_eq!(run(&mut c, 440.0, -28.0, 0.5, 8.0), Some(2));
+        assert_eq!(c.0.scene(2).name, "music");
+    }
+
+    #[test]
+    fn scene_changes_wait_for_the_new_one_to_hold() {
+        let mut c = (SceneClassifier::new(scenes(), 20.0), BassMeter::new(RATE));
+        assert_eq!(run(&mut c, 1000.0, -30.0, 4.0, 6.0), Some(0));
+        // A second of something else doesn't switch
+        assert_eq!(run(&mut c, 60.0, -12.0, 10.0, 1.0), Some(0));
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// `recalibrate` section of the config file: when to re-derive target and
+/// dead zone from recent content. Set exactly one of `every_hours` or `at`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecalibrateConfig {
+    #[serde(default)]
+    pub every_hours: Option<f32>,
+    /// Daily at this local time, "HH:MM"
+    #[serde(default)]
+    pub at: Option<String>,
+    /// Minutes of recent loudness to calibrate from
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: f32,
+}
+
+fn default_window_minutes() -> f32 {
+    30.0
+}
+
+enum Kind {
+    Every(Duration),
+    Daily(NaiveTime),
+}
+
+/// Decides when the next recalibration is due. Takes the current time on
+/// every call so tests can drive it with any clock.
+///
+/// Occurrences are kept as instants, not wall-clock times, so DST changes
+/// neither stretch an interval nor repeat a daily run. A daily time that
+/// falls in a spring-forward gap runs when the gap ends; one that occurs
+/// twice in a fall-back overlap runs at the first.
+pub struct RecalibrationSchedule {
+    kind: Kind,
+    /// None for the system's local time
+    timezone: Option<Tz>,
+    next: Option<DateTime<Utc>>,
+}
+
+impl RecalibrationSchedule {
+    pub fn new(config: &RecalibrateConfig, timezone: Option<Tz>) -> Result<Self> {
+        let kind = match (config.every_hours, &config.at) {
+            (Some(hours), None) if hours > 0.0 => {
+                Kind::Every(Duration::seconds((hours * 3600.0) as i64))
+            }
+            (Some(_), None) => bail!("recalibrate.every_hours must be positive"),
+            (None, Some(at)) => Kind::Daily(
+                NaiveTime::parse_from_str(at, "%H:%M")
+                    .with_context(|| format!("recalibrate.at '{at}' is not HH:MM"))?,
+            ),
+            _ => bail!("recalibrate needs exactly one of 'every_hours' or 'at'"),
+        };
+        if config.window_minutes <= 0.0 {
+            bail!("recalibrate.window_minutes must be positive");
+        }
+        Ok(Self {
+            kind,
+            timezone,
+            next: None,
+        })
+    }
+
+    fn following(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        match self.kind {
+            Kind::Every(interval) => after + interval,
+            Kind::Daily(time) => {
+                let today = self.local(after).date().and_time(time);
+                let at = self.resolve(today);
+                if at > after {
+                    at
+                } else {
+                    self.resolve(today + Duration::days(1))
+                }
+            }
+        }
+    }
+
+    fn local(&self, t: DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            Some(tz) => t.with_timezone(&tz).naive_local(),
+            None => t.with_timezone(&Local).naive_local(),
+        }
+    }
+
+    fn instants(&self, local: NaiveDateTime) -> LocalResult<DateTime<Utc>> {
+        match self.timezone {
+            Some(tz) => tz.from_local_datetime(&local).map(|t| t.to_utc()),
+            None => Local.from_local_datetime(&local).map(|t| t.to_utc()),
+        }
+    }
+
+    /// The instant of a local time: the first of two in an overlap, the end
+    /// of the gap for one that is skipped.
+    fn resolve(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        (0..=24 * 60)
+            .find_map(|m| self.instants(local + Duration::minutes(m)).earliest())
+            .unwrap_or_else(|| local.and_utc())
+    }
+
+    /// True once each time the schedule comes round. The first call only
+    /// starts the clock.
+    pub fn due(&mut self, now: DateTime<Utc>) -> bool {
+        let Some(next) = self.next else {
+            self.next = Some(self.following(now));
+            return false;
+        };
+        if now < next {
+            return false;
+        }
+        // Skip any missed occurrences (e.g. after suspend) rather than firing for each
+        self.next = Some(self.following(now));
+        true
+    }
+
+    /// When the next recalibration will run, in local time, once started.
+    pub fn next(&self) -> Option<NaiveDateTime> {
+        self.next.map(|t| self.local(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    /// The tests' wall clock: New York local time, unambiguous outside
+    /// the DST changes (2024-03-10 and 2024-11-03, both at 02:00).
+    const NEW_YORK: Tz = chrono_tz::America::New_York;
+
+    fn utc(local: NaiveDateTime) -> DateTime<Utc> {
+        NEW_YORK.from_local_datetime(&local).unwrap().to_utc()
+    }
+
+    fn schedule(every_hours: Option<f32>, at: Option<&str>) -> RecalibrationSchedule {
+        RecalibrationSchedule::new(&config(every_hours, at), Some(NEW_YORK)).unwrap()
+    }
+
+    fn config(every_hours: Option<f32>, at: Option<&str>) -> RecalibrateConfig {
+        RecalibrateConfig {
+            every_hours,
+            at: at.map(str::to_string),
+            window_minutes: 30.0,
+        }
+    }
+
+    #[test]
+    fn daily_fires_at_the_configured_time() {
+        let mut s = schedule(None, Some("21:00"));
+        assert!(!s.due(utc(at(1, 18, 0))));
+        assert_eq!(s.next(), Some(at(1, 21, 0)));
+        assert!(!s.due(utc(at(1, 20, 59))));
+        assert!(s.due(utc(at(1, 21, 0))));
+        // Not again until tomorrow evening
+        assert!(!s.due(utc(at(1, 23, 0))));
+        assert!(!s.due(utc(at(2, 20, 0))));
+        assert!(s.due(utc(at(2, 21, 1))));
+    }
+
+    #[test]
+    fn daily_started_after_the_time_waits_for_tomorrow() {
+        let mut s = schedule(None, Some("07:30"));
+        s.due(utc(at(1, 9, 0)));
+        assert_eq!(s.next(), Some(at(2, 7, 30)));
+    }
+
+    #[test]
+    fn interval_fires_every_n_hours_and_skips_missed_runs() {
+        let mut s = schedule(Some(6.0), None);
+        assert!(!s.due(utc(at(1, 0, 0))));
+        assert!(!s.due(utc(at(1, 5, 59))));
+        assert!(s.due(utc(at(1, 6, 0))));
+        // Asleep for a day: one recalibration, then back on a 6h cadence
+        assert!(s.due(utc(at(2, 7, 0))));
+        assert!(!s.due(utc(at(2, 8, 0))));
+        assert_eq!(s.next(), Some(at(2, 13, 0)));
+    }
+
+    #[test]
+    fn interval_keeps_real_hours_across_dst() {
+        // 00:00 EST + 6h is 07:00 EDT: the spring-forward hour still counts
+        let mut s = schedule(Some(6.0), None);
+        assert!(!s.due(utc(at(10, 0, 0))));
+        assert!(!s.due(utc(at(10, 6, 30))));
+        assert_eq!(s.next(), Some(at(10, 7, 0)));
+        assert!(s.due(utc(at(10, 7, 0))));
+    }
+
+    #[test]
+    fn daily_time_in_the_gap_runs_once_when_it_ends() {
+        // 02:30 doesn't exist on 2024-03-10; 03:00 EDT follows 01:59 EST
+        let mut s = schedule(None, Some("02:30"));
+        assert!(!s.due(utc(at(10, 0, 0))));
+        assert_eq!(s.next(), Some(at(10, 3, 0)));
+        assert!(!s.due(utc(at(10, 1, 59))));
+        assert!(s.due(utc(at(10, 3, 0))));
+        assert!(!s.due(utc(at(10, 23, 0))));
+        // Back to 02:30 the next night
+        assert_eq!(s.next(), Some(at(11, 2, 30)));
+    }
+
+    #[test]
+    fn daily_time_in_the_overlap_runs_only_the_first_time() {
+        let day = |h, m| {
+            NaiveDate::from_ymd_opt(2024, 11, 3)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        // 01:30 happens twice on 2024-11-03, first EDT (05:30 UTC), then EST
+        let first = NEW_YORK
+            .from_local_datetime(&day(1, 30))
+            .earliest()
+            .unwrap();
+        let second = NEW_YORK.from_local_datetime(&day(1, 30)).latest().unwrap();
+        assert_eq!(second - first, Duration::hours(1));
+
+        let mut s = schedule(None, Some("01:30"));
+        assert!(!s.due(utc(day(0, 0))));
+        assert!(s.due(first.to_utc()));
+        assert!(!s.due(second.to_utc()));
+        assert!(!s.due(second.to_utc() + Duration::minutes(30)));
+        assert_eq!(s.next().map(|t| t.date()), day(0, 0).date().succ_opt());
+    }
+
+    #[test]
+    fn rejects_ambiguous_or_malformed_schedules() {
+        assert!(RecalibrationSchedule::new(&config(None, None), None).is_err());
+        assert!(RecalibrationSchedule::new(&config(Some(1.0), Some("21:00")), None).is_err());
+        assert!(RecalibrationSchedule::new(&config(None, Some("9pm")), None).is_err());
+        assert!(RecalibrationSchedule::new(&config(Some(0.0), None), None).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::dsp::rms_to_dbfs;
+
+/// Seconds of audio the self-test listens to.
+const SELFTEST_SEC: f32 = 2.0;
+
+/// Outcome of the startup input check.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Pass {
+        level_dbfs: f32,
+    },
+    /// Too quiet to be a live mic near a playing TV
+    TooQuiet {
+        level_dbfs: f32,
+    },
+}
+
+/// Checks that the first seconds of capture carry real signal, catching a
+/// muted or wrong input that would otherwise run "fine" doing nothing.
+pub struct SelfTest {
+    min_dbfs: f32,
+    remaining: usize,
+    sum_squares: f64,
+    count: usize,
+}
+
+impl SelfTest {
+    pub fn new(min_dbfs: f32, sample_rate: u32) -> Self {
+        Self {
+            min_dbfs,
+            remaining: (SELFTEST_SEC * sample_rate as f32) as usize,
+            sum_squares: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Feed captured samples. Returns the verdict once enough has been heard.
+    pub fn push(&mut self, samples: &[f32]) -> Option<Verdict> {
+        let take = samples.len().min(self.remaining);
+        self.sum_squares += samples[..take]
+            .iter()
+            .map(|&s| (s as f64) * (s as f64))
+            .sum::<f64>();
+        self.count += take;
+        self.remaining -= take;
+        if self.remaining > 0 || self.count == 0 {
+            return None;
+        }
+
+        let rms = (self.sum_squares / self.count as f64).sqrt() as f32;
+        let level_dbfs = rms_to_dbfs(rms);
+        Some(if level_dbfs >= self.min_dbfs {
+            Verdict::Pass { level_dbfs }
+        } else {
+            Verdict::TooQuiet { level_dbfs }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(amplitude: f32) -> Verdict {
+        let mut test = SelfTest::new(-50.0, 8000);
+        let mut verdict = None;
+        for _ in 0..100 {
+            verdict = verdict.or(test.push(&vec![amplitude; 400]));
+        }
+        verdict.unwrap()
+    }
+
+    #[test]
+    fn active_input_passes() {
+        // About -20 dBFS
+        assert!(
+            matches!(run(0.1), Verdict::Pass { level_dbfs } if (level_dbfs + 20.0).abs() < 0.1)
+        );
+    }
+
+    #[test]
+    fn muted_input_fails() {
+        assert!(matches!(run(0.0), Verdict::TooQuiet { .. }));
+        // Hiss from an unconnected input, about -70 dBFS
+        assert!(matches!(run(0.0003), Verdict::TooQuiet { level_dbfs } if level_dbfs < -60.0));
+    }
+
+    #[test]
+    fn verdict_comes_once_after_the_test_period() {
+        let mut test = SelfTest::new(-50.0, 8000);
+        // 1.95s: not yet
+        for _ in 0..39 {
+            assert_eq!(test.push(&[0.1; 400]), None);
+        }
+        assert!(test.push(&[0.1; 400]).is_some());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{mpsc, watch};
+
+use crate::sink::{Controls, VolumeSink};
+
+/// What happened to one send, reported back to the main loop.
+#[derive(Debug)]
+pub struct SendOutcome {
+    pub zone: usize,
+    pub volume: f32,
+    /// Parameters sent with it, if any
+    pub params: BTreeMap<String, f32>,
+    /// (endpoint name, error) for every endpoint still without the value
+    pub failures: Vec<(String, String)>,
+    /// Time spent delivering to all endpoints
+    pub latency: Duration,
+}
+
+/// Background task delivering volumes to a zone's sinks. Submitting never
+/// waits on the network: only the newest value is kept, so a slow endpoint
+/// gets the latest decision rather than a backlog.
+///
+/// When some endpoints fail, submitting the same controls again retries
+/// only those: the others already have them.
+pub struct Sender {
+    tx: watch::Sender<Option<Controls>>,
+    endpoints: Vec<String>,
+    forget: Arc<AtomicBool>,
+}
+
+impl Sender {
+    /// Decisions arriving within `coalesce` of the first are collapsed into
+    /// a single send of the last one.
+    pub fn spawn(
+        zone: usize,
+        sinks: Vec<Box<dyn VolumeSink>>,
+        coalesce: Duration,
+        outcomes: mpsc::UnboundedSender<SendOutcome>,
+    ) -> Self {
+        let (tx, rx) = watch::channel(None);
+        let endpoints = sinks.iter().map(|s| s.name().to_string()).collect();
+        let forget = Arc::new(AtomicBool::new(false));
+        tokio::spawn(run(zone, rx, sinks, coalesce, outcomes, forget.clone()));
+        Self {
+            tx,
+            endpoints,
+            forget,
+        }
+    }
+
+    pub fn submit(&self, controls: Controls) {
+        self.tx.send_replace(Some(controls));
+    }
+
+    /// What the endpoints have is no longer known (e.g. one changed at the
+    /// device): the next send goes to all of them.
+    pub fn forget_delivered(&self) {
+        self.forget.store(true, Ordering::Relaxed);
+    }
+
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+}
+
+async fn run(
+    zone: usize,
+    mut rx: watch::Receiver<Option<Controls>>,
+    sinks: Vec<Box<dyn VolumeSink>>,
+    coalesce: Duration,
+    outcomes: mpsc::UnboundedSender<SendOutcome>,
+    forget: Arc<AtomicBool>,
+) {
+    // The last controls some endpoints failed, and which have them
+    let mut partial: Option<(Controls, Vec<bool>)> = None;
+    while rx.changed().await.is_ok() {
+        if !coalesce.is_zero() {
+            tokio::time::sleep(coalesce).await;
+        }
+        let Some(controls) = rx.borrow_and_update().clone() else {
+            continue;
+        };
+
+        if forget.swap(false, Ordering::Relaxed) {
+            partial = None;
+        }
+        let mut delivered = match partial.take() {
+            Some((sent, delivered)) if sent == controls => delivered,
+            _ => vec![false; sinks.len()],
+        };
+
+        let started = Instant::now();
+        let mut failures = Vec::new();
+        for (sink, delivered) in sinks.iter().zip(&mut delivered) {
+            if *delivered {
+                continue;
+            }
+            match sink.set_controls(&controls).await {
+                Ok(()) => *delivered = true,
+                Err(e) => failures.push((sink.name().to_string(), e.to_string())),
+            }
+        }
+        if !failures.is_empty() {
+            partial = Some((controls.clone(), delivered));
+        }
+        let outcome = SendOutcome {
+            zone,
+            volume: controls.volume,
+            params: controls.params,
+            failures,
+            latency: started.elapsed(),
+        };
+        if outcomes.send(outcome).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::tests::RecordingSink;
+
+    fn volume(volume: f32) -> Controls {
+        Controls {
+            volume,
+            ..Controls::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn flood_within_window_sends_final_value_once() {
+        let sink = RecordingSink::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sender = Sender::spawn(
+            0,
+            vec![Box::new(sink.clone())],
+            Duration::from_millis(100),
+            tx,
+        );
+
+        for i in 0..20 {
+            sender.submit(volume(i as f32 / 100.0));
+        }
+
+        let outcome = rx.recv().await.unwrap();
+        assert_eq!(outcome.volume, 0.19);
+        assert!(outcome.failures.is_empty());
+        assert_eq!(sink.sent(), vec![0.19]);
+
+        // Nothing else queued behind it
+        let more = tokio::time::timeout(Duration::from_millis(200), rx.recv()).await;
+        assert!(more.is_err());
+    }
+
+    #[tokio::test]
+    async fn without_window_each_spaced_decision_is_sent() {
+        let sink = RecordingSink::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sender = Sender::spawn(3, vec![Box::new(sink.clone())], Duration::ZERO, tx);
+
+        sender.submit(volume(0.4));
+        assert_eq!(rx.recv().await.unwrap().zone, 3);
+        sender.submit(volume(0.5));
+        rx.recv().await.unwrap();
+        assert_eq!(sink.sent(), vec![0.4, 0.5]);
+    }
+
+    #[tokio::test]
+    async fn a_retry_only_goes_to_the_endpoints_that_failed() {
+        let (up, down) = (RecordingSink::default(), RecordingSink::default());
+        down.failing.store(true, Ordering::Relaxed);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sinks: Vec<Box<dyn VolumeSink>> = vec![Box::new(up.clone()), Box::new(down.clone())];
+        let sender = Sender::spawn(0, sinks, Duration::ZERO, tx);
+
+        sender.submit(volume(0.4));
+        assert_eq!(rx.recv().await.unwrap().failures.len(), 1);
+        // Still failing: the one that has it isn't asked again
+        sender.submit(volume(0.4));
+        assert_eq!(rx.recv().await.unwrap().failures.len(), 1);
+        down.failing.store(false, Ordering::Relaxed);
+        sender.submit(volume(0.4));
+        assert!(rx.recv().await.unwrap().failures.is_empty());
+        assert_eq!((up.sent(), down.sent()), (vec![0.4], vec![0.4]));
+
+        // A new value, or the same once what they have is unknown, goes to both
+        sender.submit(volume(0.5));
+        rx.recv().await.unwrap();
+        down.failing.store(true, Ordering::Relaxed);
+        sender.submit(volume(0.6));
+        rx.recv().await.unwrap();
+        down.failing.store(false, Ordering::Relaxed);
+        sender.forget_delivered();
+        sender.submit(volume(0.6));
+        rx.recv().await.unwrap();
+        assert_eq!(
+            (up.sent(), down.sent()),
+            (vec![0.4, 0.5, 0.6, 0.6], vec![0.4, 0.5, 0.6])
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Level features in a POSIX shared-memory ring, for local consumers that
+//! want each capture's numbers without a socket in the way.
+//!
+//! Layout, native endian, at the start of the region (`--shm audilator` is
+//! `/dev/shm/audilator` on Linux):
+//!
+//! ```text
+//! offset  size  header
+//!  0      4     magic       0x4C445541 ("AUDL")
+//!  4      4     version     1
+//!  8      4     capacity    slots in the ring
+//! 12      4     slot_size   32
+//! 16      8     write_index records written so far (atomic)
+//! 24      40    reserved
+//!
+//! 64 + (n % capacity) * 32, one slot per record n:
+//!  0      4     seq         seqlock: odd while the slot is being written
+//!  4      4     zone        zone index, in config order
+//!  8      8     timestamp   microseconds since the Unix epoch
+//! 16      4     rms         linear, f32
+//! 20      4     peak        linear absolute, f32
+//! 24      4     dbfs        RMS in dBFS, f32
+//! 28      4     reserved
+//! ```
+//!
+//! Readers load `write_index` (acquire). The newest record is at
+//! `write_index - 1`. For a slot, they load `seq` (acquire), skip it if odd,
+//! copy the fields, load `seq` again, and retry if it changed: the writer
+//! lapped them mid-read.
+//!
+//! Built on `shm_open`/`mmap` from `libc` (already here for the FIFO)
+//! rather than the `shared_memory` crate: its `Shmem` isn't `Send`, so it
+//! couldn't live in the async main loop, and the layout above is all a
+//! consumer needs to know, with no crate-specific link files.
+//!
+//! Each run makes a fresh region: a name left behind by a crashed run, or
+//! held by anything else, is unlinked first rather than written into.
+//! Readers still mapping the old region keep it, frozen, until they reopen.
+
+use std::ffi::CString;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::dsp::rms_to_dbfs;
+
+const MAGIC: u32 = 0x4C44_5541;
+const VERSION: u32 = 1;
+/// About 20s of 50ms captures.
+pub const DEFAULT_SLOTS: u32 = 512;
+
+#[repr(C)]
+struct Header {
+    magic: AtomicU32,
+    version: AtomicU32,
+    capacity: AtomicU32,
+    slot_size: AtomicU32,
+    write_index: AtomicU64,
+    _reserved: [u64; 5],
+}
+
+#[repr(C)]
+struct Slot {
+    seq: AtomicU32,
+    zone: AtomicU32,
+    timestamp_us: AtomicU64,
+    rms: AtomicU32,
+    peak: AtomicU32,
+    dbfs: AtomicU32,
+    _reserved: u32,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+const SLOT_SIZE: usize = std::mem::size_of::<Slot>();
+
+/// One capture buffer's levels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Features {
+    pub zone: u32,
+    pub timestamp_us: u64,
+    pub rms: f32,
+    pub peak: f32,
+    pub dbfs: f32,
+}
+
+impl Features {
+    pub fn measure(zone: usize, samples: &[f32]) -> Self {
+        let sum: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
+        let rms = (sum / samples.len().max(1) as f64).sqrt() as f32;
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        Self {
+            zone: zone as u32,
+            timestamp_us,
+            rms,
+            peak: samples.iter().fold(0.0, |p: f32, s| p.max(s.abs())),
+            dbfs: rms_to_dbfs(rms),
+        }
+    }
+}
+
+/// The writer's end. Creating it replaces any region under the name, then
+/// sizes and initializes it; dropping it unmaps and removes the name.
+pub struct ShmRing {
+    name: CString,
+    base: *mut u8,
+    len: usize,
+    capacity: u32,
+    written: u64,
+}
+
+// The mapping is plain shared memory, touched only through atomics.
+unsafe impl Send for ShmRing {}
+
+impl ShmRing {
+    pub fn create(name: &str, capacity: u32) -> Result<Self> {
+        let name = CString::new(shm_name(name))?;
+        let len = HEADER_SIZE + capacity as usize * SLOT_SIZE;
+        // A stale region's size and header can't be trusted: start over.
+        // Fails harmlessly when there is none.
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+        let base = map(&name, len, true)?;
+        let ring = Self {
+            name,
+            base,
+            len,
+            capacity,
+            written: 0,
+        };
+        let header = ring.header();
+        header.capacity.store(capacity, Ordering::Relaxed);
+        header.slot_size.store(SLOT_SIZE as u32, Ordering::Relaxed);
+        header.version.store(VERSION, Ordering::Relaxed);
+        header.write_index.store(0, Ordering::Relaxed);
+        // Last: a reader seeing the magic sees everything above
+        header.magic.store(MAGIC, Ordering::Release);
+        Ok(ring)
+    }
+
+    pub fn write(&mut self, features: &Features) {
+        let slot = self.slot(self.written);
+        // Even at rest: odd says "being written"
+        let seq = slot.seq.load(Ordering::Relaxed);
+        slot.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.zone.store(features.zone, Ordering::Relaxed);
+        slot.timestamp_us
+            .store(features.timestamp_us, Ordering::Relaxed);
+        slot.rms.store(features.rms.to_bits(), Ordering::Relaxed);
+        slot.peak.store(features.peak.to_bits(), Ordering::Relaxed);
+        slot.dbfs.store(features.dbfs.to_bits(), Ordering::Relaxed);
+        slot.seq.store(seq.wrapping_add(2), Ordering::Release);
+
+        self.written += 1;
+        self.header()
+            .write_index
+            .store(self.written, Ordering::Release);
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.base as *const Header) }
+    }
+
+    fn slot(&self, index: u64) -> &Slot {
+        slot_at(self.base, self.capacity, index)
+    }
+}
+
+impl Drop for ShmRing {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base.cast(), self.len);
+            libc::shm_unlink(self.name.as_ptr());
+        }
+    }
+}
+
+/// POSIX names are "/name".
+fn shm_name(name: &str) -> String {
+    if name.starts_with('/') {
+        name.to_string()
+    } else {
+        format!("/{name}")
+    }
+}
+
+fn slot_at<'a>(base: *mut u8, capacity: u32, index: u64) -> &'a Slot {
+    let offset = HEADER_SIZE + (index % capacity as u64) as usize * SLOT_SIZE;
+    unsafe { &*(base.add(offset) as *const Slot) }
+}
+
+/// Map `name`, creating and sizing it first when `create`. Creating fails
+/// if the name exists, so a region is only ever sized by its writer.
+fn map(name: &CString, len: usize, create: bool) -> Result<*mut u8> {
+    let error = |what: &str| {
+        anyhow!(
+            "Cannot {what} shared memory {}: {}",
+            name.to_string_lossy(),
+            std::io::Error::last_os_error()
+        )
+    };
+    let flags = if create {
+        libc::O_CREAT | libc::O_EXCL | libc::O_RDWR
+    } else {
+        libc::O_RDWR
+    };
+    unsafe {
+        let fd = libc::shm_open(name.as_ptr(), flags, 0o644);
+        if fd < 0 {
+            return Err(error("open"));
+        }
+        if create && libc::ftruncate(fd, len as libc::off_t) != 0 {
+            libc::close(fd);
+            return Err(error("size"));
+        }
+        let base = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        );
+        libc::close(fd);
+        if base == libc::MAP_FAILED {
+            return Err(error("map"));
+        }
+        Ok(base.cast())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A consumer following the documented protocol on its own mapping.
+    struct Reader {
+        base: *mut u8,
+        len: usize,
+    }
+
+    impl Reader {
+        fn open(name: &str) -> Self {
+            let name = CString::new(shm_name(name)).unwrap();
+            let header = map(&name, HEADER_SIZE, false).unwrap();
+            let capacity = unsafe { &*(header as *const Header) }
+                .capacity
+                .load(Ordering::Relaxed);
+            unsafe { libc::munmap(header.cast(), HEADER_SIZE) };
+            let len = HEADER_SIZE + capacity as usize * SLOT_SIZE;
+            Self {
+                base: map(&name, len, false).unwrap(),
+                len,
+            }
+        }
+
+        fn header(&self) -> &Header {
+            unsafe { &*(self.base as *const Header) }
+        }
+
+        fn latest(&self) -> Option<Features> {
+            let header = self.header();
+            assert_eq!(header.magic.load(Ordering::Acquire), MAGIC);
+            let index = header.write_index.load(Ordering::Acquire).checked_sub(1)?;
+            let slot = slot_at(self.base, header.capacity.load(Ordering::Relaxed), index);
+            loop {
+                let before = slot.seq.load(Ordering::Acquire);
+                if before % 2 == 1 {
+                    continue;
+                }
+                let features = Features {
+                    zone: slot.zone.load(Ordering::Relaxed),
+                    timestamp_us: slot.timestamp_us.load(Ordering::Relaxed),
+                    rms: f32::from_bits(slot.rms.load(Ordering::Relaxed)),
+                    peak: f32::from_bits(slot.peak.load(Ordering::Relaxed)),
+                    dbfs: f32::from_bits(slot.dbfs.load(Ordering::Relaxed)),
+                };
+                fence(Ordering::Acquire);
+                if slot.seq.load(Ordering::Relaxed) == before {
+                    return Some(features);
+                }
+            }
+        }
+    }
+
+    impl Drop for Reader {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.base.cast(), self.len) };
+        }
+    }
+
+    #[test]
+    fn layout_matches_the_docs() {
+        assert_eq!(HEADER_SIZE, 64);
+        assert_eq!(SLOT_SIZE, 32);
+    }
+
+    #[test]
+    fn reader_sees_what_was_written() {
+        let name = format!("audilator-test-{}", std::process::id());
+        let mut ring = ShmRing::create(&name, 4).unwrap();
+        let reader = Reader::open(&name);
+        assert_eq!(reader.latest(), None);
+
+        let quiet = Features::measure(0, &[0.1; 400]);
+        ring.write(&quiet);
+        assert_eq!(reader.latest(), Some(quiet));
+        assert!((quiet.dbfs + 20.0).abs() < 0.01);
+
+        // Laps the ring: still the newest
+        for zone in 0..6 {
+            ring.write(&Features::measure(zone, &[0.5, -0.8]));
+        }
+        let latest = reader.latest().unwrap();
+        assert_eq!((latest.zone, latest.peak), (5, 0.8));
+        assert_eq!(reader.header().write_index.load(Ordering::Acquire), 7);
+    }
+
+    #[test]
+    fn a_stale_region_is_replaced() {
+        let name = format!("audilator-test-stale-{}", std::process::id());
+        let c_name = CString::new(shm_name(&name)).unwrap();
+        // Left behind too small, with someone else's header
+        let stale = map(&c_name, HEADER_SIZE, true).unwrap();
+        unsafe {
+            (*(stale as *const Header))
+                .magic
+                .store(1, Ordering::Relaxed);
+            libc::munmap(stale.cast(), HEADER_SIZE);
+        }
+        assert!(map(&c_name, HEADER_SIZE, true).is_err());
+
+        let mut ring = ShmRing::create(&name, 8).unwrap();
+        let reader = Reader::open(&name);
+        assert_eq!(reader.header().capacity.load(Ordering::Relaxed), 8);
+        for zone in 0..8 {
+            ring.write(&Features::measure(zone, &[0.5]));
+        }
+        assert_eq!(reader.latest().map(|f| f.zone), Some(7));
+    }
+
+    #[test]
+    fn dropping_the_writer_removes_the_name() {
+        let name = format!("audilator-test-drop-{}", std::process::id());
+        drop(ShmRing::create(&name, 4).unwrap());
+        let c_name = CString::new(shm_name(&name)).unwrap();
+        assert!(map(&c_name, HEADER_SIZE, false).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Where volume decisions go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// The zone's controllers over HTTP
+    Http,
+    /// The zone's controllers over a persistent WebSocket each
+    #[value(name = "ws-client")]
+    WsClient,
+    /// This Mac's default output device (macOS only)
+    #[value(name = "coreaudio")]
+    CoreAudio,
+    /// A control change on a MIDI output, e.g. a hardware mixer's fader
+    Midi,
+}
+
+/// How a zone with several controllers turns their reported volumes into
+/// one starting volume.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReadbackCombine {
+    /// The quietest, so nothing starts louder than it already was
+    Min,
+    Max,
+    Mean,
+    /// The first endpoint, in config order, that reported
+    First,
+}
+
+impl ReadbackCombine {
+    /// `reported` has one entry per endpoint; those that failed to report
+    /// are None and left out. None if no endpoint reported.
+    pub fn combine(self, reported: &[Option<f32>]) -> Option<f32> {
+        let mut values = reported.iter().flatten().copied();
+        match self {
+            Self::Min => values.reduce(f32::min),
+            Self::Max => values.reduce(f32::max),
+            Self::Mean => {
+                let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+                (n > 0).then(|| sum / n as f32)
+            }
+            Self::First => values.next(),
+        }
+    }
+}
+
+/// One send: the volume and any config file `params`, together.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Controls {
+    pub volume: f32,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, f32>,
+    /// The zone's loudness score when this was decided. Only the WebSocket
+    /// message carries it.
+    #[serde(skip)]
+    pub score: Option<u8>,
+}
+
+/// The score rides along for readouts: the same volume and params are the
+/// same send whatever it was.
+impl PartialEq for Controls {
+    fn eq(&self, other: &Self) -> bool {
+        self.volume == other.volume && self.params == other.params
+    }
+}
+
+/// Something that can be told to set a 0.0-1.0 volume.
+pub trait VolumeSink: Send + Sync {
+    /// Human-readable identity for logs and `/status`.
+    fn name(&self) -> &str;
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_>;
+
+    /// Set the volume and parameters at once. Sinks with nowhere to put
+    /// the parameters set just the volume.
+    fn set_controls<'a>(&'a self, controls: &'a Controls) -> SinkFuture<'a> {
+        self.set_volume(controls.volume)
+    }
+}
+
+/// The Windows controller's `POST /volume` endpoint.
+pub struct HttpSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpSink {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Self { client, url }
+    }
+
+    async fn post(&self, controls: &Controls) -> Result<()> {
+        let resp = self.client.post(&self.url).json(controls).send().await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(anyhow!("{}", resp.status()))
+        }
+    }
+}
+
+impl VolumeSink for HttpSink {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+        Box::pin(async move {
+            let controls = Controls {
+                volume,
+                ..Controls::default()
+            };
+            self.post(&controls).await
+        })
+    }
+
+    fn set_controls<'a>(&'a self, controls: &'a Controls) -> SinkFuture<'a> {
+        Box::pin(self.post(controls))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::testutil::MockServer;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Records every volume it's asked to set, or fails while `failing`.
+    #[derive(Clone, Default)]
+    pub(crate) struct RecordingSink {
+        pub sent: Arc<Mutex<Vec<f32>>>,
+        pub failing: Arc<AtomicBool>,
+    }
+
+    impl RecordingSink {
+        pub(crate) fn sent(&self) -> Vec<f32> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    impl VolumeSink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Box::pin(async { Err(anyhow!("unreachable")) });
+            }
+            self.sent.lock().unwrap().push(volume);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[test]
+    fn readback_policies_skip_silent_endpoints() {
+        let reported = [None, Some(0.4), Some(0.8), None, Some(0.3)];
+        assert_eq!(ReadbackCombine::Min.combine(&reported), Some(0.3));
+        assert_eq!(ReadbackCombine::Max.combine(&reported), Some(0.8));
+        assert!((ReadbackCombine::Mean.combine(&reported).unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(ReadbackCombine::First.combine(&reported), Some(0.4));
+
+        for policy in [
+            ReadbackCombine::Min,
+            ReadbackCombine::Max,
+            ReadbackCombine::Mean,
+            ReadbackCombine::First,
+        ] {
+            assert_eq!(policy.combine(&[None, None]), None);
+            assert_eq!(policy.combine(&[Some(0.6)]), Some(0.6));
+        }
+    }
+
+    #[tokio::test]
+    async fn http_sink_posts_volume() {
+        let server = MockServer::start(200).await;
+        let sink = HttpSink::new(reqwest::Client::new(), server.url("/volume"));
+        sink.set_volume(0.25).await.unwrap();
+
+        let reqs = server.requests();
+        assert_eq!(reqs[0].method, "POST");
+        let body: serde_json::Value = serde_json::from_str(&reqs[0].body).unwrap();
+        assert_eq!(body, serde_json::json!({"volume": 0.25}));
+    }
+
+    #[tokio::test]
+    async fn http_sink_posts_params_with_the_volume() {
+        let server = MockServer::start(200).await;
+        let sink = HttpSink::new(reqwest::Client::new(), server.url("/volume"));
+        let controls = Controls {
+            volume: 0.5,
+            params: [("bass".to_string(), 0.25), ("dialogue".to_string(), 0.75)].into(),
+            score: Some(70),
+        };
+        sink.set_controls(&controls).await.unwrap();
+
+        let reqs = server.requests();
+        assert_eq!(reqs.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&reqs[0].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"volume": 0.5, "params": {"bass": 0.25, "dialogue": 0.75}})
+        );
+    }
+
+    #[tokio::test]
+    async fn http_sink_reports_rejection() {
+        let server = MockServer::start(500).await;
+        let sink = HttpSink::new(reqwest::Client::new(), server.url("/volume"));
+        assert!(sink.set_volume(0.25).await.is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Speech detection from two classic time-domain features, no spectrum needed.
+//! Over the last second, split into 20ms frames:
+//!
+//! - low-energy ratio: the share of frames under half the mean energy. The
+//!   gaps between syllables put speech well over music, which rarely dips.
+//! - high zero-crossing ratio: the share of frames crossing zero over 1.5x
+//!   as often as the mean. Fricatives ("s", "f") against voiced sounds make
+//!   speech's crossing rate jump about; a steady instrument's doesn't.
+//!
+//! Speech needs both.
+
+use std::collections::VecDeque;
+
+const FRAME_SEC: f32 = 0.02;
+const HISTORY_SEC: f32 = 1.0;
+const LOW_ENERGY_RATIO: f64 = 0.5;
+const MIN_LOW_ENERGY_SHARE: f32 = 0.15;
+const HIGH_ZCR_RATIO: f32 = 1.5;
+const MIN_HIGH_ZCR_SHARE: f32 = 0.1;
+/// Speech must be gone this long before it counts as over, so pauses
+/// between sentences don't let the volume move.
+const RELEASE_SEC: f32 = 1.0;
+
+/// Per-frame (energy, zero-crossing rate).
+type Frame = (f64, f32);
+
+pub struct SpeechDetector {
+    frame_samples: usize,
+    energy: f64,
+    crossings: usize,
+    count: usize,
+    last: f32,
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    speaking: bool,
+    /// Updates since speech was last seen
+    quiet_updates: usize,
+    release_updates: usize,
+}
+
+impl SpeechDetector {
+    pub fn new(sample_rate: u32, update_rate_hz: f32) -> Self {
+        Self {
+            frame_samples: ((sample_rate as f32 * FRAME_SEC) as usize).max(1),
+            energy: 0.0,
+            crossings: 0,
+            count: 0,
+            last: 0.0,
+            frames: VecDeque::new(),
+            capacity: (HISTORY_SEC / FRAME_SEC) as usize,
+            speaking: false,
+            quiet_updates: 0,
+            release_updates: (RELEASE_SEC * update_rate_hz) as usize,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            self.energy += (s as f64).powi(2);
+            if (s >= 0.0) != (self.last >= 0.0) {
+                self.crossings += 1;
+            }
+            self.last = s;
+            self.count += 1;
+            if self.count >= self.frame_samples {
+                if self.frames.len() >= self.capacity {
+                    self.frames.pop_front();
+                }
+                self.frames.push_back((
+                    self.energy / self.count as f64,
+                    self.crossings as f32 / self.count as f32,
+                ));
+                self.energy = 0.0;
+                self.crossings = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    /// Once per analysis update. True while speech is playing.
+    pub fn update(&mut self) -> bool {
+        if self.speech_like() {
+            self.speaking = true;
+            self.quiet_updates = 0;
+        } else if self.speaking {
+            self.quiet_updates += 1;
+            self.speaking = self.quiet_updates < self.release_updates;
+        }
+        self.speaking
+    }
+
+    fn speech_like(&self) -> bool {
+        if self.frames.len() < self.capacity {
+            return false;
+        }
+        let n = self.frames.len() as f32;
+        let mean_energy = self.frames.iter().map(|f| f.0).sum::<f64>() / n as f64;
+        let mean_zcr = self.frames.iter().map(|f| f.1).sum::<f32>() / n;
+        let share = |pred: &dyn Fn(&Frame) -> bool| {
+            self.frames.iter().filter(|f| pred(f)).count() as f32 / n
+        };
+        let low_energy = share(&|f| f.0 < LOW_ENERGY_RATIO * mean_energy);
+        let high_zcr = share(&|f| f.1 > HIGH_ZCR_RATIO * mean_zcr);
+        low_energy >= MIN_LOW_ENERGY_SHARE && high_zcr >= MIN_HIGH_ZCR_SHARE
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const RATE: u32 = 8000;
+
+    /// Syllables at 4 Hz: 125ms voiced (150 Hz with harmonics), 50ms of
+    /// fricative noise, 75ms pause.
+    pub(crate) fn speech_like(secs: f32, amplitude: f32) -> Vec<f32> {
+        let mut rng = fastrand::Rng::with_seed(7);
+        (0..(secs * RATE as f32) as usize)
+            .map(|n| {
+                let t = n as f32 / RATE as f32;
+                let phase = 2.0 * std::f32::consts::PI * 150.0 * t;
+                match (t % 0.25) / 0.25 {
+                    p if p < 0.5 => amplitude * (phase.sin() + 0.5 * (2.0 * phase).sin()) / 1.5,
+                    p if p < 0.7 => amplitude * 0.5 * (rng.f32() * 2.0 - 1.0),
+                    _ => 0.0,
+                }
+            })
+            .collect()
+    }
+
+    /// A steady two-note chord.
+    pub(crate) fn music_like(secs: f32, amplitude: f32) -> Vec<f32> {
+        (0..(secs * RATE as f32) as usize)
+            .map(|n| {
+                let t = n as f32 / RATE as f32;
+                let tau = 2.0 * std::f32::consts::PI;
+                amplitude * ((tau * 440.0 * t).sin() + (tau * 660.0 * t).sin()) / 2.0
+            })
+            .collect()
+    }
+
+    /// Feed in 50ms updates; the state after each.
+    fn run(detector: &mut SpeechDetector, samples: &[f32]) -> Vec<bool> {
+        samples
+            .chunks(RATE as usize / 20)
+            .map(|chunk| {
+                detector.push(chunk);
+                detector.update()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speech_is_detected_and_music_is_not() {
+        let mut d = SpeechDetector::new(RATE, 20.0);
+        assert!(*run(&mut d, &speech_like(3.0, 0.3)).last().unwrap());
+        let mut d = SpeechDetector::new(RATE, 20.0);
+        assert!(!run(&mut d, &music_like(3.0, 0.3)).into_iter().any(|s| s));
+    }
+
+    #[test]
+    fn steady_noise_is_not_speech() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let noise: Vec<f32> = (0..3 * RATE)
+            .map(|_| 0.2 * (rng.f32() * 2.0 - 1.0))
+            .collect();
+        let mut d = SpeechDetector::new(RATE, 20.0);
+        assert!(!run(&mut d, &noise).into_iter().any(|s| s));
+    }
+
+    #[test]
+    fn speech_ends_after_the_release() {
+        let mut d = SpeechDetector::new(RATE, 20.0);
+        run(&mut d, &speech_like(3.0, 0.3));
+        let after = run(&mut d, &music_like(3.0, 0.3));
+        // Still held through a pause, then released
+        assert!(after[..20].iter().all(|&s| s));
+        assert!(!after.last().unwrap());
+    }
+}
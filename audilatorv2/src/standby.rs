@@ -0,0 +1,195 @@
+use std::time::{Duration, Instant};
+
+use crate::dsp::rms_to_dbfs;
+use crate::transition::Transition;
+
+/// In standby, how often a buffer is checked for returning audio.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+/// Time constant of the level envelope, in audio time: a click doesn't
+/// count as audio, and a quiet buffer in a programme isn't silence.
+const ENVELOPE_SEC: f32 = 0.1;
+/// How long the envelope has to stay over the threshold to wake.
+const WAKE_HOLD_SEC: f32 = 0.5;
+
+/// Idles a zone after hours of silence: a TV in standby overnight needs no
+/// analysis and no sends. Unlike --silence-hold, which freezes the volume
+/// through a quiet scene, standby stops the compressor altogether.
+pub struct Standby {
+    after: Duration,
+    threshold_dbfs: f32,
+    sample_rate: f32,
+    envelope_dbfs: f32,
+    /// Seconds of audio the envelope has been over the threshold
+    loud_for: f32,
+    quiet_since: Option<Instant>,
+    last_probe: Option<Instant>,
+    /// A probe heard something: every buffer counts until the envelope
+    /// either wakes or drops back
+    listening: bool,
+    asleep: bool,
+}
+
+impl Standby {
+    pub fn new(after: Duration, threshold_dbfs: f32, sample_rate: u32) -> Self {
+        Self {
+            after,
+            threshold_dbfs,
+            sample_rate: sample_rate as f32,
+            envelope_dbfs: rms_to_dbfs(0.0),
+            loud_for: 0.0,
+            quiet_since: None,
+            last_probe: None,
+            listening: false,
+            asleep: false,
+        }
+    }
+
+    /// Look at captured samples. Returns `Engaged` on going into standby and
+    /// `Released` when audio comes back.
+    pub fn observe(&mut self, samples: &[f32], now: Instant) -> Option<Transition> {
+        if self.asleep && !self.listening {
+            if self
+                .last_probe
+                .is_some_and(|last| now.duration_since(last) < PROBE_INTERVAL)
+            {
+                return None;
+            }
+            self.last_probe = Some(now);
+        }
+
+        let level = level_dbfs(samples);
+        let secs = samples.len() as f32 / self.sample_rate;
+        self.envelope_dbfs += (level - self.envelope_dbfs) * (1.0 - (-secs / ENVELOPE_SEC).exp());
+        let quiet = self.envelope_dbfs < self.threshold_dbfs;
+        self.loud_for = if quiet { 0.0 } else { self.loud_for + secs };
+
+        if !self.asleep {
+            if !quiet {
+                self.quiet_since = None;
+                return None;
+            }
+            let since = *self.quiet_since.get_or_insert(now);
+            if now.duration_since(since) < self.after {
+                return None;
+            }
+            self.asleep = true;
+            self.last_probe = Some(now);
+            return Some(Transition::Engaged);
+        }
+
+        self.listening = !quiet || level >= self.threshold_dbfs;
+        if self.loud_for < WAKE_HOLD_SEC {
+            return None;
+        }
+        self.asleep = false;
+        self.listening = false;
+        self.quiet_since = None;
+        Some(Transition::Released)
+    }
+
+    pub fn asleep(&self) -> bool {
+        self.asleep
+    }
+}
+
+fn level_dbfs(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return -80.0;
+    }
+    let sum: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    rms_to_dbfs((sum / samples.len() as f64).sqrt() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 400 samples are 50 ms
+    const RATE: u32 = 8000;
+    const SILENCE: [f32; 400] = [0.0; 400];
+    /// About -20 dBFS
+    const AUDIO: [f32; 400] = [0.1; 400];
+
+    /// Feed `buffers` of `samples` 50 ms apart from `from`, returning the
+    /// first change.
+    fn feed(
+        standby: &mut Standby,
+        samples: &[f32],
+        buffers: u32,
+        from: Instant,
+    ) -> Option<Transition> {
+        (0..buffers).find_map(|n| standby.observe(samples, from + Duration::from_millis(50) * n))
+    }
+
+    #[test]
+    fn long_silence_stands_by_and_audio_wakes() {
+        let start = Instant::now();
+        let mut standby = Standby::new(Duration::from_secs(3600), -60.0, RATE);
+        let at = |sec: u64| start + Duration::from_secs(sec);
+
+        // A quiet scene is not standby
+        assert_eq!(standby.observe(&SILENCE, at(0)), None);
+        assert_eq!(standby.observe(&SILENCE, at(600)), None);
+        assert_eq!(feed(&mut standby, &AUDIO, 4, at(601)), None);
+
+        // An hour of unbroken silence is, counted from where the envelope
+        // falls below the threshold
+        assert_eq!(feed(&mut standby, &SILENCE, 20, at(602)), None);
+        for sec in (603..602 + 3600).step_by(60) {
+            assert_eq!(standby.observe(&SILENCE, at(sec)), None);
+        }
+        assert_eq!(standby.observe(&SILENCE, at(4202)), None);
+        assert_eq!(
+            standby.observe(&SILENCE, at(4203)),
+            Some(Transition::Engaged)
+        );
+        assert!(standby.asleep());
+        assert_eq!(standby.observe(&SILENCE, at(7200)), None);
+
+        assert_eq!(
+            feed(&mut standby, &AUDIO, 20, at(7201)),
+            Some(Transition::Released)
+        );
+        assert!(!standby.asleep());
+    }
+
+    #[test]
+    fn clicks_neither_wake_nor_keep_awake() {
+        let start = Instant::now();
+        let mut standby = Standby::new(Duration::from_secs(60), -60.0, RATE);
+        let at = |sec: u64| start + Duration::from_secs(sec);
+        let click = [0.5; 40];
+
+        // A click now and then through the silence: still goes to sleep
+        for sec in 0..60 {
+            standby.observe(&SILENCE, at(sec));
+            assert_eq!(standby.observe(&click, at(sec)), None);
+        }
+        assert_eq!(standby.observe(&SILENCE, at(60)), Some(Transition::Engaged));
+
+        // A probe landing on a click, then silence: stays asleep
+        assert_eq!(standby.observe(&click, at(61)), None);
+        assert_eq!(feed(&mut standby, &SILENCE, 40, at(61)), None);
+        assert!(standby.asleep());
+    }
+
+    #[test]
+    fn standby_only_probes_occasionally() {
+        let start = Instant::now();
+        let mut standby = Standby::new(Duration::from_secs(60), -60.0, RATE);
+        standby.observe(&SILENCE, start);
+        let asleep = start + Duration::from_secs(60);
+        assert_eq!(standby.observe(&SILENCE, asleep), Some(Transition::Engaged));
+
+        // Audio between probes goes unseen until the next one, which
+        // then listens on until the audio has held long enough
+        let soon = asleep + Duration::from_millis(100);
+        assert_eq!(standby.observe(&AUDIO, soon), None);
+        assert!(feed(&mut standby, &AUDIO, 5, soon).is_none());
+        assert!(!standby.listening);
+        assert_eq!(
+            feed(&mut standby, &AUDIO, 20, asleep + PROBE_INTERVAL),
+            Some(Transition::Released)
+        );
+    }
+}
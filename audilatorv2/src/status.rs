@@ -0,0 +1,275 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use crate::adaptive::Thresholds;
+use crate::dsp::{Recalibration, Saturation};
+use crate::learn::ContentType;
+use crate::loudness::Loudness;
+use crate::params::ParamStatus;
+use crate::units::LoudnessUnit;
+
+/// Snapshot served at `GET /status`.
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct Status {
+    pub zones: Vec<ZoneStatus>,
+    /// Sends left under --max-sends-per-session
+    pub sends_remaining: Option<u64>,
+    /// What each zone's `levels` are in (--units)
+    pub units: LoudnessUnit,
+}
+
+/// A zone's levels in the status `units`, next to the dBFS fields.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct UnitLevels {
+    pub envelope: Option<f32>,
+    pub quiet: Option<f32>,
+    pub loud: Option<f32>,
+    /// Target after the last scheduled recalibration
+    pub recalibrated_target: Option<f32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ZoneStatus {
+    pub name: String,
+    pub envelope_dbfs: Option<f32>,
+    pub loudness: Option<Loudness>,
+    /// Current quiet/loud boundaries (adaptive with --adaptive-window)
+    pub thresholds: Option<Thresholds>,
+    pub volume: f32,
+    pub last_sent: Option<f32>,
+    pub endpoints: Vec<String>,
+    /// Whether the last send to every endpoint succeeded (None before the first)
+    pub healthy: Option<bool>,
+    /// No send for --settle-time: leveling has converged on this content
+    pub settled: bool,
+    /// Smoothed round-trip of recent sends
+    pub latency_ms: Option<f32>,
+    /// Current minimum time between sends (stretched by --max-interval throttling)
+    pub send_interval_ms: f32,
+    /// "min" or "max" while pinned at --vol-min/--vol-max
+    pub saturated: Option<Saturation>,
+    /// Config scene the content matches, if scenes are configured
+    pub scene: Option<String>,
+    /// Control envelope on the 0-100 score scale
+    pub score: Option<u8>,
+    /// Speech, music or mixed, with --learn-file
+    pub content: Option<ContentType>,
+    /// Config file `params`: each one's value and the feature driving it
+    pub params: Vec<ParamStatus>,
+    /// The last scheduled recalibration, in dBFS
+    pub recalibration: Option<Recalibration>,
+    pub levels: UnitLevels,
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+/// `POST /nudge?db=N[&zone=NAME]`: the listener wants N dB more (or less)
+/// of what's playing, in one zone or all of them.
+#[derive(Debug, PartialEq)]
+pub struct Nudge {
+    pub zone: Option<String>,
+    pub db: f32,
+}
+
+impl Nudge {
+    fn parse(query: &str) -> Option<Self> {
+        let mut nudge = Nudge {
+            zone: None,
+            db: f32::NAN,
+        };
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "db" => nudge.db = value.parse().ok()?,
+                "zone" => nudge.zone = Some(percent_decode(value)?),
+                _ => {}
+            }
+        }
+        nudge.db.is_finite().then_some(nudge)
+    }
+}
+
+/// A query string value with `+` and `%XX` escapes undone, e.g. a zone
+/// named "living room" arriving as `living%20room`. None if malformed.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.bytes();
+    while let Some(b) = rest.next() {
+        bytes.push(match b {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [rest.next()?, rest.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            b => b,
+        });
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Serve `GET /status` as JSON on `addr`, and `POST /nudge` into `nudges`
+/// when given. Returns the bound address once listening.
+pub async fn serve(
+    addr: SocketAddr,
+    status: SharedStatus,
+    nudges: Option<mpsc::UnboundedSender<Nudge>>,
+) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let bound = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(handle(socket, status.clone(), nudges.clone()));
+        }
+    });
+    Ok(bound)
+}
+
+async fn handle(
+    mut socket: TcpStream,
+    status: SharedStatus,
+    nudges: Option<mpsc::UnboundedSender<Nudge>>,
+) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        match socket.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+        if buf.len() > 16 * 1024 {
+            return;
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let (method, path) = (request_line.next(), request_line.next());
+
+    let (code, body) = match (method, path) {
+        (Some("GET"), Some("/status")) => {
+            let snapshot = status.lock().unwrap().clone();
+            (
+                "200 OK",
+                serde_json::to_string(&snapshot).unwrap_or_default(),
+            )
+        }
+        (Some("POST"), Some(path)) if path.starts_with("/nudge") && nudges.is_some() => {
+            let query = path.split_once('?').map_or("", |(_, q)| q);
+            match (Nudge::parse(query), &nudges) {
+                (Some(nudge), Some(tx)) => {
+                    let _ = tx.send(nudge);
+                    ("202 Accepted", "{}".to_string())
+                }
+                _ => (
+                    "400 Bad Request",
+                    r#"{"error":"Expected /nudge?db=N"}"#.to_string(),
+                ),
+            }
+        }
+        _ => ("404 Not Found", r#"{"error":"Not found"}"#.to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = socket.write_all(response.as_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_status_json() {
+        let status = SharedStatus::default();
+        status.lock().unwrap().zones.push(ZoneStatus {
+            name: "living".into(),
+            envelope_dbfs: Some(-24.0),
+            loudness: None,
+            thresholds: None,
+            volume: 0.5,
+            last_sent: None,
+            endpoints: vec!["http://x/volume".into()],
+            healthy: None,
+            settled: false,
+            latency_ms: Some(120.0),
+            send_interval_ms: 500.0,
+            saturated: Some(Saturation::Max),
+            scene: Some("dialogue".into()),
+            score: Some(70),
+            content: Some(ContentType::Speech),
+            params: Vec::new(),
+            recalibration: None,
+            levels: UnitLevels {
+                envelope: Some(76.0),
+                ..UnitLevels::default()
+            },
+        });
+        status.lock().unwrap().sends_remaining = Some(42);
+        status.lock().unwrap().units = LoudnessUnit::Phon;
+        let addr = serve("127.0.0.1:0".parse().unwrap(), status, None)
+            .await
+            .unwrap();
+
+        let body: serde_json::Value = reqwest::get(format!("http://{addr}/status"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["zones"][0]["name"], "living");
+        assert_eq!(body["zones"][0]["volume"], 0.5);
+        assert_eq!(body["zones"][0]["latency_ms"], 120.0);
+        assert_eq!(body["zones"][0]["saturated"], "max");
+        assert_eq!(body["zones"][0]["scene"], "dialogue");
+        assert_eq!(body["zones"][0]["score"], 70);
+        assert_eq!(body["zones"][0]["content"], "speech");
+        assert_eq!(body["sends_remaining"], 42);
+        assert_eq!(body["units"], "phon");
+        assert_eq!(body["zones"][0]["levels"]["envelope"], 76.0);
+
+        let resp = reqwest::get(format!("http://{addr}/nope")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn forwards_nudges() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let addr = serve(
+            "127.0.0.1:0".parse().unwrap(),
+            SharedStatus::default(),
+            Some(tx),
+        )
+        .await
+        .unwrap();
+        let client = reqwest::Client::new();
+        let post = |query: &str| client.post(format!("http://{addr}/nudge{query}")).send();
+
+        assert_eq!(post("?db=2.5").await.unwrap().status(), 202);
+        assert_eq!(
+            rx.recv().await,
+            Some(Nudge {
+                zone: None,
+                db: 2.5
+            })
+        );
+        assert_eq!(post("?zone=living&db=-1").await.unwrap().status(), 202);
+        assert_eq!(rx.recv().await.unwrap().zone.as_deref(), Some("living"));
+        assert_eq!(
+            post("?zone=living%20room&db=1").await.unwrap().status(),
+            202
+        );
+        assert_eq!(
+            rx.recv().await.unwrap().zone.as_deref(),
+            Some("living room")
+        );
+        assert_eq!(post("?zone=%2&db=1").await.unwrap().status(), 400);
+        assert_eq!(post("?db=loud").await.unwrap().status(), 400);
+        assert_eq!(post("").await.unwrap().status(), 400);
+    }
+}
@@ -0,0 +1,64 @@
+/// Source of the loudness target the compressor steers toward.
+/// Consulted on every analysis update, so implementations must be cheap.
+pub trait TargetProvider: Send {
+    fn current_target(&self) -> f32;
+}
+
+/// A target that never changes (the `--target` flag).
+pub struct FixedTarget(pub f32);
+
+impl TargetProvider for FixedTarget {
+    fn current_target(&self) -> f32 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::dsp::tests::{feed_level, test_config};
+    use crate::dsp::Compressor;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Target pushed from outside, e.g. by an endpoint or another task.
+    #[derive(Clone)]
+    pub(crate) struct PushedTarget(Arc<AtomicU32>);
+
+    impl PushedTarget {
+        pub(crate) fn new(dbfs: f32) -> Self {
+            Self(Arc::new(AtomicU32::new(dbfs.to_bits())))
+        }
+
+        pub(crate) fn set(&self, dbfs: f32) {
+            self.0.store(dbfs.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    impl TargetProvider for PushedTarget {
+        fn current_target(&self) -> f32 {
+            f32::from_bits(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn fixed_target_holds_volume_at_target() {
+        let mut comp = Compressor::with_target(test_config(), 0.5, Box::new(FixedTarget(-20.0)));
+        feed_level(&mut comp, -20.0, 2.0); // let the envelope settle
+        let results = feed_level(&mut comp, -20.0, 5.0);
+        assert!(results.iter().all(|r| r.delta_db == 0.0));
+    }
+
+    #[test]
+    fn pushed_target_takes_effect_on_next_update() {
+        let target = PushedTarget::new(-20.0);
+        let mut comp = Compressor::with_target(test_config(), 0.5, Box::new(target.clone()));
+        feed_level(&mut comp, -20.0, 5.0);
+
+        // Same content, but the goal is now 10 dB quieter: volume must come down
+        target.set(-30.0);
+        let results = feed_level(&mut comp, -20.0, 1.0);
+        assert!(results.iter().any(|r| r.delta_db < 0.0));
+        assert!(results.iter().all(|r| r.delta_db <= 0.0));
+    }
+}
@@ -0,0 +1,8 @@
+/// A held or flagged state turning on or off, reported by the detectors that
+/// gate leveling (kill switch, pause, standby, notch) or raise warnings
+/// (imbalance, inactivity).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Transition {
+    Engaged,
+    Released,
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, List, Paragraph, Sparkline};
+use ratatui::Frame;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use crate::events::Event;
+use crate::loudness::Loudness;
+use crate::units::Units;
+
+/// Readings kept for the history graph (~10s at the default 50ms window).
+const HISTORY_LEN: usize = 200;
+/// Entries kept in the recent-events list.
+const EVENTS_LEN: usize = 50;
+/// Floor of the level meter and graph.
+const FLOOR_DBFS: f32 = -80.0;
+
+/// Everything the dashboard shows, folded from the event stream.
+struct Dashboard {
+    history: VecDeque<f32>,
+    level_dbfs: f32,
+    score: u8,
+    envelope_dbfs: f32,
+    loudness: Option<Loudness>,
+    target_dbfs: f32,
+    volume: f32,
+    last_sent: Option<f32>,
+    state: &'static str,
+    healthy: Option<bool>,
+    events: VecDeque<String>,
+    units: Units,
+}
+
+impl Dashboard {
+    fn new(units: Units) -> Self {
+        Self {
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            level_dbfs: FLOOR_DBFS,
+            score: 0,
+            envelope_dbfs: FLOOR_DBFS,
+            loudness: None,
+            target_dbfs: 0.0,
+            volume: 0.0,
+            last_sent: None,
+            state: "-",
+            healthy: None,
+            events: VecDeque::with_capacity(EVENTS_LEN),
+            units,
+        }
+    }
+
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Reading {
+                envelope_dbfs,
+                display_dbfs,
+                target_dbfs,
+                delta_db,
+                volume,
+                silent,
+                loudness,
+                score,
+            } => {
+                if self.history.len() >= HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.history.push_back(display_dbfs);
+                self.level_dbfs = display_dbfs;
+                self.score = score;
+                self.envelope_dbfs = envelope_dbfs;
+                self.loudness = Some(loudness);
+                self.target_dbfs = target_dbfs;
+                self.volume = volume;
+                self.state = if silent {
+                    "silent"
+                } else if delta_db > 0.01 {
+                    "quiet"
+                } else if delta_db < -0.01 {
+                    "loud"
+                } else {
+                    "normal"
+                };
+            }
+            Event::Sent { volume } => {
+                self.last_sent = Some(volume);
+                if self.healthy != Some(true) {
+                    self.push_event("controller reachable".to_string());
+                }
+                self.healthy = Some(true);
+                self.push_event(format!("sent volume {volume:.3}"));
+            }
+            Event::SendFailed { error } => {
+                self.healthy = Some(false);
+                self.push_event(format!("send failed: {error}"));
+            }
+            Event::Settled => self.push_event("volume settled".to_string()),
+        }
+    }
+
+    fn push_event(&mut self, line: String) {
+        if self.events.len() >= EVENTS_LEN {
+            self.events.pop_back();
+        }
+        self.events.push_front(line);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [meter, graph, info, events] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Min(3),
+        ])
+        .areas(frame.area());
+
+        let ratio = ((self.level_dbfs - FLOOR_DBFS) / -FLOOR_DBFS).clamp(0.0, 1.0);
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(" Level "))
+                .gauge_style(Style::default().fg(Color::Green))
+                .ratio(ratio as f64)
+                .label(format!(
+                    "{}  (score {})",
+                    self.units.show(self.level_dbfs),
+                    self.score
+                )),
+            meter,
+        );
+
+        let bars: Vec<u64> = self
+            .history
+            .iter()
+            .map(|&db| (db - FLOOR_DBFS).max(0.0) as u64)
+            .collect();
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(" History "))
+                .max((-FLOOR_DBFS) as u64)
+                .data(&bars),
+            graph,
+        );
+
+        let health = match self.healthy {
+            None => "waiting",
+            Some(true) => "ok",
+            Some(false) => "failing",
+        };
+        let sent = self
+            .last_sent
+            .map(|v| format!("{v:.3}"))
+            .unwrap_or_else(|| "-".to_string());
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(format!(
+                    "Volume:     {:.3}  (last sent {sent})",
+                    self.volume
+                )),
+                Line::from(format!("Target:     {}", self.units.show(self.target_dbfs))),
+                Line::from(format!(
+                    "Envelope:   {}  (unsmoothed)",
+                    self.units.show(self.envelope_dbfs)
+                )),
+                Line::from(match self.loudness {
+                    Some(l) => format!(
+                        "Loudness:   M {:+.1}  S {:+.1}  I {} LUFS",
+                        l.momentary,
+                        l.short_term,
+                        l.integrated
+                            .map(|i| format!("{i:+.1}"))
+                            .unwrap_or_else(|| "-".to_string())
+                    ),
+                    None => "Loudness:   -".to_string(),
+                }),
+                Line::from(format!("State:      {}", self.state)),
+                Line::from(format!("Controller: {health}")),
+            ])
+            .block(Block::bordered().title(" Status  (q to quit) ")),
+            info,
+        );
+
+        frame.render_widget(
+            List::new(self.events.iter().map(String::as_str))
+                .block(Block::bordered().title(" Events ")),
+            events,
+        );
+    }
+}
+
+/// Run the dashboard until `q`/Esc/Ctrl+C or `running` is cleared.
+/// Blocking; call from its own thread. Clears `running` on exit.
+pub fn run(
+    mut rx: broadcast::Receiver<Event>,
+    running: Arc<AtomicBool>,
+    units: Units,
+) -> Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let mut dash = Dashboard::new(units);
+
+    let result = (|| -> Result<()> {
+        while running.load(Ordering::Relaxed) {
+            loop {
+                match rx.try_recv() {
+                    Ok(event) => dash.apply(event),
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+            terminal.draw(|frame| dash.draw(frame))?;
+
+            if event::poll(Duration::from_millis(100))? {
+                if let TermEvent::Key(key) = event::read()? {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc);
+                    if key.kind == KeyEventKind::Press && (quit || ctrl_c) {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    ratatui::restore();
+    running.store(false, Ordering::Relaxed);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{LoudnessUnit, DEFAULT_FULL_SCALE_SPL};
+
+    fn dashboard() -> Dashboard {
+        Dashboard::new(Units::new(LoudnessUnit::Dbfs, DEFAULT_FULL_SCALE_SPL))
+    }
+
+    fn reading(envelope_dbfs: f32, delta_db: f32) -> Event {
+        Event::Reading {
+            envelope_dbfs,
+            display_dbfs: envelope_dbfs,
+            target_dbfs: -25.0,
+            delta_db,
+            volume: 0.5,
+            silent: false,
+            loudness: Loudness {
+                momentary: envelope_dbfs,
+                short_term: envelope_dbfs,
+                integrated: None,
+            },
+            score: 50,
+        }
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut dash = dashboard();
+        for i in 0..HISTORY_LEN + 10 {
+            dash.apply(reading(-(i as f32) / 10.0, 0.0));
+        }
+        assert_eq!(dash.history.len(), HISTORY_LEN);
+        assert_eq!(dash.level_dbfs, -((HISTORY_LEN + 9) as f32) / 10.0);
+    }
+
+    #[test]
+    fn state_follows_correction_direction() {
+        let mut dash = dashboard();
+        dash.apply(reading(-40.0, 1.0));
+        assert_eq!(dash.state, "quiet");
+        dash.apply(reading(-10.0, -1.0));
+        assert_eq!(dash.state, "loud");
+        dash.apply(reading(-25.0, 0.0));
+        assert_eq!(dash.state, "normal");
+    }
+
+    #[test]
+    fn health_tracks_sends() {
+        let mut dash = dashboard();
+        assert_eq!(dash.healthy, None);
+        dash.apply(Event::SendFailed {
+            error: "timeout".into(),
+        });
+        assert_eq!(dash.healthy, Some(false));
+        dash.apply(Event::Sent { volume: 0.4 });
+        assert_eq!(dash.healthy, Some(true));
+        assert_eq!(dash.last_sent, Some(0.4));
+        assert!(dash.events[0].contains("0.400"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Perceptual loudness units for those who'd rather think in phons or sones.
+//!
+//! Approximations, not a loudness model. A phon is the dB SPL of an equally
+//! loud 1 kHz tone; the measured level stands in for that tone, with digital
+//! full scale assumed to play at `--full-scale-spl` where the mic is:
+//!
+//! ```text
+//! phon = dBFS + full_scale_spl
+//! sone = 2^((phon - 40) / 10)      phon >= 40
+//! sone = (phon / 40)^2.642         below 40 (low-level approximation)
+//! ```
+//!
+//! Levels below 0 phon count as 0 sone. Differences stay in dB either way,
+//! so dead zone and hysteresis are unaffected.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// Default SPL of a full-scale signal at the listening position.
+pub const DEFAULT_FULL_SCALE_SPL: f32 = 100.0;
+/// Below 40 phon, the exponent of the low-level sone approximation.
+const LOW_LEVEL_EXPONENT: f32 = 2.642;
+
+/// How levels are entered and shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LoudnessUnit {
+    /// Decibels relative to digital full scale
+    #[default]
+    Dbfs,
+    /// Approximate phons
+    Phon,
+    /// Approximate sones
+    Sone,
+}
+
+/// Converts between dBFS and the chosen unit.
+#[derive(Clone, Copy, Debug)]
+pub struct Units {
+    unit: LoudnessUnit,
+    full_scale_spl: f32,
+}
+
+impl Units {
+    pub fn new(unit: LoudnessUnit, full_scale_spl: f32) -> Self {
+        Self {
+            unit,
+            full_scale_spl,
+        }
+    }
+
+    /// A dBFS level in this unit.
+    pub fn value(&self, dbfs: f32) -> f32 {
+        let phon = dbfs + self.full_scale_spl;
+        match self.unit {
+            LoudnessUnit::Dbfs => dbfs,
+            LoudnessUnit::Phon => phon,
+            LoudnessUnit::Sone if phon >= 40.0 => 2.0_f32.powf((phon - 40.0) / 10.0),
+            LoudnessUnit::Sone => (phon.max(0.0) / 40.0).powf(LOW_LEVEL_EXPONENT),
+        }
+    }
+
+    /// A level in this unit back to dBFS.
+    pub fn dbfs(&self, value: f32) -> f32 {
+        let phon = match self.unit {
+            LoudnessUnit::Dbfs => return value,
+            LoudnessUnit::Phon => value,
+            LoudnessUnit::Sone if value >= 1.0 => 40.0 + 10.0 * value.log2(),
+            LoudnessUnit::Sone => 40.0 * value.max(0.0).powf(1.0 / LOW_LEVEL_EXPONENT),
+        };
+        phon - self.full_scale_spl
+    }
+
+    /// The bare number, at the precision this unit reads well at.
+    pub fn number(&self, dbfs: f32) -> String {
+        let v = self.value(dbfs);
+        match self.unit {
+            LoudnessUnit::Dbfs => format!("{v:+.1}"),
+            LoudnessUnit::Phon => format!("{v:.1}"),
+            LoudnessUnit::Sone => format!("{v:.2}"),
+        }
+    }
+
+    /// Number and unit, e.g. "-25.0 dBFS" or "75.0 phon".
+    pub fn show(&self, dbfs: f32) -> String {
+        let suffix = match self.unit {
+            LoudnessUnit::Dbfs => "dBFS",
+            LoudnessUnit::Phon => "phon",
+            LoudnessUnit::Sone => "sone",
+        };
+        format!("{} {suffix}", self.number(dbfs))
+    }
+}
+
+/// Maps levels onto a 0-100 loudness score for simple readouts ("loudness
+/// is at 70"): 0 at the floor, 100 at the ceiling, linear in dB between.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreScale {
+    floor_dbfs: f32,
+    ceiling_dbfs: f32,
+}
+
+impl ScoreScale {
+    pub fn new(floor_dbfs: f32, ceiling_dbfs: f32) -> Result<Self> {
+        if ceiling_dbfs <= floor_dbfs {
+            bail!("--score-ceiling must be louder than --score-floor");
+        }
+        Ok(Self {
+            floor_dbfs,
+            ceiling_dbfs,
+        })
+    }
+
+    pub fn score(&self, dbfs: f32) -> u8 {
+        let share = (dbfs - self.floor_dbfs) / (self.ceiling_dbfs - self.floor_dbfs);
+        (share.clamp(0.0, 1.0) * 100.0).round() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn units(unit: LoudnessUnit) -> Units {
+        Units::new(unit, DEFAULT_FULL_SCALE_SPL)
+    }
+
+    #[test]
+    fn score_spans_floor_to_ceiling() {
+        let scale = ScoreScale::new(-60.0, 0.0).unwrap();
+        assert_eq!(scale.score(-60.0), 0);
+        assert_eq!(scale.score(0.0), 100);
+        assert_eq!(scale.score(-18.0), 70);
+        // Clamped outside the range
+        assert_eq!(scale.score(-90.0), 0);
+        assert_eq!(scale.score(6.0), 100);
+        assert!(ScoreScale::new(-20.0, -20.0).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn score_never_falls_as_level_rises(a in -100.0f32..10.0, b in -100.0f32..10.0) {
+            let scale = ScoreScale::new(-60.0, -10.0).unwrap();
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            prop_assert!(scale.score(low) <= scale.score(high));
+        }
+    }
+
+    #[test]
+    fn reference_points() {
+        let phon = units(LoudnessUnit::Phon);
+        assert_eq!(phon.value(-25.0), 75.0);
+        assert_eq!(phon.dbfs(75.0), -25.0);
+
+        let sone = units(LoudnessUnit::Sone);
+        // 40 phon is 1 sone; every 10 phon doubles it
+        assert!((sone.value(-60.0) - 1.0).abs() < 1e-6);
+        assert!((sone.value(-50.0) - 2.0).abs() < 1e-5);
+        assert!((sone.value(-25.0) - 2.0_f32.powf(3.5)).abs() < 1e-4);
+        // The halves meet at 40 phon
+        assert!((sone.value(-60.001) - 1.0).abs() < 1e-3);
+        assert_eq!(sone.value(-120.0), 0.0);
+    }
+
+    #[test]
+    fn dbfs_passes_through() {
+        let dbfs = units(LoudnessUnit::Dbfs);
+        assert_eq!(dbfs.value(-25.0), -25.0);
+        assert_eq!(dbfs.dbfs(-25.0), -25.0);
+        assert_eq!(dbfs.show(-25.0), "-25.0 dBFS");
+    }
+
+    #[test]
+    fn full_scale_spl_shifts_phons() {
+        let phon = Units::new(LoudnessUnit::Phon, 90.0);
+        assert_eq!(phon.show(-25.0), "65.0 phon");
+    }
+
+    proptest! {
+        #[test]
+        fn conversions_invert(dbfs in -100.0f32..0.0) {
+            for unit in [LoudnessUnit::Dbfs, LoudnessUnit::Phon, LoudnessUnit::Sone] {
+                let u = units(unit);
+                let back = u.dbfs(u.value(dbfs));
+                prop_assert!((back - dbfs).abs() < 1e-3, "{:?}: {} -> {}", unit, dbfs, back);
+            }
+        }
+
+        #[test]
+        fn sones_rise_with_level(a in -100.0f32..0.0, b in -100.0f32..0.0) {
+            let sone = units(LoudnessUnit::Sone);
+            if a < b {
+                prop_assert!(sone.value(a) <= sone.value(b));
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Volumes over one long-lived WebSocket per endpoint instead of a POST per
+//! change. Each `{"id": n, "volume": v}` text message is answered by the
+//! server with `{"id": n, "volume": current}` once applied, or
+//! `{"id": n, "error": "..."}`. A message without an `id` is the server
+//! pushing its volume after a change at the device; it goes back to the
+//! zone as a readback. With config file `params` the message carries them
+//! too, as over HTTP.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::sink::{Controls, SinkFuture, VolumeSink};
+
+/// Same budget as an HTTP send, for the whole send including a reconnect.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    #[serde(flatten)]
+    controls: &'a Controls,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<u8>,
+}
+
+/// Anything the server sends: an answer carries the `id` it answers.
+#[derive(Deserialize)]
+struct Frame {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    volume: Option<f32>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// An open connection: its sending half, and the answers its reader task
+/// picked out of everything the server sends.
+struct Connection {
+    writer: SplitSink<Socket, Message>,
+    answers: mpsc::UnboundedReceiver<Frame>,
+}
+
+/// Where pushed volumes go: the zone's index and the main loop's channel.
+type Pushes = (usize, mpsc::UnboundedSender<(usize, f32)>);
+
+/// The WebSocket flavour of an HTTP endpoint URL.
+pub fn ws_url(http_url: &str) -> String {
+    match http_url.strip_prefix("http://") {
+        Some(rest) => format!("ws://{rest}"),
+        None => http_url.to_string(),
+    }
+}
+
+/// Connects on first use and again after the connection drops.
+pub struct WsSink {
+    url: String,
+    pushes: Option<Pushes>,
+    next_id: AtomicU64,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl WsSink {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            pushes: None,
+            next_id: AtomicU64::new(1),
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Report volumes the server pushes as `(zone, volume)` on `tx`.
+    pub fn with_pushes(mut self, zone: usize, tx: mpsc::UnboundedSender<(usize, f32)>) -> Self {
+        self.pushes = Some((zone, tx));
+        self
+    }
+
+    async fn send(&self, controls: &Controls) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        let mut connection = tokio::time::timeout_at(deadline, self.connection.lock())
+            .await
+            .map_err(|_| anyhow!("busy for {TIMEOUT:?}"))?;
+        let attempt = async {
+            // A connection that died since the last send gets one fresh
+            // attempt now, rather than failing this send and waiting out a
+            // retry
+            if let Some(c) = connection.as_mut() {
+                match self.exchange(c, controls).await {
+                    Ok(answer) => return Ok(answer),
+                    Err(_) => *connection = None,
+                }
+            }
+            let c = connection.insert(self.connect().await?);
+            self.exchange(c, controls).await
+        };
+        match tokio::time::timeout_at(deadline, attempt).await {
+            // A rejection isn't a dead connection
+            Ok(Ok(answer)) => accepted(answer),
+            Ok(Err(e)) => {
+                *connection = None;
+                Err(e)
+            }
+            Err(_) => {
+                // Whatever the connection is doing, it isn't answering
+                *connection = None;
+                Err(anyhow!("no answer within {TIMEOUT:?}"))
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let (ws, _) = connect_async(self.url.as_str()).await?;
+        let (writer, mut reader) = ws.split();
+        let (tx, answers) = mpsc::unbounded_channel();
+        let pushes = self.pushes.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    // The connection was dropped: hang up
+                    _ = tx.closed() => break,
+                    message = reader.next() => message,
+                };
+                // Pings are answered by the library
+                let Some(Ok(message)) = message else { break };
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                let Ok(frame) = serde_json::from_str::<Frame>(text.as_str()) else {
+                    continue;
+                };
+                match (frame.id, frame.volume, &pushes) {
+                    (Some(_), ..) => {
+                        let _ = tx.send(frame);
+                    }
+                    (None, Some(volume), Some((zone, pushes))) => {
+                        let _ = pushes.send((*zone, volume));
+                    }
+                    _ => {}
+                }
+            }
+        });
+        Ok(Connection { writer, answers })
+    }
+
+    /// Send `controls` and wait for the answer to it. Errors mean the
+    /// connection is no good.
+    async fn exchange(&self, c: &mut Connection, controls: &Controls) -> Result<Frame> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = serde_json::to_string(&Request {
+            id,
+            controls,
+            score: controls.score,
+        })?;
+        c.writer.send(Message::text(request)).await?;
+        while let Some(answer) = c.answers.recv().await {
+            // Late answers to sends that timed out are skipped
+            if answer.id == Some(id) {
+                return Ok(answer);
+            }
+        }
+        Err(anyhow!("connection closed"))
+    }
+}
+
+fn accepted(answer: Frame) -> Result<()> {
+    match answer.error {
+        Some(e) => Err(anyhow!("rejected: {e}")),
+        None => Ok(()),
+    }
+}
+
+impl VolumeSink for WsSink {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn set_volume(&self, volume: f32) -> SinkFuture<'_> {
+        Box::pin(async move {
+            let controls = Controls {
+                volume,
+                ..Controls::default()
+            };
+            self.send(&controls).await
+        })
+    }
+
+    fn set_controls<'a>(&'a self, controls: &'a Controls) -> SinkFuture<'a> {
+        Box::pin(self.send(controls))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Instant;
+    use tokio::net::TcpListener;
+
+    #[derive(Clone, Copy)]
+    enum Reply {
+        Ack,
+        Reject,
+        /// Push a volume of 0.9 before each ack
+        Push,
+        Silent,
+    }
+
+    /// Accepts WebSocket connections and answers each volume per `reply`,
+    /// until `drop_after` messages on one connection, when it hangs up.
+    struct MockWsServer {
+        url: String,
+        received: Arc<StdMutex<Vec<serde_json::Value>>>,
+        connections: Arc<StdMutex<usize>>,
+    }
+
+    impl MockWsServer {
+        async fn start(drop_after: Option<usize>, reply: Reply) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}/volume", listener.local_addr().unwrap());
+            let received = Arc::new(StdMutex::new(Vec::new()));
+            let connections = Arc::new(StdMutex::new(0));
+            let (log, count) = (received.clone(), connections.clone());
+            tokio::spawn(async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    *count.lock().unwrap() += 1;
+                    let log = log.clone();
+                    tokio::spawn(async move {
+                        let mut seen = 0;
+                        while let Some(Ok(Message::Text(text))) = ws.next().await {
+                            let body: serde_json::Value =
+                                serde_json::from_str(text.as_str()).unwrap();
+                            let (id, volume) = (&body["id"], body["volume"].as_f64().unwrap());
+                            log.lock().unwrap().push(body.clone());
+                            let answer = match reply {
+                                Reply::Ack => serde_json::json!({ "id": id, "volume": volume }),
+                                Reply::Reject => serde_json::json!({ "id": id, "error": "muted" }),
+                                Reply::Push => {
+                                    let push = serde_json::json!({ "volume": 0.9 });
+                                    ws.send(Message::text(push.to_string())).await.unwrap();
+                                    serde_json::json!({ "id": id, "volume": volume })
+                                }
+                                Reply::Silent => continue,
+                            };
+                            ws.send(Message::text(answer.to_string())).await.unwrap();
+                            seen += 1;
+                            if Some(seen) == drop_after {
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+            Self {
+                url,
+                received,
+                connections,
+            }
+        }
+
+        fn received(&self) -> Vec<f32> {
+            let received = self.received.lock().unwrap();
+            received
+                .iter()
+                .map(|body| body["volume"].as_f64().unwrap() as f32)
+                .collect()
+        }
+
+        fn connections(&self) -> usize {
+            *self.connections.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_over_one_connection() {
+        let server = MockWsServer::start(None, Reply::Ack).await;
+        let sink = WsSink::new(server.url.clone());
+        for v in [0.2, 0.4, 0.6] {
+            sink.set_volume(v).await.unwrap();
+        }
+        assert_eq!(server.received(), vec![0.2, 0.4, 0.6]);
+        assert_eq!(server.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_the_server_hangs_up() {
+        let server = MockWsServer::start(Some(1), Reply::Ack).await;
+        let sink = WsSink::new(server.url.clone());
+        sink.set_volume(0.3).await.unwrap();
+        // The old connection is gone; this send goes out on a new one
+        sink.set_volume(0.5).await.unwrap();
+        assert_eq!(server.received(), vec![0.3, 0.5]);
+        assert_eq!(server.connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_rejection_and_unreachable_servers() {
+        let server = MockWsServer::start(None, Reply::Reject).await;
+        let sink = WsSink::new(server.url.clone());
+        assert!(sink.set_volume(0.3).await.is_err());
+        // A rejection isn't a dead connection
+        assert!(sink.set_volume(0.4).await.is_err());
+        assert_eq!(server.connections(), 1);
+
+        let closed = ws_url(&crate::testutil::MockServer::closed_url().await);
+        assert!(WsSink::new(closed).set_volume(0.3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pushes_are_read_back_without_desyncing_answers() {
+        let server = MockWsServer::start(None, Reply::Push).await;
+        let (tx, mut pushes) = mpsc::unbounded_channel();
+        let sink = WsSink::new(server.url.clone()).with_pushes(3, tx);
+        for v in [0.2, 0.4] {
+            sink.set_volume(v).await.unwrap();
+        }
+        assert_eq!(server.received(), vec![0.2, 0.4]);
+        assert_eq!(pushes.recv().await, Some((3, 0.9)));
+        assert_eq!(pushes.recv().await, Some((3, 0.9)));
+    }
+
+    #[tokio::test]
+    async fn the_score_goes_out_with_the_volume() {
+        let server = MockWsServer::start(None, Reply::Ack).await;
+        let sink = WsSink::new(server.url.clone());
+        let controls = Controls {
+            volume: 0.5,
+            score: Some(70),
+            ..Controls::default()
+        };
+        sink.set_controls(&controls).await.unwrap();
+        let body = server.received.lock().unwrap()[0].clone();
+        assert_eq!(body["score"], 70);
+        assert_eq!(body["volume"], 0.5);
+    }
+
+    #[tokio::test]
+    async fn a_silent_server_fails_the_send_within_the_timeout() {
+        let server = MockWsServer::start(None, Reply::Silent).await;
+        let sink = WsSink::new(server.url.clone());
+        let start = Instant::now();
+        assert!(sink.set_volume(0.3).await.is_err());
+        assert!(start.elapsed() < TIMEOUT + Duration::from_millis(500));
+        // The next send starts over on a new connection
+        assert!(sink.set_volume(0.4).await.is_err());
+        assert_eq!(server.connections(), 2);
+    }
+
+    #[test]
+    fn http_urls_become_ws() {
+        assert_eq!(
+            ws_url("http://192.168.1.10:8080/volume"),
+            "ws://192.168.1.10:8080/volume"
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use std::time::Instant;
+
+use crate::adaptive::Thresholds;
+use crate::dsp::{
+    Compressor, CompressorConfig, DisplaySmoother, ProcessResult, Reading, Recalibration,
+    Saturation,
+};
+use crate::learn::{ContentType, LearnedTargets};
+use crate::loudness::Loudness;
+use crate::output::{Cooldown, SendGate, SettleTracker};
+use crate::params::ParamSet;
+use crate::sender::{SendOutcome, Sender};
+use crate::sink::Controls;
+use crate::status::{UnitLevels, ZoneStatus};
+use crate::units::{LoudnessUnit, ScoreScale, Units, DEFAULT_FULL_SCALE_SPL};
+
+/// One independently-levelled room: its compressor and the endpoints it drives.
+pub struct Zone {
+    pub name: String,
+    compressor: Compressor,
+    gate: SendGate,
+    cooldown: Cooldown,
+    settle: SettleTracker,
+    sender: Sender,
+    in_flight: Option<Controls>,
+    /// Config file `params`, sent along with the volume
+    params: Option<ParamSet>,
+    display: DisplaySmoother,
+    score: Option<ScoreScale>,
+    /// For the status `levels`
+    units: Units,
+    recalibration: Option<Recalibration>,
+    envelope_dbfs: Option<f32>,
+    display_dbfs: Option<f32>,
+    loudness: Option<Loudness>,
+    thresholds: Option<Thresholds>,
+    volume: f32,
+    healthy: Option<bool>,
+    saturated: Option<Saturation>,
+    scene: Option<usize>,
+}
+
+impl Zone {
+    pub fn new(
+        name: String,
+        compressor: Compressor,
+        initial_volume: f32,
+        gate: SendGate,
+        cooldown: Cooldown,
+        settle: SettleTracker,
+        sender: Sender,
+    ) -> Self {
+        Self {
+            name,
+            compressor,
+            gate,
+            cooldown,
+            settle,
+            sender,
+            in_flight: None,
+            params: None,
+            display: DisplaySmoother::new(0.0, 1.0),
+            score: None,
+            units: Units::new(LoudnessUnit::Dbfs, DEFAULT_FULL_SCALE_SPL),
+            recalibration: None,
+            envelope_dbfs: None,
+            display_dbfs: None,
+            loudness: None,
+            thresholds: None,
+            volume: initial_volume,
+            healthy: None,
+            saturated: None,
+            scene: None,
+        }
+    }
+
+    /// Level audio captured for this zone, from `readings` of `samples` by
+    /// the analyzer its capture shares with other zones.
+    pub fn apply(&mut self, samples: &[f32], readings: &[Reading]) -> Option<ProcessResult> {
+        if let Some(params) = &mut self.params {
+            params.push(samples);
+        }
+        let result = self.compressor.apply(readings)?;
+        if let Some(params) = &mut self.params {
+            params.update(result.envelope_dbfs, result.silent);
+        }
+        self.envelope_dbfs = Some(result.envelope_dbfs);
+        self.display_dbfs = Some(self.display.update(result.envelope_dbfs));
+        self.loudness = Some(result.loudness);
+        self.thresholds = Some(result.thresholds);
+        self.volume = result.volume;
+        self.saturated = result.saturated;
+        self.scene = result.scene;
+        Some(result)
+    }
+
+    /// Hand `volume` to the sender if it would change anything and the
+    /// cooldown allows. Returns the value submitted.
+    pub fn dispatch(&mut self, volume: f32, now: Instant) -> Option<f32> {
+        let controls = self
+            .pending(volume)
+            .filter(|c| self.in_flight.as_ref() != Some(c) && self.cooldown.ready(now))?;
+        Some(self.submit(controls))
+    }
+
+    /// Like `dispatch`, but ignores the cooldown.
+    pub fn force(&mut self, volume: f32) -> Option<f32> {
+        let controls = self.pending(volume)?;
+        Some(self.submit(controls))
+    }
+
+    /// What to send for `volume`: a parameter that moved is reason enough,
+    /// going out with the volume as it stands.
+    fn pending(&self, volume: f32) -> Option<Controls> {
+        let params_changed = self.params.as_ref().is_some_and(ParamSet::changed);
+        let volume = self.gate.pending(volume).or_else(|| {
+            params_changed.then(|| {
+                self.gate
+                    .last_sent()
+                    .unwrap_or_else(|| self.gate.effective(volume))
+            })
+        })?;
+        Some(Controls {
+            volume,
+            params: self
+                .params
+                .as_ref()
+                .map(ParamSet::values)
+                .unwrap_or_default(),
+            score: self.score(),
+        })
+    }
+
+    fn submit(&mut self, controls: Controls) -> f32 {
+        let volume = controls.volume;
+        self.sender.submit(controls.clone());
+        self.in_flight = Some(controls);
+        volume
+    }
+
+    /// Feed back a send the sender finished.
+    pub fn complete(&mut self, outcome: &SendOutcome, now: Instant) {
+        let ok = outcome.failures.is_empty();
+        if ok {
+            self.gate.mark_sent(outcome.volume);
+            if let Some(params) = &mut self.params {
+                params.mark_sent(&outcome.params);
+            }
+            self.settle.record_send(now);
+        }
+        let finished = |c: &Controls| c.volume == outcome.volume && c.params == outcome.params;
+        if self.in_flight.as_ref().is_some_and(finished) {
+            self.in_flight = None;
+        }
+        self.cooldown.record(now, ok);
+        self.cooldown.record_latency(outcome.latency);
+        self.healthy = Some(ok);
+    }
+
+    /// A heartbeat failed: the controller counts as unreachable until the
+    /// next successful send.
+    pub fn mark_unhealthy(&mut self) {
+        self.healthy = Some(false);
+    }
+
+    /// Hold the volume where it is while sends are held. On release the
+    /// compressor continues from the value last sent or forced, which is
+    /// what the sink has.
+    pub fn hold(&mut self, held: bool) {
+        if self.compressor.held() == held {
+            return;
+        }
+        self.compressor.hold(held);
+        let sink_has = self
+            .in_flight
+            .as_ref()
+            .map(|c| c.volume)
+            .or(self.gate.last_sent());
+        if let Some(volume) = sink_has.filter(|_| !held) {
+            self.compressor.resync(volume);
+            self.volume = volume;
+        }
+    }
+
+    /// The endpoint reports its volume changed at the device: carry on from
+    /// there instead of from what was last sent. Returns whether it differs.
+    pub fn adopt(&mut self, volume: f32) -> bool {
+        let changed = self.gate.last_sent() != Some(volume);
+        self.gate.mark_sent(volume);
+        self.sender.forget_delivered();
+        self.compressor.resync(volume);
+        self.volume = volume;
+        changed
+    }
+
+    /// New settings, e.g. another input device's profile: analysis starts
+    /// over from the current volume.
+    pub fn reconfigure(&mut self, config: CompressorConfig) {
+        let held = self.compressor.held();
+        self.compressor = Compressor::new(config, self.volume);
+        self.compressor.hold(held);
+    }
+
+    /// Coming out of standby: analysis restarts on the returning audio.
+    pub fn warm_up(&mut self) {
+        self.compressor.warm_up();
+    }
+
+    /// Re-derive target and dead zone from what this zone has heard lately.
+    pub fn recalibrate(&mut self) -> Option<Recalibration> {
+        let recalibration = self.compressor.recalibrate()?;
+        self.recalibration = Some(recalibration);
+        Some(recalibration)
+    }
+
+    /// True once when the volume has gone --settle-time without a send.
+    pub fn check_settled(&mut self, now: Instant) -> bool {
+        self.settle.update(now)
+    }
+
+    /// Smooth displayed levels (see --display-smoothing).
+    pub fn with_display(mut self, display: DisplaySmoother) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// Report the control envelope as a 0-100 score too.
+    pub fn with_score(mut self, scale: ScoreScale) -> Self {
+        self.score = Some(scale);
+        self
+    }
+
+    /// Show status levels in `units` too.
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// The envelope of the control metric (see --control-timescale) on
+    /// the score scale.
+    pub fn score(&self) -> Option<u8> {
+        Some(self.score?.score(self.envelope_dbfs?))
+    }
+
+    /// Envelope for readouts, after --display-smoothing.
+    pub fn display_dbfs(&self) -> Option<f32> {
+        self.display_dbfs
+    }
+
+    /// The bound this zone is pinned at, if any.
+    pub fn saturated(&self) -> Option<Saturation> {
+        self.saturated
+    }
+
+    /// Index of the scene playing, if any.
+    pub fn scene(&self) -> Option<usize> {
+        self.scene
+    }
+
+    /// What's playing, with learned targets on.
+    pub fn content_type(&self) -> Option<ContentType> {
+        self.compressor.content_type()
+    }
+
+    pub fn set_learned(&mut self, learned: LearnedTargets) {
+        self.compressor.set_learned(learned);
+    }
+
+    pub fn scene_name(&self, index: usize) -> &str {
+        self.compressor.scene_name(index)
+    }
+
+    pub fn with_params(mut self, params: ParamSet) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    pub fn gate(&self) -> &SendGate {
+        &self.gate
+    }
+
+    pub fn status(&self) -> ZoneStatus {
+        ZoneStatus {
+            name: self.name.clone(),
+            envelope_dbfs: self.envelope_dbfs,
+            loudness: self.loudness,
+            thresholds: self.thresholds,
+            volume: self.volume,
+            last_sent: self.gate.last_sent(),
+            endpoints: self.sender.endpoints().to_vec(),
+            healthy: self.healthy,
+            settled: self.settle.settled(),
+            latency_ms: self.cooldown.latency().map(|l| l.as_secs_f32() * 1000.0),
+            send_interval_ms: self.cooldown.interval().as_secs_f32() * 1000.0,
+            saturated: self.saturated,
+            scene: self.scene.map(|i| self.scene_name(i).to_string()),
+            score: self.score(),
+            content: self.content_type(),
+            params: self
+                .params
+                .as_ref()
+                .map(ParamSet::status)
+                .unwrap_or_default(),
+            recalibration: self.recalibration,
+            levels: self.levels(),
+        }
+    }
+
+    fn levels(&self) -> UnitLevels {
+        let value = |dbfs: Option<f32>| dbfs.map(|d| self.units.value(d));
+        UnitLevels {
+            envelope: value(self.envelope_dbfs),
+            quiet: value(self.thresholds.map(|t| t.quiet_dbfs)),
+            loud: value(self.thresholds.map(|t| t.loud_dbfs)),
+            recalibrated_target: value(self.recalibration.map(|r| r.target_dbfs)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::dsp::tests::test_config;
+    use crate::dsp::Analyzer;
+    use crate::output::{CooldownOn, DEFAULT_SEND_DEADBAND};
+    use crate::sink::tests::RecordingSink;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// A zone wired to a sink, with its outcomes at hand and an analyzer
+    /// standing in for its capture's.
+    pub(crate) struct TestZone {
+        pub zone: Zone,
+        pub analyzer: Analyzer,
+        outcomes: mpsc::UnboundedReceiver<SendOutcome>,
+    }
+
+    impl TestZone {
+        pub(crate) fn new(name: &str, sink: &RecordingSink, display_sec: f32) -> Self {
+            let (tx, outcomes) = mpsc::unbounded_channel();
+            let sender = Sender::spawn(0, vec![Box::new(sink.clone())], Duration::ZERO, tx);
+            let zone = Zone::new(
+                name.to_string(),
+                Compressor::new(test_config(), 0.5),
+                0.5,
+                SendGate::new(None, DEFAULT_SEND_DEADBAND),
+                Cooldown::new(Duration::ZERO, CooldownOn::Success),
+                SettleTracker::new(Duration::from_secs(5), Instant::now()),
+                sender,
+            )
+            .with_display(DisplaySmoother::new(display_sec, 20.0));
+            Self {
+                zone,
+                analyzer: Analyzer::new(&test_config()),
+                outcomes,
+            }
+        }
+
+        /// Analyze and level `samples`.
+        pub(crate) fn process(&mut self, samples: &[f32]) -> Option<ProcessResult> {
+            let readings = self.analyzer.push(samples);
+            self.zone.apply(samples, &readings)
+        }
+
+        /// Dispatch and wait for the send to finish.
+        pub(crate) async fn send(&mut self, volume: f32) -> bool {
+            if self.zone.dispatch(volume, Instant::now()).is_none() {
+                return false;
+            }
+            let outcome = self.outcomes.recv().await.unwrap();
+            self.zone.complete(&outcome, Instant::now());
+            true
+        }
+
+        /// Wait for whatever is in flight to finish. Returns its outcome.
+        pub(crate) async fn finish(&mut self) -> Option<SendOutcome> {
+            self.zone.in_flight.as_ref()?;
+            let outcome = self.outcomes.recv().await.unwrap();
+            self.zone.complete(&outcome, Instant::now());
+            Some(outcome)
+        }
+    }
+
+    #[tokio::test]
+    async fn reading_only_reaches_its_own_zone() {
+        let (living_sink, bedroom_sink) = (RecordingSink::default(), RecordingSink::default());
+        let mut living = TestZone::new("living", &living_sink, 0.0);
+        let bedroom = TestZone::new("bedroom", &bedroom_sink, 0.0);
+
+        // Loud audio captured in the living room only
+        for _ in 0..40 {
+            if let Some(r) = living.process(&[0.5; 400]) {
+                living.send(r.volume).await;
+            }
+        }
+
+        assert!(!living_sink.sent().is_empty());
+        assert!(bedroom_sink.sent().is_empty());
+        assert!(living.zone.status().last_sent.unwrap() < 0.5);
+        assert_eq!(bedroom.zone.status().last_sent, None);
+        assert_eq!(bedroom.zone.status().name, "bedroom");
+    }
+
+    #[tokio::test]
+    async fn saturated_zone_sends_the_bound_once() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+
+        // About -50 dBFS: wants far more than vol_max for 10s
+        for _ in 0..400 {
+            if let Some(r) = z.process(&[0.003; 400]) {
+                z.send(r.volume).await;
+            }
+        }
+        assert_eq!(z.zone.saturated(), Some(Saturation::Max));
+        assert_eq!(z.zone.status().saturated, Some(Saturation::Max));
+        let sent = sink.sent();
+        assert_eq!(sent.last(), Some(&0.95));
+        assert_eq!(sent.iter().filter(|&&v| v == 0.95).count(), 1);
+
+        // Briefly loud: off the bound, sending again
+        for _ in 0..10 {
+            if let Some(r) = z.process(&[0.5; 400]) {
+                z.send(r.volume).await;
+            }
+        }
+        assert_eq!(z.zone.saturated(), None);
+        assert!(sink.sent().len() > sent.len());
+    }
+
+    #[tokio::test]
+    async fn unchanged_volume_is_not_dispatched() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        assert!(z.send(0.4).await);
+        assert!(!z.send(0.4).await);
+        assert_eq!(sink.sent(), vec![0.4]);
+    }
+
+    #[tokio::test]
+    async fn adopted_volume_is_the_new_starting_point() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        assert!(z.send(0.4).await);
+        assert!(!z.zone.adopt(0.4));
+
+        assert!(z.zone.adopt(0.7));
+        assert_eq!(z.zone.status().last_sent, Some(0.7));
+        // Already there: nothing to send
+        assert!(!z.send(0.7).await);
+        // The next move is one slew step from the pushed volume, not from 0.4
+        let r = (0..10).find_map(|_| z.process(&[0.5; 400])).unwrap();
+        let step_db = 20.0 * (r.volume / 0.7).log10();
+        assert!(step_db.abs() <= 1.6, "{}", r.volume);
+    }
+
+    #[tokio::test]
+    async fn score_follows_the_control_envelope() {
+        let sink = RecordingSink::default();
+        // Slow display smoothing: the readout lags well behind the envelope
+        let mut z = TestZone::new("living", &sink, 10.0);
+        let scale = ScoreScale::new(-60.0, 0.0).unwrap();
+        z.zone = z.zone.with_score(scale);
+        for _ in 0..40 {
+            z.process(&[0.1; 400]);
+        }
+        let envelope = z.zone.status().envelope_dbfs.unwrap();
+        assert!((z.zone.display_dbfs().unwrap() - envelope).abs() > 1.0);
+        assert_eq!(z.zone.score(), Some(scale.score(envelope)));
+
+        // And goes out with the volume
+        z.zone.dispatch(0.4, Instant::now()).unwrap();
+        assert_eq!(z.zone.in_flight.as_ref().unwrap().score, z.zone.score());
+        z.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn status_levels_are_in_the_zone_units() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        z.zone = z.zone.with_units(Units::new(LoudnessUnit::Phon, 90.0));
+        for _ in 0..10 {
+            z.process(&[0.1; 400]);
+        }
+        let status = z.zone.status();
+        let (envelope, thresholds) = (status.envelope_dbfs.unwrap(), status.thresholds.unwrap());
+        assert_eq!(status.levels.envelope, Some(envelope + 90.0));
+        assert_eq!(status.levels.quiet, Some(thresholds.quiet_dbfs + 90.0));
+        assert_eq!(status.levels.loud, Some(thresholds.loud_dbfs + 90.0));
+        assert_eq!(status.levels.recalibrated_target, None);
+    }
+
+    #[tokio::test]
+    async fn in_flight_value_is_not_resubmitted() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        assert_eq!(z.zone.dispatch(0.4, Instant::now()), Some(0.4));
+        assert_eq!(z.zone.dispatch(0.4, Instant::now()), None);
+    }
+
+    #[tokio::test]
+    async fn moved_params_go_out_with_the_volume() {
+        use crate::params::{Feature, ParamConfig};
+
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        let config = ParamConfig {
+            name: "bass".into(),
+            feature: Feature::Level,
+            target: 0.0,
+            dead_zone: 1.0,
+            rate: 1.0,
+            range: [0.0, 1.0],
+            initial: None,
+        };
+        z.zone = z
+            .zone
+            .with_params(ParamSet::new(&[config], 8000, 20.0).unwrap());
+
+        z.zone.dispatch(0.4, Instant::now()).unwrap();
+        let outcome = z.outcomes.recv().await.unwrap();
+        assert_eq!(outcome.params["bass"], 0.5);
+        z.zone.complete(&outcome, Instant::now());
+        assert_eq!(z.zone.dispatch(0.4, Instant::now()), None);
+
+        // Quieter than the parameter's target: it rises on its own, and
+        // goes out with the volume unchanged
+        while z.process(&[0.05; 400]).is_none() {}
+        assert_eq!(z.zone.dispatch(0.4, Instant::now()), Some(0.4));
+        let outcome = z.outcomes.recv().await.unwrap();
+        assert_eq!(outcome.volume, 0.4);
+        assert_eq!(outcome.params["bass"], 0.55);
+        assert_eq!(z.zone.status().params[0].feature, Feature::Level);
+    }
+
+    #[tokio::test]
+    async fn display_smoothing_leaves_control_alone() {
+        let (raw_sink, smooth_sink) = (RecordingSink::default(), RecordingSink::default());
+        let mut raw = TestZone::new("raw", &raw_sink, 0.0);
+        let mut smooth = TestZone::new("smooth", &smooth_sink, 2.0);
+
+        // Quiet then a loud burst
+        let mut displays_differ = false;
+        for i in 0..60 {
+            let amp = if i < 30 { 0.01 } else { 0.5 };
+            let a = raw.process(&[amp; 400]);
+            let b = smooth.process(&[amp; 400]);
+            if let (Some(a), Some(b)) = (a, b) {
+                assert_eq!(a.volume, b.volume);
+                assert_eq!(a.target_dbfs, b.target_dbfs);
+                raw.send(a.volume).await;
+                smooth.send(b.volume).await;
+                displays_differ |= raw.zone.display_dbfs() != smooth.zone.display_dbfs();
+            }
+        }
+        assert_eq!(raw_sink.sent(), smooth_sink.sent());
+        assert!(displays_differ);
+        // Smoothed readout lags the burst
+        assert!(smooth.zone.display_dbfs().unwrap() < raw.zone.display_dbfs().unwrap());
+    }
+
+    #[tokio::test]
+    async fn settles_without_sends_and_resets_on_send() {
+        let sink = RecordingSink::default();
+        let mut z = TestZone::new("living", &sink, 0.0);
+        let start = Instant::now();
+        let later = |secs: u64| start + Duration::from_secs(secs);
+
+        assert!(!z.zone.check_settled(later(1)));
+        assert!(z.zone.check_settled(later(6)));
+        assert!(z.zone.status().settled);
+
+        z.zone.dispatch(0.4, later(7)).unwrap();
+        let outcome = z.outcomes.recv().await.unwrap();
+        z.zone.complete(&outcome, later(7));
+        assert!(!z.zone.status().settled);
+        assert!(!z.zone.check_settled(later(10)));
+        assert!(z.zone.check_settled(later(12)));
+    }
+}
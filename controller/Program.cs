@@ -136,6 +136,12 @@ class Program
                 case "/volume" when req.HttpMethod == "GET":
                     HandleGetVolume(resp);
                     break;
+                case "/volume/channels" when req.HttpMethod == "POST":
+                    await HandleSetChannelVolumes(req, resp);
+                    break;
+                case "/volume/channels" when req.HttpMethod == "GET":
+                    HandleGetChannelVolumes(resp);
+                    break;
                 case "/health":
                     HandleHealth(resp);
                     break;
@@ -211,6 +217,83 @@ class Program
         SendJson(resp, 200, new { volume = vol });
     }
 
+    private static async Task HandleSetChannelVolumes(HttpListenerRequest req, HttpListenerResponse resp)
+    {
+        if (req.InputStream == null || !req.HasEntityBody)
+        {
+            SendJson(resp, 400, new { error = "Missing request body" });
+            return;
+        }
+
+        using var reader = new StreamReader(req.InputStream, Encoding.UTF8);
+        string body = await reader.ReadToEndAsync();
+
+        ChannelVolumeRequest? chReq;
+        try
+        {
+            chReq = JsonSerializer.Deserialize<ChannelVolumeRequest>(body, JsonOpts);
+        }
+        catch
+        {
+            SendJson(resp, 400, new { error = "Invalid JSON" });
+            return;
+        }
+
+        if (chReq?.Channels == null || chReq.Channels.Count == 0)
+        {
+            SendJson(resp, 400, new { error = "Invalid request" });
+            return;
+        }
+
+        if (_device == null)
+        {
+            SendJson(resp, 500, new { error = "Audio device not available" });
+            return;
+        }
+
+        var channels = _device.AudioEndpointVolume.Channels;
+        foreach (var ch in chReq.Channels)
+        {
+            if (ch.Channel < 0 || ch.Channel >= channels.Count)
+            {
+                SendJson(resp, 400, new { error = $"Channel {ch.Channel} out of range (device has {channels.Count})" });
+                return;
+            }
+        }
+
+        try
+        {
+            foreach (var ch in chReq.Channels)
+                channels[ch.Channel].VolumeLevelScalar = Math.Clamp(ch.Volume, 0.0f, 1.0f);
+
+            Console.WriteLine($"  Channels -> {string.Join(", ", chReq.Channels.Select(c => $"{c.Channel}:{c.Volume:F3}"))}");
+            SendJson(resp, 200, new { status = "ok", channels = ReadChannelVolumes() });
+        }
+        catch (Exception ex)
+        {
+            SendJson(resp, 500, new { error = $"Failed to set channel volume: {ex.Message}" });
+        }
+    }
+
+    private static void HandleGetChannelVolumes(HttpListenerResponse resp)
+    {
+        if (_device == null)
+        {
+            SendJson(resp, 500, new { error = "Audio device not available" });
+            return;
+        }
+
+        SendJson(resp, 200, new { channels = ReadChannelVolumes() });
+    }
+
+    private static float[] ReadChannelVolumes()
+    {
+        var channels = _device!.AudioEndpointVolume.Channels;
+        return Enumerable.Range(0, channels.Count)
+            .Select(i => channels[i].VolumeLevelScalar)
+            .ToArray();
+    }
+
     private static void HandleHealth(HttpListenerResponse resp)
     {
         SendJson(resp, 200, new
@@ -231,3 +314,7 @@ class Program
 }
 
 record VolumeRequest(float Volume);
+
+record ChannelVolume(int Channel, float Volume);
+
+record ChannelVolumeRequest(List<ChannelVolume> Channels);